@@ -3,12 +3,17 @@
 //! Provides easy methods to navigate through the epub parts and to get
 //! the content as string.
 
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
 use std::io::{Read, Seek};
 
+use crate::encoding;
+use crate::limits::{LimitError, Limits, RATIO_CHECK_MIN_SIZE};
+use crate::ocf;
+
 /// Epub archive struct. Here it's stored the file path and the list of
 /// files in the zip archive.
 #[derive(Clone, Debug)]
@@ -16,6 +21,7 @@ pub struct EpubArchive<R: Read + Seek> {
     zip: zip::ZipArchive<R>,
     pub path: PathBuf,
     pub files: Vec<String>,
+    limits: Limits,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -28,6 +34,15 @@ pub enum ArchiveError {
     Utf8(#[from] std::str::Utf8Error),
     #[error("Invalid UTF-8 Path")]
     PathUtf8,
+    #[error("Entry Not Found: {0}")]
+    EntryNotFound(String),
+    #[error("Can't decompress {name}: {source}")]
+    Decompression {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("Limit Exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
 }
 impl From<std::string::FromUtf8Error> for ArchiveError {
     fn from(e: std::string::FromUtf8Error) -> Self {
@@ -58,7 +73,39 @@ impl<R: Read + Seek> EpubArchive<R> {
     ///
     /// Returns an error if the zip is broken.
     pub fn from_reader(reader: R) -> Result<Self, ArchiveError> {
-        let zip = zip::ZipArchive::new(reader)?;
+        Self::from_reader_with_limits(reader, Limits::default())
+    }
+
+    /// Opens the epub contained in `reader`, checking the sizes declared in
+    /// the zip archive against the `limits`. The same limits are enforced
+    /// reading each entry later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the zip is broken and
+    /// [`ArchiveError::LimitExceeded`] if it exceeds the `limits`.
+    pub fn from_reader_with_limits(reader: R, limits: Limits) -> Result<Self, ArchiveError> {
+        let mut zip = zip::ZipArchive::new(reader)?;
+
+        if zip.len() > limits.max_entries {
+            return Err(LimitError::Entries {
+                limit: limits.max_entries,
+            }
+            .into());
+        }
+
+        let mut total: u64 = 0;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i)?;
+            check_entry_size(entry.name(), entry.size(), entry.compressed_size(), &limits)?;
+            total = total.saturating_add(entry.size());
+            if total > limits.max_total_size {
+                return Err(LimitError::TotalSize {
+                    limit: limits.max_total_size,
+                }
+                .into());
+            }
+        }
 
         let files: Vec<String> = zip.file_names().map(String::from).collect();
 
@@ -66,6 +113,7 @@ impl<R: Read + Seek> EpubArchive<R> {
             zip,
             path: PathBuf::new(),
             files,
+            limits,
         })
     }
 
@@ -73,38 +121,70 @@ impl<R: Read + Seek> EpubArchive<R> {
     ///
     /// # Errors
     ///
-    /// Returns an error if the name doesn't exists in the zip archive.
+    /// Returns [`ArchiveError::EntryNotFound`] if the name doesn't exists in
+    /// the zip archive and [`ArchiveError::Decompression`] if the entry is
+    /// corrupted.
     pub fn get_entry<P: AsRef<Path>>(&mut self, name: P) -> Result<Vec<u8>, ArchiveError> {
-        let mut entry: Vec<u8> = vec![];
-
         let name = name.as_ref().to_str().ok_or(ArchiveError::PathUtf8)?;
 
         match self.zip.by_name(name) {
-            Ok(mut zipfile) => {
-                zipfile.read_to_end(&mut entry)?;
-                return Ok(entry);
-            }
+            Ok(zipfile) => return read_entry(name, zipfile, &self.limits),
             Err(zip::result::ZipError::FileNotFound) => {}
             Err(e) => {
                 return Err(e.into());
             }
-        };
+        }
 
         // try percent encoding
-        let name = percent_encoding::percent_decode(name.as_bytes()).decode_utf8()?;
-        let mut zipfile = self.zip.by_name(&name)?;
-        zipfile.read_to_end(&mut entry)?;
-        Ok(entry)
+        let Ok(decoded) = percent_encoding::percent_decode(name.as_bytes()).decode_utf8() else {
+            return Err(ArchiveError::EntryNotFound(name.to_string()));
+        };
+        match self.zip.by_name(&decoded) {
+            Ok(zipfile) => read_entry(name, zipfile, &self.limits),
+            Err(zip::result::ZipError::FileNotFound) => {
+                Err(ArchiveError::EntryNotFound(name.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
-    /// Returns the content of the file by the `name` as `String`.
+    /// Returns the content of the file by the `name` as `String`, decoded
+    /// from the encoding of its byte order mark, XML declaration, `@charset`
+    /// rule or `<meta>` charset, or from UTF-8.
     ///
     /// # Errors
     ///
-    /// Returns an error if the name doesn't exists in the zip archive.
+    /// Returns an error if the name doesn't exists in the zip archive, or
+    /// if the content is decoded from UTF-8 and isn't valid.
     pub fn get_entry_as_str<P: AsRef<Path>>(&mut self, name: P) -> Result<String, ArchiveError> {
         let content = self.get_entry(name)?;
-        String::from_utf8(content).map_err(ArchiveError::from)
+        encoding::decode_string(content).map_err(ArchiveError::from)
+    }
+
+    /// Returns `true` if the file `name` exists in the zip archive, looking
+    /// for the percent decoded name too, like [`Self::get_entry`] does.
+    pub fn contains<P: AsRef<Path>>(&self, name: P) -> bool {
+        let Some(name) = name.as_ref().to_str() else {
+            return false;
+        };
+        if self.files.iter().any(|f| f == name) {
+            return true;
+        }
+        percent_encoding::percent_decode(name.as_bytes())
+            .decode_utf8()
+            .is_ok_and(|name| self.files.iter().any(|f| *f == name))
+    }
+
+    /// Returns `true` if the file `name` is stored in the zip archive
+    /// without compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name doesn't exists in the zip archive.
+    pub fn is_stored<P: AsRef<Path>>(&mut self, name: P) -> Result<bool, ArchiveError> {
+        let name = name.as_ref().to_str().ok_or(ArchiveError::PathUtf8)?;
+        let zipfile = self.zip.by_name(name)?;
+        Ok(zipfile.compression() == zip::CompressionMethod::Stored)
     }
 
     /// Returns the content of container file "META-INF/container.xml".
@@ -113,7 +193,251 @@ impl<R: Read + Seek> EpubArchive<R> {
     ///
     /// Returns an error if the epub doesn't have the container file.
     pub fn get_container_file(&mut self) -> Result<Vec<u8>, ArchiveError> {
-        let content = self.get_entry("META-INF/container.xml")?;
+        let content = self.get_entry(ocf::CONTAINER_FILE)?;
         Ok(content)
     }
 }
+
+fn check_entry_size(
+    name: &str,
+    size: u64,
+    compressed_size: u64,
+    limits: &Limits,
+) -> Result<(), LimitError> {
+    if size > limits.max_entry_size {
+        return Err(LimitError::EntrySize {
+            name: name.to_string(),
+            limit: limits.max_entry_size,
+        });
+    }
+    if size >= RATIO_CHECK_MIN_SIZE && size / compressed_size.max(1) > limits.max_compression_ratio
+    {
+        return Err(LimitError::CompressionRatio {
+            name: name.to_string(),
+            limit: limits.max_compression_ratio,
+        });
+    }
+    Ok(())
+}
+
+/// Reads the entry content, never reading more than its declared size, so a
+/// zip with forged sizes can't bypass the limits
+fn read_entry<R: Read>(
+    name: &str,
+    zipfile: zip::read::ZipFile<'_, R>,
+    limits: &Limits,
+) -> Result<Vec<u8>, ArchiveError> {
+    let declared = zipfile.size();
+    check_entry_size(name, declared, zipfile.compressed_size(), limits)?;
+
+    let mut entry: Vec<u8> = vec![];
+    zipfile
+        .take(declared.saturating_add(1))
+        .read_to_end(&mut entry)
+        .map_err(|source| ArchiveError::Decompression {
+            name: name.to_string(),
+            source,
+        })?;
+    if entry.len() as u64 > declared {
+        return Err(LimitError::SizeMismatch {
+            name: name.to_string(),
+            declared,
+        }
+        .into());
+    }
+    Ok(entry)
+}
+
+/// Inflates the raw deflate `data` of the entry `name`, as found in
+/// resources compressed before being encrypted
+#[cfg(feature = "lcp")]
+pub(crate) fn inflate(name: &str, data: &[u8], limits: &Limits) -> Result<Vec<u8>, ArchiveError> {
+    let mut content = vec![];
+    flate2::read::DeflateDecoder::new(data)
+        .take(limits.max_entry_size.saturating_add(1))
+        .read_to_end(&mut content)
+        .map_err(|source| ArchiveError::Decompression {
+            name: name.to_string(),
+            source,
+        })?;
+    if content.len() as u64 > limits.max_entry_size {
+        return Err(LimitError::EntrySize {
+            name: name.to_string(),
+            limit: limits.max_entry_size,
+        }
+        .into());
+    }
+    Ok(content)
+}
+
+/// A normalized path to an entry of the zip archive.
+///
+/// The hrefs found in the epub documents are percent-decoded, their `.` and
+/// `..` segments are resolved, clamped at the archive root, and absolute
+/// paths, drive letters and external URLs are rejected, so the resulting
+/// path never points outside the archive.
+///
+/// # Examples
+///
+/// ```
+/// use epub::archive::{ArchivePath, PathError};
+///
+/// let opf = ArchivePath::new("OEBPS/content.opf").unwrap();
+/// let chapter = opf.resolve("Text/../Text/chapter%201.xhtml").unwrap();
+/// assert_eq!("OEBPS/Text/chapter 1.xhtml", chapter.as_str());
+///
+/// let escaped = opf.resolve("../../../etc/passwd").unwrap();
+/// assert_eq!("etc/passwd", escaped.as_str());
+///
+/// assert!(matches!(opf.resolve("/etc/passwd"), Err(PathError::Absolute(_))));
+/// assert!(matches!(opf.resolve("C:\\book.xhtml"), Err(PathError::DriveLetter(_))));
+/// assert!(matches!(opf.resolve("http://example.com"), Err(PathError::External(_))));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArchivePath(String);
+
+/// The reasons a href can't be converted to an [`ArchivePath`]
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum PathError {
+    #[error("Absolute path not allowed: {0}")]
+    Absolute(String),
+    #[error("Drive letter not allowed: {0}")]
+    DriveLetter(String),
+    #[error("External URL not allowed: {0}")]
+    External(String),
+    #[error("Invalid percent encoding: {0}")]
+    Encoding(String),
+}
+
+impl ArchivePath {
+    /// The root of the archive
+    #[must_use]
+    pub const fn root() -> Self {
+        Self(String::new())
+    }
+
+    /// Builds the path to an entry from its `path` from the archive root,
+    /// as it's named in the archive. Unlike the hrefs of [`Self::join`],
+    /// the path isn't percent-decoded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path is absolute, has a drive letter or is an
+    /// external URL.
+    pub fn new(path: &str) -> Result<Self, PathError> {
+        Self::root().push(path, path)
+    }
+
+    /// Returns the path of `href` relative to this path, considering this
+    /// path a directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the href is absolute, has a drive letter, is an
+    /// external URL or isn't valid percent encoded UTF-8.
+    pub fn join(&self, href: &str) -> Result<Self, PathError> {
+        let decoded = percent_encoding::percent_decode(href.as_bytes())
+            .decode_utf8()
+            .map_err(|_| PathError::Encoding(href.to_string()))?;
+        self.push(&decoded, href)
+    }
+
+    /// Returns the path of `href` relative to this path, considering this
+    /// path a file, like the hrefs found inside a document.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the href is absolute, has a drive letter, is an
+    /// external URL or isn't valid percent encoded UTF-8.
+    pub fn resolve(&self, href: &str) -> Result<Self, PathError> {
+        self.parent().join(href)
+    }
+
+    /// Returns the directory containing this path, the root for the root
+    #[must_use]
+    pub fn parent(&self) -> Self {
+        self.0
+            .rfind('/')
+            .map_or_else(Self::root, |i| Self(self.0[..i].to_string()))
+    }
+
+    /// Returns `true` if this is the root of the archive
+    #[must_use]
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the path as it's named in the zip archive
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the path as a [`Path`]
+    #[must_use]
+    pub fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    /// Appends the decoded `path` of the `href` to this directory
+    fn push(&self, path: &str, href: &str) -> Result<Self, PathError> {
+        if has_drive_letter(path) {
+            return Err(PathError::DriveLetter(href.to_string()));
+        }
+        if has_scheme(path) {
+            return Err(PathError::External(href.to_string()));
+        }
+        if path.starts_with(['/', '\\']) {
+            return Err(PathError::Absolute(href.to_string()));
+        }
+
+        let mut segments: Vec<&str> = self.segments().collect();
+        for segment in path.split(['/', '\\']) {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                s => segments.push(s),
+            }
+        }
+        Ok(Self(segments.join("/")))
+    }
+
+    fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|s| !s.is_empty())
+    }
+}
+
+impl fmt::Display for ArchivePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<Path> for ArchivePath {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl From<ArchivePath> for PathBuf {
+    fn from(path: ArchivePath) -> Self {
+        Self::from(path.0)
+    }
+}
+
+fn has_drive_letter(href: &str) -> bool {
+    let bytes = href.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Returns `true` if `href` starts with a URL scheme like `http:`
+pub(crate) fn has_scheme(href: &str) -> bool {
+    let Some((scheme, _)) = href.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
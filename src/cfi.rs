@@ -0,0 +1,836 @@
+//! EPUB Canonical Fragment Identifiers, to refer to a precise location in
+//! the book.
+//!
+//! A CFI like `epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)` walks
+//! the package document to a spine item, then the content document to an
+//! element or a character of a text. Even steps are elements, the second
+//! child element is `/4`, and odd steps the text between them.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::cfi::{Cfi, DomPosition};
+//! use epub::doc::EpubDoc;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let cfi = doc.generate_text_cfi(1, 5).unwrap();
+//! let location = doc.resolve_cfi(&cfi).unwrap();
+//! assert_eq!(location.spine_index, 1);
+//! assert_eq!(location.text_offset, 5);
+//!
+//! // the first child element of the `<body>`, the second one of the root
+//! let position = DomPosition {
+//!     path: vec![1, 0],
+//!     offset: None,
+//! };
+//! let cfi = doc.generate_cfi(1, &position).unwrap();
+//! assert_eq!(cfi.to_string(), "epubcfi(/6/4!/4/2)");
+//!
+//! let parsed: Cfi = cfi.to_string().parse().unwrap();
+//! assert_eq!(parsed, cfi);
+//! ```
+//!
+//! Character offsets are counted in UTF-16 code units, like the offsets of
+//! the DOM. Temporal and spatial offsets aren't supported.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::doc::{DocError, EpubDoc, ParseMode};
+use crate::dom::{self, Element, Node};
+use crate::text::{Extract, TextOptions};
+
+/// Characters escaped with `^` in the assertions
+const SPECIAL: &[char] = &['^', '[', ']', '(', ')', ',', ';', '='];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CfiError {
+    #[error("Invalid CFI at character {0}")]
+    Invalid(usize),
+    #[error("Unsupported CFI offset '{0}'")]
+    Unsupported(char),
+    #[error("CFI not found in the book: {0}")]
+    NotFound(String),
+}
+
+/// A parsed CFI, a location or a range between two locations
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cfi {
+    /// The location, or the common parent of a range
+    pub path: CfiPath,
+    /// The start and end of a range, relative to the `path`
+    pub range: Option<(CfiPath, CfiPath)>,
+}
+
+/// Steps to a location, with the character offset in the last one
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CfiPath {
+    pub steps: Vec<Step>,
+    pub offset: Option<CharOffset>,
+}
+
+/// A step to a child: `/4[id]`, or `!/4[id]` to step into the document
+/// referenced by the previous one
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Step {
+    /// Even for the elements, odd for the text between them
+    pub index: usize,
+    /// The assertion between brackets, escaped, usually the child id
+    pub assertion: Option<String>,
+    /// `true` if preceded by `!`
+    pub indirect: bool,
+}
+
+/// A character offset, `:10` or `:10[text assertion]`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CharOffset {
+    /// UTF-16 offset in the text
+    pub offset: usize,
+    /// The assertion between brackets, escaped
+    pub assertion: Option<String>,
+}
+
+/// Where a CFI points to in the book
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// The spine item
+    pub spine_index: usize,
+    /// Byte offset in the text of the chapter, as returned by
+    /// [`EpubDoc::chapter_text`]
+    pub text_offset: usize,
+    /// The `id` of the nearest element at or before the location
+    pub element_id: Option<String>,
+}
+
+/// A position in the DOM of a content document to make a CFI from: an
+/// element, and optionally a character of its text
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DomPosition {
+    /// Indices of the child elements to walk from the root element, the
+    /// first child being `0`. Empty for the root element.
+    pub path: Vec<usize>,
+    /// Offset in the text of the element and its descendants, in UTF-16
+    /// code units like the DOM, or [`None`] for the element itself
+    pub offset: Option<usize>,
+}
+
+impl Step {
+    /// Returns a step to the child `index`, with an assertion of its `id`
+    #[must_use]
+    pub fn new(index: usize, id: Option<&str>) -> Self {
+        Self {
+            index,
+            assertion: id.filter(|id| !id.is_empty()).map(escape),
+            indirect: false,
+        }
+    }
+
+    /// Returns the id asserted, without the parameters and unescaped
+    #[must_use]
+    pub fn id(&self) -> Option<String> {
+        let assertion = self.assertion.as_deref()?;
+        let id = unescape(split_unescaped(assertion, ';').0);
+        (!id.is_empty()).then_some(id)
+    }
+}
+
+impl CfiPath {
+    /// Compares the locations, ignoring the assertions
+    fn cmp_location(&self, other: &Self) -> Ordering {
+        let steps = self.steps.iter().map(|s| s.index);
+        let other_steps = other.steps.iter().map(|s| s.index);
+        steps.cmp(other_steps).then_with(|| {
+            let offset = self.offset.as_ref().map(|o| o.offset);
+            offset.cmp(&other.offset.as_ref().map(|o| o.offset))
+        })
+    }
+
+    fn join(&self, local: &Self) -> Self {
+        let mut steps = self.steps.clone();
+        steps.extend(local.steps.iter().cloned());
+        Self {
+            steps,
+            offset: local.offset.clone().or_else(|| self.offset.clone()),
+        }
+    }
+}
+
+impl Cfi {
+    /// Parses a CFI, with or without the `epubcfi(...)` wrapper and the
+    /// leading `#` of a fragment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CfiError::Invalid`] with the position of the syntax error.
+    pub fn parse(cfi: &str) -> Result<Self, CfiError> {
+        let cfi = cfi.strip_prefix('#').unwrap_or(cfi);
+        let (inner, start) = match cfi.strip_prefix("epubcfi(") {
+            Some(inner) => {
+                let inner = inner
+                    .strip_suffix(')')
+                    .ok_or(CfiError::Invalid(cfi.len()))?;
+                (inner, "epubcfi(".len())
+            }
+            None => (cfi, 0),
+        };
+
+        let mut parser = Parser {
+            input: inner,
+            position: 0,
+            start,
+        };
+        let path = parser.path(true)?;
+        let range = if parser.eat(',') {
+            let from = parser.path(false)?;
+            if !parser.eat(',') {
+                return Err(parser.error());
+            }
+            let to = parser.path(false)?;
+            Some((from, to))
+        } else {
+            None
+        };
+        if parser.position < parser.input.len() {
+            return Err(parser.error());
+        }
+        Ok(Self { path, range })
+    }
+
+    /// Returns `true` if the CFI is a range
+    #[must_use]
+    pub const fn is_range(&self) -> bool {
+        self.range.is_some()
+    }
+
+    /// Returns the location, or the start of a range
+    #[must_use]
+    pub fn start(&self) -> CfiPath {
+        match &self.range {
+            Some((start, _)) => self.path.join(start),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Returns the location, or the end of a range
+    #[must_use]
+    pub fn end(&self) -> CfiPath {
+        match &self.range {
+            Some((_, end)) => self.path.join(end),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Returns a range CFI from `start` to `end`, with their common steps
+    /// as parent
+    #[must_use]
+    pub fn from_range(start: CfiPath, end: CfiPath) -> Self {
+        let common = start
+            .steps
+            .iter()
+            .zip(&end.steps)
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(start.steps.len().saturating_sub(1))
+            .min(end.steps.len().saturating_sub(1));
+        let path = CfiPath {
+            steps: start.steps[..common].to_vec(),
+            offset: None,
+        };
+        let local = |path: CfiPath| CfiPath {
+            steps: path.steps[common..].to_vec(),
+            offset: path.offset,
+        };
+        Self {
+            path,
+            range: Some((local(start), local(end))),
+        }
+    }
+}
+
+impl FromStr for Cfi {
+    type Err = CfiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl From<CfiPath> for Cfi {
+    fn from(path: CfiPath) -> Self {
+        Self { path, range: None }
+    }
+}
+
+impl PartialOrd for Cfi {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Orders the CFIs in reading order, by start and then by end. CFIs to the
+/// same location are ordered by their text.
+impl Ord for Cfi {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.start()
+            .cmp_location(&other.start())
+            .then_with(|| self.end().cmp_location(&other.end()))
+            .then_with(|| self.to_string().cmp(&other.to_string()))
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.indirect {
+            f.write_str("!")?;
+        }
+        write!(f, "/{}", self.index)?;
+        if let Some(assertion) = &self.assertion {
+            write!(f, "[{}]", assertion)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CfiPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "{}", step)?;
+        }
+        if let Some(offset) = &self.offset {
+            write!(f, ":{}", offset.offset)?;
+            if let Some(assertion) = &offset.assertion {
+                write!(f, "[{}]", assertion)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cfi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epubcfi({}", self.path)?;
+        if let Some((start, end)) = &self.range {
+            write!(f, ",{},{}", start, end)?;
+        }
+        f.write_str(")")
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    /// Position of the input in the whole CFI, for the errors
+    start: usize,
+}
+
+impl Parser<'_> {
+    const fn error(&self) -> CfiError {
+        CfiError::Invalid(self.start + self.position)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses the steps and offset, at least one step if `required`
+    fn path(&mut self, required: bool) -> Result<CfiPath, CfiError> {
+        let mut path = CfiPath::default();
+        loop {
+            let indirect = self.eat('!');
+            if !self.eat('/') {
+                if indirect {
+                    return Err(self.error());
+                }
+                break;
+            }
+            let index = self.integer()?;
+            let assertion = self.assertion()?;
+            path.steps.push(Step {
+                index,
+                assertion,
+                indirect,
+            });
+        }
+        if required && path.steps.is_empty() {
+            return Err(self.error());
+        }
+        match self.peek() {
+            Some(':') => {
+                self.position += 1;
+                let offset = self.integer()?;
+                let assertion = self.assertion()?;
+                path.offset = Some(CharOffset { offset, assertion });
+            }
+            Some(c @ ('~' | '@')) => return Err(CfiError::Unsupported(c)),
+            _ => {}
+        }
+        Ok(path)
+    }
+
+    fn integer(&mut self) -> Result<usize, CfiError> {
+        let digits = self.input[self.position..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.input.len() - self.position);
+        let value = self.input[self.position..self.position + digits]
+            .parse()
+            .map_err(|_| self.error())?;
+        self.position += digits;
+        Ok(value)
+    }
+
+    /// Parses an optional assertion, keeping it escaped
+    fn assertion(&mut self) -> Result<Option<String>, CfiError> {
+        if !self.eat('[') {
+            return Ok(None);
+        }
+        let rest = &self.input[self.position..];
+        let (assertion, after) = split_unescaped(rest, ']');
+        if after.is_none() {
+            return Err(CfiError::Invalid(self.start + self.input.len()));
+        }
+        self.position += assertion.len() + 1;
+        Ok(Some(assertion.to_string()))
+    }
+}
+
+/// Splits `s` at the first `separator` not escaped with `^`
+fn split_unescaped(s: &str, separator: char) -> (&str, Option<&str>) {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '^' => escaped = true,
+            c if c == separator => return (&s[..i], Some(&s[i + 1..])),
+            _ => {}
+        }
+    }
+    (s, None)
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if SPECIAL.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+impl Extract {
+    /// Returns the steps and character offset of the text byte `offset`,
+    /// moved back to the start of its character
+    pub(crate) fn cfi_path(&self, offset: usize) -> CfiPath {
+        let index = self.segments.partition_point(|s| s.offset <= offset);
+        let Some(segment) = index.checked_sub(1).map(|i| self.segments[i]) else {
+            // no text before, pointing to the first element
+            let steps = self
+                .elements
+                .iter()
+                .find(|e| !e.steps.is_empty())
+                .map(|e| e.steps.clone())
+                .unwrap_or_default();
+            return CfiPath {
+                steps,
+                offset: None,
+            };
+        };
+        let mut end = offset.min(self.text.len());
+        while !self.text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let delta: usize = self.text[segment.offset..end]
+            .chars()
+            .map(char::len_utf16)
+            .sum();
+        CfiPath {
+            steps: self.nodes[segment.node].clone(),
+            offset: Some(CharOffset {
+                offset: segment.source + delta,
+                assertion: None,
+            }),
+        }
+    }
+
+    /// Returns the range CFI of the text `range`, after the spine item
+    /// steps `prefix`
+    pub(crate) fn cfi_range(&self, prefix: &[Step], range: Range<usize>) -> Cfi {
+        let start = content_path(prefix, self.cfi_path(range.start));
+        let end = content_path(prefix, self.cfi_path(range.end));
+        Cfi::from_range(start, end)
+    }
+
+    /// Returns the text offset of the content document `path`, or of the
+    /// nearest location after it
+    fn text_offset(&self, path: &CfiPath) -> usize {
+        let indices: Vec<usize> = path.steps.iter().map(|s| s.index).collect();
+        let same = |steps: &[Step]| steps.iter().map(|s| s.index).eq(indices.iter().copied());
+
+        // the ids asserted win over the indices
+        for (depth, step) in path.steps.iter().enumerate().rev() {
+            let Some(id) = step.id() else {
+                continue;
+            };
+            let prefix = |steps: &[Step]| {
+                steps.len() == depth + 1
+                    && steps
+                        .iter()
+                        .zip(&indices)
+                        .all(|(s, index)| s.index == *index)
+            };
+            let matches = self
+                .elements
+                .iter()
+                .any(|e| prefix(&e.steps) && e.id.as_deref() == Some(id.as_str()));
+            if !matches {
+                if let Some(element) = self.elements.iter().find(|e| e.id.as_ref() == Some(&id)) {
+                    return element.offset;
+                }
+            }
+            break;
+        }
+
+        if let Some(node) = self.nodes.iter().position(|steps| same(steps)) {
+            let offset = path.offset.as_ref().map_or(0, |o| o.offset);
+            let segment = self
+                .segments
+                .iter()
+                .rfind(|s| s.node == node && s.source <= offset);
+            if let Some(segment) = segment {
+                let mut units = segment.source;
+                for (i, c) in self.text[segment.offset..].char_indices() {
+                    if units >= offset {
+                        return segment.offset + i;
+                    }
+                    units += c.len_utf16();
+                }
+                return self.text.len();
+            }
+        }
+        if let Some(element) = self.elements.iter().find(|e| same(&e.steps)) {
+            return element.offset;
+        }
+
+        // the nearest element or text after the location
+        let after = |steps: &[Step]| {
+            steps
+                .iter()
+                .map(|s| s.index)
+                .cmp(indices.iter().copied())
+                .is_ge()
+        };
+        let element = self
+            .elements
+            .iter()
+            .find(|e| after(&e.steps))
+            .map(|e| e.offset);
+        let text = self
+            .segments
+            .iter()
+            .find(|s| after(&self.nodes[s.node]))
+            .map(|s| s.offset);
+        match (element, text) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b).unwrap_or(self.text.len()),
+        }
+    }
+
+    /// Returns the location of the content document `path`
+    fn locate(&self, spine_index: usize, path: &CfiPath) -> Location {
+        let text_offset = self.text_offset(path);
+        Location {
+            spine_index,
+            text_offset,
+            element_id: self.anchor_at(text_offset).map(String::from),
+        }
+    }
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Returns the location the CFI points to, or the start of a range.
+    ///
+    /// If an element asserted in the CFI by its id isn't at the position
+    /// expected, the element with that id is used instead. If the location
+    /// doesn't exist in the chapter, the nearest one after it is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CfiError::NotFound`] if the CFI doesn't point to a spine
+    /// item, or an error if the documents can't be read or parsed.
+    pub fn resolve_cfi(&mut self, cfi: &Cfi) -> Result<Location, DocError> {
+        self.resolve_cfi_path(&cfi.start())
+    }
+
+    /// Returns the start and end locations of the CFI, see
+    /// [`Self::resolve_cfi`]. Both are the same if it isn't a range.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CfiError::NotFound`] if the CFI doesn't point to a spine
+    /// item, or an error if the documents can't be read or parsed.
+    pub fn resolve_cfi_range(&mut self, cfi: &Cfi) -> Result<(Location, Location), DocError> {
+        let start = self.resolve_cfi_path(&cfi.start())?;
+        let end = self.resolve_cfi_path(&cfi.end())?;
+        Ok((start, end))
+    }
+
+    /// Returns the CFI of the DOM `position` in the spine item `index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, [`CfiError::NotFound`] if the element or the character
+    /// doesn't exist, or an error if the documents can't be read or parsed.
+    pub fn generate_cfi(&mut self, index: usize, position: &DomPosition) -> Result<Cfi, DocError> {
+        let spine = self.spine_steps()?;
+        let prefix = spine.get(index).ok_or(DocError::SpineIndexOutOfBounds {
+            index,
+            len: spine.len(),
+        })?;
+        let root = self.chapter_dom(index)?;
+        let path = dom_path(&root, position)
+            .ok_or_else(|| CfiError::NotFound(format!("{:?}", position)))?;
+        Ok(content_path(prefix, path).into())
+    }
+
+    /// Returns the CFI of the byte `offset`, not a count of characters, in
+    /// the text of the spine item `index`, as returned by
+    /// [`Self::chapter_text`] or the search hits. An offset inside a
+    /// character is moved back to its start.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, or an error if the documents can't be read or parsed.
+    pub fn generate_text_cfi(&mut self, index: usize, offset: usize) -> Result<Cfi, DocError> {
+        let spine = self.spine_steps()?;
+        let prefix = spine.get(index).ok_or(DocError::SpineIndexOutOfBounds {
+            index,
+            len: spine.len(),
+        })?;
+        let extract = self.chapter_extract(index, TextOptions::default())?;
+        Ok(content_path(prefix, extract.cfi_path(offset)).into())
+    }
+
+    /// Returns the range CFI of the text `range` of the spine item `index`,
+    /// in bytes of the text returned by [`Self::chapter_text`]. The offsets
+    /// inside a character are moved back to its start.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, or an error if the documents can't be read or parsed.
+    pub fn generate_cfi_range(
+        &mut self,
+        index: usize,
+        range: Range<usize>,
+    ) -> Result<Cfi, DocError> {
+        let spine = self.spine_steps()?;
+        let prefix = spine.get(index).ok_or(DocError::SpineIndexOutOfBounds {
+            index,
+            len: spine.len(),
+        })?;
+        let extract = self.chapter_extract(index, TextOptions::default())?;
+        Ok(extract.cfi_range(prefix, range))
+    }
+
+    fn resolve_cfi_path(&mut self, path: &CfiPath) -> Result<Location, DocError> {
+        let not_found = || CfiError::NotFound(Cfi::from(path.clone()).to_string());
+        let split = path
+            .steps
+            .iter()
+            .position(|s| s.indirect)
+            .unwrap_or(path.steps.len());
+        let (package, content) = path.steps.split_at(split);
+        let [spine_step, itemref_step] = package else {
+            return Err(not_found().into());
+        };
+
+        let root = self.package_dom()?;
+        child_element(&root, spine_step.index)
+            .filter(|e| e.name() == "spine")
+            .ok_or_else(not_found)?;
+        let itemrefs = self.spine_itemrefs(&root);
+        let asserted = itemref_step
+            .id()
+            .and_then(|id| itemrefs.iter().position(|(_, e)| e.attr("id") == Some(&id)));
+        let spine_index = asserted
+            .or_else(|| {
+                itemrefs
+                    .iter()
+                    .position(|(steps, _)| steps[1].index == itemref_step.index)
+            })
+            .ok_or_else(not_found)?;
+
+        let extract = self.chapter_extract(spine_index, TextOptions::default())?;
+        let content = CfiPath {
+            steps: content.to_vec(),
+            offset: path.offset.clone(),
+        };
+        Ok(extract.locate(spine_index, &content))
+    }
+
+    /// Returns the package document steps to every spine item
+    pub(crate) fn spine_steps(&mut self) -> Result<Vec<Vec<Step>>, DocError> {
+        let root = self.package_dom()?;
+        let itemrefs = self.spine_itemrefs(&root);
+        Ok(itemrefs.into_iter().map(|(steps, _)| steps).collect())
+    }
+
+    /// Returns the package document steps and the element of every spine
+    /// item, by spine index. The elements dropped from the spine in lenient
+    /// mode are skipped.
+    fn spine_itemrefs<'a>(&self, root: &'a Element) -> Vec<(Vec<Step>, &'a Element)> {
+        let mut itemrefs = vec![];
+        let Some((i, spine)) = child_elements(root)
+            .enumerate()
+            .find(|(_, e)| e.name() == "spine")
+        else {
+            return itemrefs;
+        };
+        let spine_step = Step::new(2 * (i + 1), spine.attr("id"));
+        let mut items = self.spine.iter().peekable();
+        for (j, itemref) in child_elements(spine).enumerate() {
+            if items.peek().map(|item| item.idref.as_str()) != itemref.attr("idref") {
+                continue;
+            }
+            items.next();
+            let itemref_step = Step::new(2 * (j + 1), itemref.attr("id"));
+            itemrefs.push((vec![spine_step.clone(), itemref_step], itemref));
+        }
+        itemrefs
+    }
+
+    fn package_dom(&mut self) -> Result<Element, DocError> {
+        let root_file = self.root_file.clone();
+        let content = self.archive.get_entry(&root_file)?;
+        let strict = self.options.mode == ParseMode::Strict;
+        let root = dom::parse(&content, strict, &self.options.limits);
+        Ok(root.map_err(|e| e.in_file(&root_file))?)
+    }
+}
+
+/// Returns the `path` in a content document after the spine item steps
+fn content_path(prefix: &[Step], path: CfiPath) -> CfiPath {
+    let mut steps = prefix.to_vec();
+    let mut content = path.steps.into_iter();
+    if let Some(mut first) = content.next() {
+        first.indirect = true;
+        steps.push(first);
+    }
+    steps.extend(content);
+    CfiPath {
+        steps,
+        offset: path.offset,
+    }
+}
+
+/// Returns the content document path of the DOM `position`, or [`None`] if
+/// it doesn't exist in the `root` element
+fn dom_path(root: &Element, position: &DomPosition) -> Option<CfiPath> {
+    let mut element = root;
+    let mut steps = vec![];
+    for &index in &position.path {
+        element = child_elements(element).nth(index)?;
+        steps.push(Step::new(2 * (index + 1), element.attr("id")));
+    }
+    let Some(offset) = position.offset else {
+        return Some(CfiPath {
+            steps,
+            offset: None,
+        });
+    };
+    match text_position(element, offset) {
+        Ok((text_steps, offset)) => {
+            steps.extend(text_steps);
+            Some(CfiPath {
+                steps,
+                offset: Some(CharOffset {
+                    offset,
+                    assertion: None,
+                }),
+            })
+        }
+        // the start of an element without text
+        Err(0) if offset == 0 => Some(CfiPath {
+            steps,
+            offset: None,
+        }),
+        Err(_) => None,
+    }
+}
+
+/// Returns the steps to the text between the children of `element` that
+/// has the character `offset` of its text, and the offset in that text.
+/// The offsets are in UTF-16 code units, the error is the length of the
+/// text of `element` when it's shorter.
+fn text_position(element: &Element, offset: usize) -> Result<(Vec<Step>, usize), usize> {
+    let mut seen = 0;
+    // the odd step of the text, and the length of what's before it
+    let mut step = 1;
+    let mut chunk = 0;
+    for child in &element.children {
+        match child {
+            Node::Text(text) => {
+                let len = text.encode_utf16().count();
+                if len > 0 && offset <= seen + len {
+                    return Ok((vec![Step::new(step, None)], chunk + offset - seen));
+                }
+                seen += len;
+                chunk += len;
+            }
+            Node::Element(e) => {
+                match text_position(e, offset - seen) {
+                    Ok((mut steps, offset)) => {
+                        steps.insert(0, Step::new(step + 1, e.attr("id")));
+                        return Ok((steps, offset));
+                    }
+                    Err(len) => seen += len,
+                }
+                step += 2;
+                chunk = 0;
+            }
+        }
+    }
+    Err(seen)
+}
+
+fn child_elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(|child| match child {
+        Node::Element(e) => Some(e),
+        Node::Text(_) => None,
+    })
+}
+
+/// Returns the child element of the even step `index`
+fn child_element(element: &Element, index: usize) -> Option<&Element> {
+    if index == 0 || index % 2 == 1 {
+        return None;
+    }
+    child_elements(element).nth(index / 2 - 1)
+}
@@ -0,0 +1,263 @@
+//! A CSS tokenizer, just enough to find the references to other resources:
+//! `url()`, the `@import` strings and the strings of `image-set()`.
+//!
+//! Comments and strings are skipped, so a `url(` inside them isn't taken
+//! as a reference, and the escapes of the strings and urls are decoded.
+
+/// Returns the `css` with its references replaced by `rewrite`. The
+/// references to the document itself, `#id`, are kept.
+pub fn rewrite_references(css: &str, rewrite: &dyn Fn(&str) -> String) -> String {
+    let mut tokenizer = Tokenizer {
+        css,
+        position: 0,
+        out: String::with_capacity(css.len()),
+        copied: 0,
+    };
+    tokenizer.run(rewrite);
+    tokenizer.out.push_str(&css[tokenizer.copied..]);
+    tokenizer.out
+}
+
+struct Tokenizer<'a> {
+    css: &'a str,
+    position: usize,
+    out: String,
+    /// The css before this position is already in `out`
+    copied: usize,
+}
+
+impl Tokenizer<'_> {
+    fn run(&mut self, rewrite: &dyn Fn(&str) -> String) {
+        // `true` for the open functions whose strings are urls
+        let mut functions: Vec<bool> = vec![];
+        let mut import = false;
+
+        while let Some(c) = self.peek() {
+            match c {
+                '/' if self.rest().starts_with("/*") => {
+                    let end = self.rest()[2..]
+                        .find("*/")
+                        .map_or(self.css.len(), |i| self.position + i + 4);
+                    self.position = end;
+                }
+                '"' | '\'' => {
+                    let start = self.position;
+                    let value = self.string();
+                    if import || functions.last() == Some(&true) {
+                        self.replace(start, &value, rewrite, quote);
+                    }
+                    import = false;
+                }
+                '@' => {
+                    self.position += 1;
+                    let name = self.ident();
+                    import = name.eq_ignore_ascii_case("import");
+                }
+                '\\' => {
+                    self.escape();
+                }
+                c if is_name_start(c) => {
+                    let start = self.position;
+                    let name = self.ident();
+                    if self.peek() != Some('(') {
+                        continue;
+                    }
+                    self.position += 1;
+                    if name.eq_ignore_ascii_case("url") {
+                        if let Some(value) = self.url() {
+                            self.replace(start, &value, rewrite, |url| {
+                                format!("url({})", quote(url))
+                            });
+                            import = false;
+                            continue;
+                        }
+                    }
+                    let name = name.to_ascii_lowercase();
+                    functions.push(name == "image-set" || name == "-webkit-image-set");
+                }
+                '(' | '[' | '{' => {
+                    self.position += 1;
+                    functions.push(false);
+                    import &= c != '{';
+                }
+                ')' | ']' | '}' => {
+                    self.position += 1;
+                    functions.pop();
+                }
+                ';' => {
+                    self.position += 1;
+                    import = false;
+                }
+                c => self.position += c.len_utf8(),
+            }
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.css[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Replaces the css from `start` to the current position by the `url`
+    /// rewritten, formatted by `format`, if it changes
+    fn replace(
+        &mut self,
+        start: usize,
+        url: &str,
+        rewrite: &dyn Fn(&str) -> String,
+        format: impl Fn(&str) -> String,
+    ) {
+        if url.is_empty() || url.starts_with('#') {
+            return;
+        }
+        let rewritten = rewrite(url);
+        if rewritten == url {
+            return;
+        }
+        self.out.push_str(&self.css[self.copied..start]);
+        self.out.push_str(&format(&rewritten));
+        self.copied = self.position;
+    }
+
+    /// Consumes a name, decoding its escapes
+    fn ident(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                match self.escape() {
+                    Some(c) => name.push(c),
+                    None => break,
+                }
+            } else if is_name_start(c) || c.is_ascii_digit() || c == '-' {
+                name.push(c);
+                self.position += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        name
+    }
+
+    /// Consumes a quoted string, returning its decoded value
+    fn string(&mut self) -> String {
+        let Some(quote) = self.peek() else {
+            return String::new();
+        };
+        self.position += 1;
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                c if c == quote => {
+                    self.position += 1;
+                    break;
+                }
+                // a bad string, ending at the line
+                '\n' => break,
+                '\\' if self.rest()[1..].starts_with('\n') => self.position += 2,
+                '\\' => value.extend(self.escape()),
+                c => {
+                    value.push(c);
+                    self.position += c.len_utf8();
+                }
+            }
+        }
+        value
+    }
+
+    /// Consumes the argument of `url(` and the closing parenthesis,
+    /// returning the url, or [`None`] if it isn't a valid url token
+    fn url(&mut self) -> Option<String> {
+        let start = self.position;
+        self.skip_whitespace();
+        let value = if matches!(self.peek(), Some('"' | '\'')) {
+            let value = self.string();
+            self.skip_whitespace();
+            value
+        } else {
+            let mut value = String::new();
+            while let Some(c) = self.peek() {
+                match c {
+                    ')' => break,
+                    c if c.is_whitespace() => {
+                        self.skip_whitespace();
+                        break;
+                    }
+                    '"' | '\'' | '(' => {
+                        self.position = start;
+                        return None;
+                    }
+                    '\\' => value.extend(self.escape()),
+                    c => {
+                        value.push(c);
+                        self.position += c.len_utf8();
+                    }
+                }
+            }
+            value
+        };
+        if self.peek() == Some(')') {
+            self.position += 1;
+            Some(value)
+        } else {
+            self.position = start;
+            None
+        }
+    }
+
+    /// Consumes an escape starting with `\`, returning the character
+    fn escape(&mut self) -> Option<char> {
+        self.position += 1;
+        let rest = self.rest();
+        let hex = rest
+            .chars()
+            .take(6)
+            .take_while(char::is_ascii_hexdigit)
+            .count();
+        if hex > 0 {
+            let code = u32::from_str_radix(&rest[..hex], 16).unwrap_or(0xFFFD);
+            self.position += hex;
+            // one whitespace after the hex digits is part of the escape
+            if let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+                self.position += c.len_utf8();
+            }
+            return Some(match code {
+                0 => '\u{FFFD}',
+                code => char::from_u32(code).unwrap_or('\u{FFFD}'),
+            });
+        }
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.position += c.len_utf8();
+        }
+    }
+}
+
+const fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '-' || !c.is_ascii()
+}
+
+/// Returns the `value` as a double quoted CSS string
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\a "),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
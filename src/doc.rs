@@ -4,20 +4,30 @@
 //! chapters, etc.
 //!
 //! Main references to EPUB specs:
-//! - https://www.w3.org/TR/epub-33
-//! - https://idpf.org/epub/201
+//! - <https://www.w3.org/TR/epub-33>
+//! - <https://idpf.org/epub/201>
 
+pub use crate::xmlutils::XMLError;
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::{Read, Seek};
-use std::path::{Component, Path, PathBuf};
-use xmlutils::XMLError;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::archive::EpubArchive;
+use crate::archive::{ArchiveError, ArchivePath, EpubArchive, PathError};
+use crate::encoding;
+use crate::encryption::{self, DrmEvidence, DrmReport, EncryptedData, Obfuscation};
+use crate::inject::{Injection, Placement};
+use crate::limits::{LimitError, Limits};
+use crate::ocf::{self, ManifestEntry, Rights, Signature, VerifiedReference};
+use crate::references;
+use crate::uri::{EpubScheme, UriResolver};
+use crate::validate::{Diagnostic, Severity};
 
-use crate::xmlutils;
+use crate::xmlutils::{self, Syntax};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DocError {
@@ -27,11 +37,115 @@ pub enum DocError {
     XmlError(#[from] crate::xmlutils::XMLError),
     #[error("I/O Error: {0}")]
     IOError(#[from] std::io::Error),
-    #[error("Invalid EPub")]
-    InvalidEpub,
+    #[error("Missing <manifest> element in {}", .path.display())]
+    MissingManifest { path: PathBuf },
+    #[error("Missing <spine> element in {}", .path.display())]
+    MissingSpine { path: PathBuf },
+    #[error("Missing <metadata> element in {}", .path.display())]
+    MissingMetadata { path: PathBuf },
+    #[error("TOC \"{id}\" not found in the manifest of {}", .path.display())]
+    TocNotFound { id: String, path: PathBuf },
+    #[error("Resource \"{id}\" not found in the manifest")]
+    ResourceNotFound { id: String },
+    #[error("Spine index {index} out of bounds, the spine has {len} items")]
+    SpineIndexOutOfBounds { index: usize, len: usize },
+    #[error("Invalid EPub: {0}")]
+    SpecViolation(Diagnostic),
+    #[error("CFI Error: {0}")]
+    Cfi(#[from] crate::cfi::CfiError),
+    #[cfg(feature = "lcp")]
+    #[error("LCP Error: {0}")]
+    Lcp(#[from] crate::lcp::LcpError),
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+impl DocError {
+    /// Returns the limit exceeded if this error is caused by a breach of
+    /// the [`Limits`] in [`EpubOptions`]
+    #[must_use]
+    pub fn limit_exceeded(&self) -> Option<&LimitError> {
+        match self {
+            Self::ArchiveError(crate::archive::ArchiveError::LimitExceeded(e)) => Some(e),
+            Self::XmlError(e) => match e.inner() {
+                XMLError::LimitExceeded(e) => Some(e),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Returns [`None`] for the error of a spine item that can't be read or
+/// parsed, to skip it and carry on with the rest of the book. The limits
+/// exceeded are still errors.
+pub(crate) fn skip_broken<T>(result: Result<T, DocError>) -> Result<Option<T>, DocError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.limit_exceeded().is_some() => Err(e),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A parsed file of the META-INF directory, with its content
+type MetaInfFile = (RefCell<xmlutils::XMLNode>, Vec<u8>);
+
+/// How to deal with epub files that don't follow the spec
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// The mode of [`EpubDoc::new`] and [`EpubDoc::from_reader`]: a broken
+    /// package document or a missing `<manifest>`, `<spine>` or
+    /// `<metadata>` is an error, while the broken manifest, spine or table
+    /// of contents items are skipped, collecting them as warnings. The
+    /// content documents are read as leniently as in [`Self::Lenient`] and
+    /// the epub isn't validated.
+    #[default]
+    Compatible,
+    /// Fails on the first spec violation found, with a precise error
+    Strict,
+    /// Recovers as much as possible, collecting the problems found as
+    /// warnings. See [`EpubDoc::warnings`].
+    Lenient,
+}
+
+/// Options to open an epub file
+///
+/// # Examples
+///
+/// ```
+/// use epub::doc::{EpubDoc, EpubOptions};
+///
+/// // test.epub doesn't store the mimetype as the first file
+/// let doc = EpubDoc::new_with_options("test.epub", EpubOptions::strict());
+/// assert!(doc.is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EpubOptions {
+    pub mode: ParseMode,
+    /// Limits to protect against malicious files, these are enforced in
+    /// both modes
+    pub limits: Limits,
+}
+
+impl EpubOptions {
+    /// Options to fail on any spec violation
+    #[must_use]
+    pub fn strict() -> Self {
+        Self {
+            mode: ParseMode::Strict,
+            ..Self::default()
+        }
+    }
+
+    /// Options to open anything that can be read
+    #[must_use]
+    pub fn lenient() -> Self {
+        Self {
+            mode: ParseMode::Lenient,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd)]
 pub enum EpubVersion {
     Version2_0,
     Version3_0,
@@ -46,7 +160,7 @@ pub struct NavPoint {
     /// the resource path
     pub content: PathBuf,
     /// nested navpoints
-    pub children: Vec<NavPoint>,
+    pub children: Vec<Self>,
     /// the order in the toc
     pub play_order: Option<usize>,
 }
@@ -95,6 +209,7 @@ pub struct MetadataItem {
 }
 
 impl MetadataItem {
+    #[must_use]
     pub fn refinement(&self, property: &str) -> Option<&MetadataRefinement> {
         self.refined.iter().find(|r| r.property == property)
     }
@@ -124,11 +239,15 @@ pub struct ResourceItem {
 #[derive(Clone, Debug)]
 pub struct EpubDoc<R: Read + Seek> {
     /// the zip archive
-    archive: EpubArchive<R>,
+    pub(crate) archive: EpubArchive<R>,
 
     /// The current chapter, is an spine index
     current: usize,
 
+    /// Byte offset in the text of the current chapter, see
+    /// [`Self::set_current_position`]
+    pub(crate) current_offset: usize,
+
     /// epub spec version
     pub version: EpubVersion,
 
@@ -168,13 +287,40 @@ pub struct EpubDoc<R: Read + Seek> {
     /// Custom css list to inject in every xhtml file
     pub extra_css: Vec<String>,
 
+    /// elements to inject in the head of every xhtml file
+    injections: Vec<(Injection, Placement)>,
+
     /// unique identifier
     pub unique_identifier: Option<String>,
+
+    /// options used to open the epub
+    pub(crate) options: EpubOptions,
+
+    /// problems recovered from while opening the epub
+    warnings: Vec<Diagnostic>,
+
+    /// resources listed in META-INF/encryption.xml
+    encryption: Vec<EncryptedData>,
+
+    /// builds the urls of the `_with_epub_uris` methods
+    uri_resolver: Arc<dyn UriResolver>,
+
+    /// text of the chapters, extracted on the first search
+    pub(crate) search_index: Option<crate::search::SearchIndex>,
+
+    /// LCP content key, once unlocked
+    #[cfg(feature = "lcp")]
+    pub(crate) lcp_key: Option<crate::lcp::ContentKey>,
 }
 
-/// A EpubDoc used for testing purposes
+/// A `EpubDoc` used for testing purposes
 #[cfg(feature = "mock")]
 impl EpubDoc<std::io::Cursor<Vec<u8>>> {
+    /// Returns an empty `EpubDoc`, without any file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the empty zip archive can't be read.
     pub fn mock() -> Result<Self, DocError> {
         // binary for empty zip file so that archive can be created
         let data: Vec<u8> = vec![
@@ -194,8 +340,17 @@ impl EpubDoc<std::io::Cursor<Vec<u8>>> {
             root_file: PathBuf::new(),
             root_base: PathBuf::new(),
             current: 0,
+            current_offset: 0,
             extra_css: vec![],
+            injections: vec![],
             unique_identifier: None,
+            options: EpubOptions::default(),
+            warnings: vec![],
+            encryption: vec![],
+            uri_resolver: Arc::new(EpubScheme),
+            search_index: None,
+            #[cfg(feature = "lcp")]
+            lcp_key: None,
         })
     }
 }
@@ -218,11 +373,24 @@ impl EpubDoc<BufReader<File>> {
     /// # Errors
     ///
     /// Returns an error if the epub is broken or if the file doesn't
-    /// exists.
+    /// exists, see [`ParseMode::Compatible`].
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, DocError> {
+        Self::new_with_options(path, EpubOptions::default())
+    }
+
+    /// Opens the epub file in `path` with the `options` provided.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the epub is broken or if the file doesn't
+    /// exists. In [`ParseMode::Strict`] any spec violation is an error.
+    pub fn new_with_options<P: AsRef<Path>>(
+        path: P,
+        options: EpubOptions,
+    ) -> Result<Self, DocError> {
         let path = path.as_ref();
         let file = File::open(path)?;
-        let mut doc = Self::from_reader(BufReader::new(file))?;
+        let mut doc = Self::from_reader_with_options(BufReader::new(file), options)?;
         doc.archive.path = path.to_path_buf();
         Ok(doc)
     }
@@ -253,13 +421,42 @@ impl<R: Read + Seek> EpubDoc<R> {
     ///
     /// # Errors
     ///
-    /// Returns an error if the epub is broken.
+    /// Returns an error if the epub is broken, see
+    /// [`ParseMode::Compatible`].
     pub fn from_reader(reader: R) -> Result<Self, DocError> {
-        let mut archive = EpubArchive::from_reader(reader)?;
+        Self::from_reader_with_options(reader, EpubOptions::default())
+    }
+
+    /// Opens the epub contained in `reader` with the `options` provided.
+    ///
+    /// With [`ParseMode::Lenient`] the problems found are collected and can
+    /// be read with [`Self::warnings`]. With [`ParseMode::Strict`] the first
+    /// spec violation is returned as an error, a structural one like
+    /// [`DocError::MissingManifest`] or a [`DocError::SpecViolation`] found
+    /// validating the epub. The default [`ParseMode::Compatible`] opens the
+    /// epub like [`Self::from_reader`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use epub::doc::{EpubDoc, EpubOptions};
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    ///
+    /// let file = File::open("test.epub").unwrap();
+    /// let doc = EpubDoc::from_reader_with_options(BufReader::new(file), EpubOptions::lenient());
+    /// assert!(doc.is_ok());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the epub is broken, or on any spec violation in
+    /// [`ParseMode::Strict`].
+    pub fn from_reader_with_options(reader: R, options: EpubOptions) -> Result<Self, DocError> {
+        let mut archive = EpubArchive::from_reader_with_limits(reader, options.limits)?;
 
         let container = archive.get_container_file()?;
-        let root_file = get_root_file(&container)?;
-        let base_path = root_file.parent().expect("All files have a parent");
+        let root_file = get_root_file(&container, &options.limits)?;
         let mut doc = Self {
             archive,
             version: EpubVersion::Version2_0,
@@ -268,16 +465,257 @@ impl<R: Read + Seek> EpubDoc<R> {
             toc_title: String::new(),
             resources: HashMap::new(),
             metadata: Vec::new(),
-            root_file: root_file.clone(),
-            root_base: base_path.to_path_buf(),
+            root_base: root_file.parent().into(),
+            root_file: root_file.into(),
             current: 0,
+            current_offset: 0,
             extra_css: vec![],
+            injections: vec![],
             unique_identifier: None,
+            options,
+            warnings: vec![],
+            encryption: vec![],
+            uri_resolver: Arc::new(EpubScheme),
+            search_index: None,
+            #[cfg(feature = "lcp")]
+            lcp_key: None,
         };
         doc.fill_resources()?;
+        if let Err(e) = doc.fill_encryption() {
+            doc.recover(e)?;
+        }
+
+        if doc.options.mode == ParseMode::Strict {
+            for diagnostic in doc.validate() {
+                doc.report(diagnostic)?;
+            }
+        }
+
         Ok(doc)
     }
 
+    /// Returns the problems found while opening the epub.
+    ///
+    /// In [`ParseMode::Lenient`] and [`ParseMode::Compatible`] these are the
+    /// spec violations that were skipped or recovered from. In
+    /// [`ParseMode::Strict`] only warnings can be found here, errors abort
+    /// the opening.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// let doc = EpubDoc::new("tests/docs/book2.epub").unwrap();
+    /// for warning in doc.warnings() {
+    ///     println!("{}", warning);
+    /// }
+    /// ```
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Returns the resources listed in `META-INF/encryption.xml`.
+    ///
+    /// Obfuscated fonts are de-obfuscated when read, see
+    /// [`encryption`](crate::encryption).
+    pub fn encryption(&self) -> &[EncryptedData] {
+        &self.encryption
+    }
+
+    /// Returns which resources are encrypted and the DRM scheme used, to
+    /// refuse protected books that can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// use epub::encryption::DrmScheme;
+    ///
+    /// let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// let report = doc.drm_report();
+    /// assert_eq!(report.scheme, DrmScheme::None);
+    /// assert!(!report.is_protected());
+    /// ```
+    pub fn drm_report(&mut self) -> DrmReport {
+        let has_rights = self.archive.contains(ocf::RIGHTS_FILE);
+        let rights = if has_rights {
+            let content = self.archive.get_entry(ocf::RIGHTS_FILE);
+            Some(String::from_utf8_lossy(&content.unwrap_or_default()).into_owned())
+        } else {
+            None
+        };
+        let scheme = encryption::classify(&DrmEvidence {
+            resources: &self.encryption,
+            rights: rights.as_deref(),
+            lcp_license: self.archive.contains(ocf::LCP_LICENSE_FILE),
+            fairplay: self.archive.contains(ocf::FAIRPLAY_FILE),
+        });
+
+        DrmReport {
+            scheme,
+            resources: self.encryption.clone(),
+            has_rights,
+            has_signatures: self.archive.contains(ocf::SIGNATURES_FILE),
+        }
+    }
+
+    /// Returns the signatures of `META-INF/signatures.xml`, empty if the
+    /// epub isn't signed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signatures file can't be read or parsed.
+    pub fn signatures(&mut self) -> Result<Vec<Signature>, DocError> {
+        let Some((root, _)) = self.parse_meta_inf(ocf::SIGNATURES_FILE)? else {
+            return Ok(vec![]);
+        };
+        let signatures = ocf::parse_signatures(&root.borrow());
+        Ok(signatures)
+    }
+
+    /// Checks the digest of every reference of `META-INF/signatures.xml`
+    /// against the archive entries. The certificates aren't validated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// use epub::ocf::DigestStatus;
+    ///
+    /// let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// let verified = doc.verify_signatures().unwrap();
+    /// assert!(verified.iter().all(|r| r.status != DigestStatus::Mismatch));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signatures file can't be read or parsed.
+    pub fn verify_signatures(&mut self) -> Result<Vec<VerifiedReference>, DocError> {
+        let mut verified = vec![];
+        for reference in self.signatures()?.into_iter().flat_map(|s| s.references) {
+            let status = match reference.path() {
+                Some(path) => match self.archive.get_entry(&path) {
+                    Ok(data) => reference.verify(&data),
+                    Err(ArchiveError::EntryNotFound(_)) => ocf::DigestStatus::Missing,
+                    Err(e) => return Err(e.into()),
+                },
+                None => ocf::DigestStatus::Unsupported,
+            };
+            verified.push(VerifiedReference { reference, status });
+        }
+        Ok(verified)
+    }
+
+    /// Returns the `META-INF/rights.xml` file, if the epub has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rights file can't be read or parsed.
+    pub fn rights(&mut self) -> Result<Option<Rights>, DocError> {
+        let rights = self
+            .parse_meta_inf(ocf::RIGHTS_FILE)?
+            .map(|(root, content)| {
+                let content = String::from_utf8_lossy(&content).into_owned();
+                ocf::parse_rights(&root.borrow(), content)
+            });
+        Ok(rights)
+    }
+
+    /// Returns the container level metadata of `META-INF/metadata.xml`,
+    /// empty if the epub doesn't have it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata file can't be read or parsed.
+    pub fn container_metadata(&mut self) -> Result<Vec<MetadataItem>, DocError> {
+        let Some((root, _)) = self.parse_meta_inf(ocf::METADATA_FILE)? else {
+            return Ok(vec![]);
+        };
+        let metadata = parse_metadata(&root.borrow(), &EpubVersion::Version3_0);
+        Ok(metadata)
+    }
+
+    /// Returns the entries of the ODF manifest `META-INF/manifest.xml`,
+    /// empty if the epub doesn't have it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest file can't be read or parsed.
+    pub fn container_manifest(&mut self) -> Result<Vec<ManifestEntry>, DocError> {
+        let Some((root, _)) = self.parse_meta_inf(ocf::MANIFEST_FILE)? else {
+            return Ok(vec![]);
+        };
+        let manifest = ocf::parse_manifest(&root.borrow());
+        Ok(manifest)
+    }
+
+    /// Parses the optional XML file `path` of the META-INF directory,
+    /// returning [`None`] if the epub doesn't have it
+    fn parse_meta_inf(&mut self, path: &str) -> Result<Option<MetaInfFile>, DocError> {
+        if !self.archive.contains(path) {
+            return Ok(None);
+        }
+        let content = self.archive.get_entry(path)?;
+        let root = xmlutils::XMLReader::parse(&content, true, &self.options.limits)
+            .map_err(|e| e.in_file(path))?;
+        Ok(Some((root, content)))
+    }
+
+    /// Records a problem found while opening the epub, failing if it's an
+    /// error in strict mode
+    fn report(&mut self, diagnostic: Diagnostic) -> Result<(), DocError> {
+        if self.options.mode == ParseMode::Strict && diagnostic.severity == Severity::Error {
+            return Err(DocError::SpecViolation(diagnostic));
+        }
+        self.warnings.push(diagnostic);
+        Ok(())
+    }
+
+    /// Returns the error `e` in strict mode, or records it as a warning to
+    /// carry on opening the epub in lenient mode. In compatible mode only
+    /// the structural errors are returned.
+    fn recover(&mut self, e: DocError) -> Result<(), DocError> {
+        let fatal = match self.options.mode {
+            ParseMode::Strict => true,
+            ParseMode::Compatible => matches!(
+                e,
+                DocError::MissingManifest { .. }
+                    | DocError::MissingSpine { .. }
+                    | DocError::MissingMetadata { .. }
+            ),
+            ParseMode::Lenient => false,
+        };
+        // limits are never recovered from
+        if fatal || e.limit_exceeded().is_some() {
+            return Err(e);
+        }
+        let diagnostic = Diagnostic::from_doc_error(&self.root_file, &e);
+        self.warnings.push(diagnostic);
+        Ok(())
+    }
+
+    /// Parses the XML file `path` of the archive. In lenient mode a broken
+    /// document is reported and the elements before the error are kept,
+    /// in the other modes it's an error.
+    fn parse_xml_file(&mut self, path: &Path) -> Result<RefCell<xmlutils::XMLNode>, DocError> {
+        let content = self.archive.get_entry(path)?;
+        let limits = self.options.limits;
+        match xmlutils::XMLReader::parse(&content, true, &limits) {
+            Ok(root) => Ok(root),
+            // limits are never recovered from
+            Err(e @ XMLError::LimitExceeded(_)) => Err(e.in_file(path).into()),
+            Err(e) => {
+                let e = e.in_file(path).into();
+                if self.options.mode != ParseMode::Lenient {
+                    return Err(e);
+                }
+                self.recover(e)?;
+                let root = xmlutils::XMLReader::parse(&content, false, &limits);
+                Ok(root.map_err(|e| e.in_file(path))?)
+            }
+        }
+    }
+
     /// Returns the first metadata found with this property name.
     ///
     /// # Examples
@@ -381,7 +819,7 @@ impl<R: Read + Seek> EpubDoc<R> {
     }
 
     /// Returns Release Identifier defined at
-    /// https://www.w3.org/publishing/epub32/epub-packages.html#sec-metadata-elem-identifiers-pid
+    /// <https://www.w3.org/publishing/epub32/epub-packages.html#sec-metadata-elem-identifiers-pid>
     pub fn get_release_identifier(&self) -> Option<String> {
         match (
             self.unique_identifier.as_ref(),
@@ -396,38 +834,118 @@ impl<R: Read + Seek> EpubDoc<R> {
 
     /// Returns the resource content by full path in the epub archive
     ///
-    /// Returns [`None`] if the path doesn't exist in the epub
+    /// Returns [`None`] if the path doesn't exist in the epub, see
+    /// [`Self::try_get_resource_by_path`] to know the cause.
     pub fn get_resource_by_path<P: AsRef<Path>>(&mut self, path: P) -> Option<Vec<u8>> {
-        self.archive.get_entry(path).ok()
+        self.try_get_resource_by_path(path).ok()
+    }
+
+    /// Returns the resource content by full path in the epub archive
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArchiveError::EntryNotFound`](crate::archive::ArchiveError::EntryNotFound)
+    /// if the path doesn't exist in the epub or
+    /// [`ArchiveError::Decompression`](crate::archive::ArchiveError::Decompression)
+    /// if the file is corrupted.
+    pub fn try_get_resource_by_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<Vec<u8>, DocError> {
+        let content = self.archive.get_entry(&path)?;
+        self.decode_resource(path.as_ref(), content)
     }
 
     /// Returns the resource content and mime-type by the id defined in the spine
     ///
-    /// Returns [`None`] if the id doesn't exists in the epub
+    /// Returns [`None`] if the id doesn't exists in the epub, see
+    /// [`Self::try_get_resource`] to know the cause.
     pub fn get_resource(&mut self, id: &str) -> Option<(Vec<u8>, String)> {
-        let ResourceItem { path, mime, .. } = self.resources.get(id)?;
+        self.try_get_resource(id).ok()
+    }
+
+    /// Returns the resource content and mime-type by the id defined in the spine
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::{DocError, EpubDoc};
+    /// # let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// let (cover, mime) = doc.try_get_resource("portada.png").unwrap();
+    /// assert_eq!("image/png", mime);
+    ///
+    /// let missing = doc.try_get_resource("missing.png");
+    /// assert!(matches!(missing, Err(DocError::ResourceNotFound { .. })));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::ResourceNotFound`] if the id isn't in the
+    /// manifest, or the error of [`Self::try_get_resource_by_path`] if the
+    /// file can't be read.
+    pub fn try_get_resource(&mut self, id: &str) -> Result<(Vec<u8>, String), DocError> {
+        let ResourceItem { path, mime, .. } = self.resource_item(id)?;
         let path = path.clone();
         let mime = mime.clone();
-        let content = self.get_resource_by_path(&path)?;
-        Some((content, mime))
+        let content = self.try_get_resource_by_path(&path)?;
+        Ok((content, mime))
     }
 
     /// Returns the resource content by full path in the epub archive, as String
     ///
-    /// Returns [`None`] if the path doesn't exists in the epub
+    /// Returns [`None`] if the path doesn't exists in the epub, see
+    /// [`Self::try_get_resource_str_by_path`] to know the cause.
     pub fn get_resource_str_by_path<P: AsRef<Path>>(&mut self, path: P) -> Option<String> {
-        self.archive.get_entry_as_str(path).ok()
+        self.try_get_resource_str_by_path(path).ok()
+    }
+
+    /// Returns the resource content by full path in the epub archive, as String
+    ///
+    /// The content is decoded from the encoding of its byte order mark, XML
+    /// declaration or `@charset` rule, or of its `<meta>` charset if it
+    /// isn't valid UTF-8. The default is UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of [`Self::try_get_resource_by_path`] if the file
+    /// can't be read and [`ArchiveError::Utf8`](crate::archive::ArchiveError::Utf8)
+    /// if the content is decoded from UTF-8 and isn't valid.
+    pub fn try_get_resource_str_by_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<String, DocError> {
+        let content = self.try_get_resource_by_path(path)?;
+        Ok(encoding::decode_string(content).map_err(ArchiveError::from)?)
     }
 
     /// Returns the resource content and mime-type by the id defined in the spine, as String
     ///
-    /// Returns [`None`] if the id doesn't exists in the epub
+    /// Returns [`None`] if the id doesn't exists in the epub, see
+    /// [`Self::try_get_resource_str`] to know the cause.
     pub fn get_resource_str(&mut self, id: &str) -> Option<(String, String)> {
-        let ResourceItem { path, mime, .. } = self.resources.get(id)?;
+        self.try_get_resource_str(id).ok()
+    }
+
+    /// Returns the resource content and mime-type by the id defined in the spine, as String
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::ResourceNotFound`] if the id isn't in the
+    /// manifest, or the error of [`Self::try_get_resource_str_by_path`] if
+    /// the file can't be read.
+    pub fn try_get_resource_str(&mut self, id: &str) -> Result<(String, String), DocError> {
+        let ResourceItem { path, mime, .. } = self.resource_item(id)?;
         let mime = mime.clone();
         let path = path.clone();
-        let content = self.get_resource_str_by_path(path)?;
-        Some((content, mime))
+        let content = self.try_get_resource_str_by_path(path)?;
+        Ok((content, mime))
+    }
+
+    /// Returns the manifest item with the `id`
+    fn resource_item(&self, id: &str) -> Result<&ResourceItem, DocError> {
+        self.resources
+            .get(id)
+            .ok_or_else(|| DocError::ResourceNotFound { id: id.to_string() })
     }
 
     /// Returns the resource mime-type
@@ -477,16 +995,46 @@ impl<R: Read + Seek> EpubDoc<R> {
     /// The current follows the epub spine order. You can modify the current
     /// calling to `go_next`, `go_prev` or `set_current` methods.
     ///
-    /// Can return [`None`] if the epub is broken.
+    /// Can return [`None`] if the epub is broken, see [`Self::try_get_current`]
+    /// to know the cause.
     pub fn get_current(&mut self) -> Option<(Vec<u8>, String)> {
-        let current_id = self.get_current_id()?;
-        self.get_resource(&current_id)
+        self.try_get_current().ok()
     }
 
     /// See [`Self::get_current`]
     pub fn get_current_str(&mut self) -> Option<(String, String)> {
-        let current_id = self.get_current_id()?;
-        self.get_resource_str(&current_id)
+        self.try_get_current_str().ok()
+    }
+
+    /// Returns the current chapter content and mime-type
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if the spine is empty,
+    /// or the error of [`Self::try_get_resource`] for the current chapter.
+    pub fn try_get_current(&mut self) -> Result<(Vec<u8>, String), DocError> {
+        let current_id = self.current_id()?;
+        self.try_get_resource(&current_id)
+    }
+
+    /// See [`Self::try_get_current`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if the spine is empty,
+    /// or the error of [`Self::try_get_resource_str`] for the current chapter.
+    pub fn try_get_current_str(&mut self) -> Result<(String, String), DocError> {
+        let current_id = self.current_id()?;
+        self.try_get_resource_str(&current_id)
+    }
+
+    /// Returns the current chapter id, or the error if the spine is empty
+    fn current_id(&self) -> Result<String, DocError> {
+        self.get_current_id()
+            .ok_or(DocError::SpineIndexOutOfBounds {
+                index: self.current,
+                len: self.spine.len(),
+            })
     }
 
     /// Returns the current chapter data, with resource uris renamed so they
@@ -497,6 +1045,9 @@ impl<R: Read + Seek> EpubDoc<R> {
     /// for the relative path in the filesystem and that file isn't there. You should provide files
     /// with epub:// using [`Self::get_resource_by_path`]
     ///
+    /// Other urls, like the ones of a local http server, can be built with
+    /// [`Self::set_uri_resolver`].
+    ///
     /// # Examples
     ///
     /// ```
@@ -505,7 +1056,7 @@ impl<R: Read + Seek> EpubDoc<R> {
     /// let current = doc.get_current_with_epub_uris().unwrap();
     /// let text = String::from_utf8(current).unwrap();
     /// assert!(text.contains("epub://OEBPS/Images/portada.png"));
-
+    ///
     /// doc.go_next();
     /// let current = doc.get_current_with_epub_uris().unwrap();
     /// let text = String::from_utf8(current).unwrap();
@@ -515,23 +1066,153 @@ impl<R: Read + Seek> EpubDoc<R> {
     ///
     /// # Errors
     ///
-    /// Returns [`DocError::InvalidEpub`] if the epub is broken.
+    /// Returns an error if the current chapter can't be found or read, or
+    /// if it isn't a valid xhtml document.
     pub fn get_current_with_epub_uris(&mut self) -> Result<Vec<u8>, DocError> {
-        let path = self.get_current_path().ok_or(DocError::InvalidEpub)?;
-        let (current, _mime) = self.get_current().ok_or(DocError::InvalidEpub)?;
-
-        let resp = xmlutils::replace_attrs(
-            current.as_slice(),
-            |element, attr, value| match (element, attr) {
-                ("link", "href") | ("image", "href") | ("a", "href") | ("img", "src") => {
-                    build_epub_uri(&path, value)
-                }
-                _ => String::from(value),
-            },
-            &self.extra_css,
-        );
+        let current_id = self.current_id()?;
+        let ResourceItem { path, mime, .. } = self.resource_item(&current_id)?;
+        let (path, mime) = (path.clone(), mime.clone());
+        let current = self.try_get_resource_by_path(&path)?;
+        self.document_with_epub_uris(&path, &mime, &current)
+    }
+
+    /// Returns the resource content and mime-type by the id, with its
+    /// references rewritten to `epub://` uris, see
+    /// [`Self::get_resource_by_path_with_epub_uris`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::ResourceNotFound`] if the id isn't in the
+    /// manifest, or an error if the resource can't be read or parsed.
+    pub fn get_resource_with_epub_uris(&mut self, id: &str) -> Result<(Vec<u8>, String), DocError> {
+        let ResourceItem { path, mime, .. } = self.resource_item(id)?;
+        let path = path.clone();
+        let mime = mime.clone();
+        let content = self.get_resource_by_path_with_epub_uris(&path)?;
+        Ok((content, mime))
+    }
+
+    /// Returns the resource content by full path in the epub archive, with
+    /// its references rewritten to `epub://` uris.
+    ///
+    /// The xhtml and svg documents are rewritten like
+    /// [`Self::get_current_with_epub_uris`], and the `url()` and `@import`
+    /// of the stylesheets are resolved relative to the stylesheet. Other
+    /// resources are returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// # let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// let css = doc
+    ///     .get_resource_by_path_with_epub_uris("OEBPS/Styles/stylesheet.css")
+    ///     .unwrap();
+    /// assert!(!css.is_empty());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resource can't be read, or if a xhtml or svg
+    /// document isn't valid.
+    pub fn get_resource_by_path_with_epub_uris<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<Vec<u8>, DocError> {
+        let path = path.as_ref();
+        let content = self.try_get_resource_by_path(path)?;
+        let mime = self.get_resource_mime_by_path(path).unwrap_or_else(|| {
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default();
+            match extension.to_ascii_lowercase().as_str() {
+                "css" => "text/css",
+                "xhtml" | "html" | "htm" => "application/xhtml+xml",
+                "svg" => "image/svg+xml",
+                _ => "",
+            }
+            .to_string()
+        });
+
+        match mime.as_str() {
+            "application/xhtml+xml" | "text/html" | "image/svg+xml" => {
+                self.document_with_epub_uris(path, &mime, &content)
+            }
+            "text/css" => {
+                let Ok(css) = encoding::decode_string(content.clone()) else {
+                    return Ok(content);
+                };
+                let rewrite = self.uri_rewriter(path);
+                let css = encoding::declare_utf8(&css);
+                Ok(references::rewrite_css(&css, &rewrite).into_bytes())
+            }
+            _ => Ok(content),
+        }
+    }
+
+    /// Rewrites the references of the xhtml, html or svg document in
+    /// `path`, injecting the extra css.
+    ///
+    /// With the `html5` feature, the documents that aren't xml are read as
+    /// html, and so are the broken xml ones unless in strict mode.
+    fn document_with_epub_uris(
+        &self,
+        path: &Path,
+        mime: &str,
+        content: &[u8],
+    ) -> Result<Vec<u8>, DocError> {
+        let rewrite = self.uri_rewriter(path);
+        let injections = self.all_injections();
+        let replace = |syntax| {
+            xmlutils::replace_attrs(
+                content,
+                |element, attr, value| references::rewrite_attr(element, attr, value, &rewrite),
+                |css| references::rewrite_css(css, &rewrite),
+                &injections,
+                syntax,
+                &self.options.limits,
+            )
+        };
+
+        #[cfg(feature = "html5")]
+        {
+            if !crate::dom::is_xml(mime) {
+                return Ok(replace(Syntax::Html)?);
+            }
+            if self.options.mode != ParseMode::Strict {
+                return match replace(Syntax::Xml) {
+                    Ok(document) => Ok(document),
+                    // limits are never recovered from
+                    Err(e @ XMLError::LimitExceeded(_)) => Err(e.in_file(path).into()),
+                    Err(_) => Ok(replace(Syntax::Html)?),
+                };
+            }
+        }
+        #[cfg(not(feature = "html5"))]
+        let _ = mime;
+        replace(Syntax::Xml).map_err(|e| e.in_file(path).into())
+    }
+
+    /// Returns the injections followed by the extra css
+    fn all_injections(&self) -> Vec<(Injection, Placement)> {
+        let extra_css = self
+            .extra_css
+            .iter()
+            .map(|css| (Injection::Style(css.clone()), Placement::End));
+        self.injections.iter().cloned().chain(extra_css).collect()
+    }
 
-        resp.map_err(From::from)
+    /// Returns the function rewriting the references found in the archive
+    /// entry `path` with the [`UriResolver`]
+    fn uri_rewriter<'a>(&'a self, path: &Path) -> impl Fn(&str) -> String + 'a {
+        let base = ArchivePath::new(&path.to_string_lossy()).ok();
+        move |href: &str| {
+            base.as_ref().map_or_else(
+                || href.to_string(),
+                |base| self.uri_resolver.resolve(base, href),
+            )
+        }
     }
 
     /// Returns the current chapter mimetype
@@ -612,6 +1293,7 @@ impl<R: Read + Seek> EpubDoc<R> {
             false
         } else {
             self.current += 1;
+            self.current_offset = 0;
             true
         }
     }
@@ -639,6 +1321,7 @@ impl<R: Read + Seek> EpubDoc<R> {
             false
         } else {
             self.current -= 1;
+            self.current_offset = 0;
             true
         }
     }
@@ -683,12 +1366,15 @@ impl<R: Read + Seek> EpubDoc<R> {
             false
         } else {
             self.current = n;
+            self.current_offset = 0;
             true
         }
     }
 
     /// This will inject this css in every html page getted with
-    /// [`Self::get_current_with_epub_uris`]
+    /// [`Self::get_current_with_epub_uris`], in a `<style>` at the end of
+    /// the `<head>`. Use [`Self::inject`] to place it at the start or link
+    /// a stylesheet.
     ///
     /// # Examples
     ///
@@ -707,6 +1393,34 @@ impl<R: Read + Seek> EpubDoc<R> {
         self.extra_css.push(String::from(css));
     }
 
+    /// Injects an element in the `<head>` of the documents rewritten by
+    /// [`Self::get_current_with_epub_uris`] and the other `_with_epub_uris`
+    /// methods. The injections with the same `placement` keep their order,
+    /// and the [extra css](Self::add_extra_css) goes after all of them. See
+    /// the [`inject`](crate::inject) module.
+    pub fn inject(&mut self, injection: Injection, placement: Placement) {
+        self.injections.push((injection, placement));
+    }
+
+    /// Sets how [`Self::get_current_with_epub_uris`] and the other
+    /// `_with_epub_uris` methods rewrite the references, to `epub://` urls
+    /// by default. See the [`uri`](crate::uri) module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// use epub::uri::BaseUrl;
+    ///
+    /// # let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// doc.set_uri_resolver(BaseUrl::new("app://book/"));
+    /// let current = doc.get_current_with_epub_uris().unwrap();
+    /// assert!(!String::from_utf8(current).unwrap().contains("epub://"));
+    /// ```
+    pub fn set_uri_resolver(&mut self, resolver: impl UriResolver + 'static) {
+        self.uri_resolver = Arc::new(resolver);
+    }
+
     /// Function to convert a resource path to a chapter number in the spine
     /// If the resource isn't in the spine list, None will be returned
     ///
@@ -729,12 +1443,12 @@ impl<R: Read + Seek> EpubDoc<R> {
     }
 
     fn fill_resources(&mut self) -> Result<(), DocError> {
-        let container = self.archive.get_entry(&self.root_file)?;
-        let root = xmlutils::XMLReader::parse(container.as_slice())?;
+        let root_file = self.root_file.clone();
+        let root = self.parse_xml_file(&root_file)?;
         self.version = match root.borrow().get_attr("version") {
             Some(v) if v == "2.0" => EpubVersion::Version2_0,
             Some(v) if v == "3.0" => EpubVersion::Version3_0,
-            Some(v) => EpubVersion::Unknown(String::from(v)),
+            Some(v) => EpubVersion::Unknown(v),
             _ => EpubVersion::Unknown(String::from("Unknown")),
         };
         let unique_identifier_id = &root.borrow().get_attr("unique-identifier");
@@ -742,33 +1456,52 @@ impl<R: Read + Seek> EpubDoc<R> {
         // resources from manifest
         // This should be run before everything else, because other functions relies on
         // self.resources and should be filled before calling `fill_toc`
-        let manifest = root
-            .borrow()
-            .find("manifest")
-            .ok_or(DocError::InvalidEpub)?;
-        for r in &manifest.borrow().children {
-            let item = r.borrow();
-            let _ = self.insert_resource(&item);
+        let manifest = root.borrow().find("manifest");
+        if let Some(manifest) = manifest {
+            for r in &manifest.borrow().children {
+                let item = r.borrow();
+                if let Err(e) = self.insert_resource(&item) {
+                    self.recover(e.at(&root_file, Some(item.position)).into())?;
+                }
+            }
+        } else {
+            self.recover(DocError::MissingManifest {
+                path: root_file.clone(),
+            })?;
         }
 
         // items from spine
-        let spine = root.borrow().find("spine").ok_or(DocError::InvalidEpub)?;
-        for r in &spine.borrow().children {
-            let item = r.borrow();
-            let _ = self.insert_spine(&item);
-        }
+        let spine = root.borrow().find("spine");
+        if let Some(spine) = spine {
+            for r in &spine.borrow().children {
+                let item = r.borrow();
+                if let Err(e) = self.insert_spine(&item) {
+                    self.recover(e.at(&root_file, Some(item.position)).into())?;
+                }
+            }
 
-        // toc.ncx
-        if let Some(toc) = spine.borrow().get_attr("toc") {
-            let _ = self.fill_toc(&toc);
+            // toc.ncx
+            let toc = spine.borrow().get_attr("toc");
+            if let Some(toc) = toc {
+                if let Err(e) = self.fill_toc(&toc) {
+                    self.recover(e)?;
+                }
+            }
+        } else {
+            self.recover(DocError::MissingSpine {
+                path: root_file.clone(),
+            })?;
         }
 
         // metadata
-        let metadata_elem = root
-            .borrow()
-            .find("metadata")
-            .ok_or(DocError::InvalidEpub)?;
-        self.fill_metadata(&metadata_elem.borrow());
+        let metadata_elem = root.borrow().find("metadata");
+        if let Some(metadata_elem) = metadata_elem {
+            self.fill_metadata(&metadata_elem.borrow());
+        } else {
+            self.recover(DocError::MissingMetadata {
+                path: root_file.clone(),
+            })?;
+        }
 
         let identifier = if let Some(uid) = unique_identifier_id {
             // find identifier with id
@@ -784,126 +1517,74 @@ impl<R: Read + Seek> EpubDoc<R> {
         Ok(())
     }
 
-    fn fill_metadata(&mut self, elem: &xmlutils::XMLNode) {
-        // refinements are inserted here with ID as key, these are later associated to metadata
-        let mut refinements: HashMap<String, Vec<MetadataRefinement>> = HashMap::new();
-        for r in &elem.children {
-            let item = r.borrow();
-            // for each acceptable element, either push a metadata item or push a refinement
-            match (item.name.namespace_ref(), &item.name.local_name) {
-                // dcterms
-                (Some("http://purl.org/dc/elements/1.1/"), name) => {
-                    let id = item.get_attr("id");
-                    let lang = item.get_attr("lang");
-                    let property = name.clone();
-                    let value = item.text.clone().unwrap_or_default();
-
-                    let refined: Vec<MetadataRefinement> =
-                        if let EpubVersion::Version3_0 = self.version {
-                            vec![]
-                        } else {
-                            // treat it as EPUB2 dcterms, storing additional info in attributes
-                            item.attrs
-                                .iter()
-                                .filter_map(|attr| {
-                                    if let Some("http://www.idpf.org/2007/opf") =
-                                        attr.name.namespace_ref()
-                                    {
-                                        let property = attr.name.local_name.clone();
-                                        let value = attr.value.clone();
-                                        Some(MetadataRefinement {
-                                            property,
-                                            value,
-                                            lang: None,
-                                            scheme: None,
-                                        })
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect()
-                        };
-                    self.metadata.push(MetadataItem {
-                        id,
-                        property,
-                        value,
-                        lang,
-                        refined,
-                    });
-                }
+    fn fill_encryption(&mut self) -> Result<(), DocError> {
+        if !self.archive.contains(ocf::ENCRYPTION_FILE) {
+            return Ok(());
+        }
+        let root = self.parse_xml_file(Path::new(ocf::ENCRYPTION_FILE))?;
+        for item in encryption::parse_encryption(&root.borrow()) {
+            match item {
+                Ok(item) => self.encryption.push(item),
+                Err(e) => self.recover(e.into())?,
+            }
+        }
+        Ok(())
+    }
 
-                // <meta>
-                (Some("http://www.idpf.org/2007/opf"), name)
-                    if name.eq_ignore_ascii_case("meta") =>
-                {
-                    if let Some(property) = item.get_attr("property") {
-                        // EPUB3 <meta>, value in its text content
-                        let value = item.text.clone().unwrap_or_default();
-                        let lang = item.get_attr("lang");
-                        if let Some(refines) = item.get_attr("refines") {
-                            // refinement (subexpression in EPUB3 terminology)
-                            let tid = refines.strip_prefix('#').unwrap_or_else(|| &refines);
-                            let scheme = item.get_attr("scheme");
-                            let refinement = MetadataRefinement {
-                                property,
-                                value,
-                                lang,
-                                scheme,
-                            };
-                            if let Some(refs) = refinements.get_mut(tid) {
-                                refs.push(refinement);
-                            } else {
-                                refinements.insert(tid.to_string(), vec![refinement]);
-                            }
-                        } else {
-                            // primary
-                            let id = item.get_attr("id");
-                            self.metadata.push(MetadataItem {
-                                id,
-                                property,
-                                value,
-                                lang,
-                                refined: vec![],
-                            });
-                        }
-                    } else if let (Some(property), Some(value)) =
-                        (item.get_attr("name"), item.get_attr("content"))
-                    {
-                        // Legacy XHTML1.1 <meta>
-                        self.metadata.push(MetadataItem {
-                            id: None,
-                            property,
-                            value,
-                            lang: None,
-                            refined: vec![],
-                        });
-                    }
-                }
+    /// Undoes the obfuscation or the encryption of the `content` of the
+    /// resource in `path`, if it's listed in META-INF/encryption.xml. The
+    /// content is left untouched if the key isn't known.
+    #[cfg_attr(not(feature = "lcp"), allow(clippy::unnecessary_wraps))]
+    fn decode_resource(&self, path: &Path, mut content: Vec<u8>) -> Result<Vec<u8>, DocError> {
+        let Ok(path) = ArchivePath::new(&path.to_string_lossy()) else {
+            return Ok(content);
+        };
+        let Some(encrypted) = self.encryption.iter().find(|e| e.path == path) else {
+            return Ok(content);
+        };
 
-                _ => (),
+        if let Some(obfuscation) = encrypted.obfuscation() {
+            if let Some(key) = self.obfuscation_key(obfuscation) {
+                obfuscation.deobfuscate(&key, &mut content);
             }
         }
-
-        // associate refinements
-        self.metadata.iter_mut().for_each(|item| {
-            if let Some(id) = &item.id {
-                if let Some(mut refs) = refinements.remove(id) {
-                    item.refined.append(&mut refs);
+        #[cfg(feature = "lcp")]
+        if let Some(key) = &self.lcp_key {
+            if encrypted.algorithm == crate::lcp::AES256_CBC {
+                content = key.decrypt(&content)?;
+                if encrypted.compression.is_some_and(|c| c.method == 8) {
+                    content =
+                        crate::archive::inflate(path.as_str(), &content, &self.options.limits)?;
                 }
             }
-        });
+        }
+        Ok(content)
     }
 
-    // Forcibly converts separators in a filepath to unix separators to
-    // to ensure that ZipArchive's by_name method will retrieve the proper
-    // file. Failing to convert to unix-style on Windows causes the
-    // ZipArchive not to find the file.
-    fn convert_path_seps<P: AsRef<Path>>(&self, href: P) -> PathBuf {
-        let mut path = self.root_base.join(href);
-        if cfg!(windows) {
-            path = PathBuf::from(path.to_string_lossy().replace('\\', "/"));
+    /// Returns the key of the font `obfuscation`, derived from the unique
+    /// identifier, or from the first UUID identifier for the Adobe one
+    fn obfuscation_key(&self, obfuscation: Obfuscation) -> Option<Vec<u8>> {
+        let unique = self.unique_identifier.as_deref();
+        match obfuscation {
+            Obfuscation::Idpf => unique.and_then(|id| obfuscation.key(id)),
+            Obfuscation::Adobe => unique.and_then(|id| obfuscation.key(id)).or_else(|| {
+                self.metadata
+                    .iter()
+                    .filter(|m| m.property == "identifier")
+                    .find_map(|m| obfuscation.key(&m.value))
+            }),
         }
-        path
+    }
+
+    fn fill_metadata(&mut self, elem: &xmlutils::XMLNode) {
+        let metadata = parse_metadata(elem, &self.version);
+        self.metadata.extend(metadata);
+    }
+
+    /// Returns the path in the archive of a `href` found in the package
+    /// document
+    pub(crate) fn href_path(&self, href: &str) -> Result<ArchivePath, PathError> {
+        ArchivePath::new(&self.root_base.to_string_lossy())?.join(href)
     }
 
     fn insert_resource(&mut self, item: &xmlutils::XMLNode) -> Result<(), XMLError> {
@@ -917,11 +1598,16 @@ impl<R: Read + Seek> EpubDoc<R> {
             .get_attr("media-type")
             .ok_or_else(|| XMLError::AttrNotFound("media-type".into()))?;
         let properties = item.get_attr("properties");
+        let path = match self.href_path(&href) {
+            // remote resources can't be read from the archive
+            Err(PathError::External(_)) => return Ok(()),
+            path => path?,
+        };
 
         self.resources.insert(
             id,
             ResourceItem {
-                path: self.convert_path_seps(href),
+                path: path.into(),
                 mime,
                 properties,
             },
@@ -929,27 +1615,33 @@ impl<R: Read + Seek> EpubDoc<R> {
         Ok(())
     }
 
-    fn insert_spine(&mut self, item: &xmlutils::XMLNode) -> Result<(), DocError> {
+    fn insert_spine(&mut self, item: &xmlutils::XMLNode) -> Result<(), XMLError> {
         let idref = item
             .get_attr("idref")
             .ok_or_else(|| XMLError::AttrNotFound("idref".into()))?;
-        let linear = item.get_attr("linear").unwrap_or("yes".into()) == "yes";
+        let linear = item.get_attr("linear").unwrap_or_else(|| "yes".into()) == "yes";
         let properties = item.get_attr("properties");
         let id = item.get_attr("id");
         self.spine.push(SpineItem {
             idref,
             id,
-            linear,
             properties,
+            linear,
         });
         Ok(())
     }
 
     fn fill_toc(&mut self, id: &str) -> Result<(), DocError> {
-        let toc_res = self.resources.get(id).ok_or(DocError::InvalidEpub)?; // this should be turned into it's own error type, but
+        let toc_res = self
+            .resources
+            .get(id)
+            .ok_or_else(|| DocError::TocNotFound {
+                id: id.to_string(),
+                path: self.root_file.clone(),
+            })?;
 
-        let container = self.archive.get_entry(&toc_res.path)?;
-        let root = xmlutils::XMLReader::parse(container.as_slice())?;
+        let path = toc_res.path.clone();
+        let root = self.parse_xml_file(&path)?;
 
         self.toc_title = root
             .borrow()
@@ -957,7 +1649,7 @@ impl<R: Read + Seek> EpubDoc<R> {
             .and_then(|dt| {
                 dt.borrow()
                     .children
-                    .get(0)
+                    .first()
                     .and_then(|t| t.borrow().text.clone())
             })
             .unwrap_or_default();
@@ -965,16 +1657,18 @@ impl<R: Read + Seek> EpubDoc<R> {
         let mapnode = root
             .borrow()
             .find("navMap")
-            .ok_or_else(|| XMLError::AttrNotFound("navMap".into()))?;
+            .ok_or_else(|| XMLError::ElementNotFound("navMap".into()).in_file(&path))?;
 
-        self.toc.append(&mut self.get_navpoints(&mapnode.borrow()));
+        let base = ArchivePath::new(&path.to_string_lossy()).map_err(XMLError::from)?;
+        self.toc
+            .append(&mut Self::get_navpoints(&base, &mapnode.borrow()));
         self.toc.sort();
 
         Ok(())
     }
 
-    /// Recursively extract all navpoints from a node.
-    fn get_navpoints(&self, parent: &xmlutils::XMLNode) -> Vec<NavPoint> {
+    /// Recursively extract all navpoints from a node of the ncx file in `base`.
+    fn get_navpoints(base: &ArchivePath, parent: &xmlutils::XMLNode) -> Vec<NavPoint> {
         let mut navpoints = Vec::new();
 
         // TODO: parse metadata (dtb:totalPageCount, dtb:depth, dtb:maxPageNumber)
@@ -989,12 +1683,13 @@ impl<R: Read + Seek> EpubDoc<R> {
                 .and_then(|n| n.parse::<usize>().ok());
             let content = item
                 .find("content")
-                .and_then(|c| c.borrow().get_attr("src").map(|p| self.root_base.join(p)));
+                .and_then(|c| c.borrow().get_attr("src"))
+                .and_then(|src| resolve_href(base, &src));
 
             let label = item.find("navLabel").and_then(|l| {
                 l.borrow()
                     .children
-                    .get(0)
+                    .first()
                     .and_then(|t| t.borrow().text.clone())
             });
 
@@ -1002,7 +1697,7 @@ impl<R: Read + Seek> EpubDoc<R> {
                 let navpoint = NavPoint {
                     label: label_text.clone(),
                     content: content_path.clone(),
-                    children: self.get_navpoints(&item),
+                    children: Self::get_navpoints(base, &item),
                     play_order: order,
                 };
                 navpoints.push(navpoint);
@@ -1014,50 +1709,149 @@ impl<R: Read + Seek> EpubDoc<R> {
     }
 }
 
-fn get_root_file(container: &[u8]) -> Result<PathBuf, DocError> {
-    let root = xmlutils::XMLReader::parse(container)?;
+fn get_root_file(container: &[u8], limits: &Limits) -> Result<ArchivePath, DocError> {
+    let path = ocf::CONTAINER_FILE;
+    let root = xmlutils::XMLReader::parse(container, false, limits).map_err(|e| e.in_file(path))?;
     let el = root.borrow();
     let element = el
         .find("rootfile")
-        .ok_or_else(|| XMLError::AttrNotFound("rootfile".into()))?;
+        .ok_or_else(|| XMLError::ElementNotFound("rootfile".into()).in_file(path))?;
     let el2 = element.borrow();
 
     let attr = el2
         .get_attr("full-path")
-        .ok_or_else(|| XMLError::AttrNotFound("full-path".into()))?;
+        .ok_or_else(|| XMLError::AttrNotFound("full-path".into()).at(path, Some(el2.position)))?;
 
-    Ok(PathBuf::from(attr))
+    ArchivePath::root()
+        .join(&attr)
+        .map_err(|e| XMLError::from(e).at(path, Some(el2.position)).into())
 }
 
-fn build_epub_uri<P: AsRef<Path>>(path: P, append: &str) -> String {
-    // allowing external links
-    if append.starts_with("http") {
-        return String::from(append);
-    }
-
-    let path = path.as_ref();
-    let mut cpath = path.to_path_buf();
+/// Reads the metadata items of the `<metadata>` element of the package
+/// document, or of `META-INF/metadata.xml`, with their refinements
+pub(crate) fn parse_metadata(elem: &xmlutils::XMLNode, version: &EpubVersion) -> Vec<MetadataItem> {
+    let mut metadata = vec![];
+    // refinements are inserted here with ID as key, these are later associated to metadata
+    let mut refinements: HashMap<String, Vec<MetadataRefinement>> = HashMap::new();
+    for r in &elem.children {
+        let item = r.borrow();
+        // for each acceptable element, either push a metadata item or push a refinement
+        match (item.name.namespace_ref(), &item.name.local_name) {
+            // dcterms
+            (Some("http://purl.org/dc/elements/1.1/"), name) => {
+                let id = item.get_attr("id");
+                let lang = item.get_attr("lang");
+                let property = name.clone();
+                let value = item.text.clone().unwrap_or_default();
+
+                let refined: Vec<MetadataRefinement> = if *version == EpubVersion::Version3_0 {
+                    vec![]
+                } else {
+                    // treat it as EPUB2 dcterms, storing additional info in attributes
+                    item.attrs
+                        .iter()
+                        .filter_map(|attr| {
+                            if attr.name.namespace_ref() == Some("http://www.idpf.org/2007/opf") {
+                                let property = attr.name.local_name.clone();
+                                let value = attr.value.clone();
+                                Some(MetadataRefinement {
+                                    property,
+                                    value,
+                                    lang: None,
+                                    scheme: None,
+                                })
+                            } else {
+                                None
+                            }
+                        })
+                        .collect()
+                };
+                metadata.push(MetadataItem {
+                    id,
+                    property,
+                    value,
+                    lang,
+                    refined,
+                });
+            }
 
-    // current file base dir
-    cpath.pop();
-    for p in Path::new(append).components() {
-        match p {
-            Component::ParentDir => {
-                cpath.pop();
+            // <meta>
+            (Some("http://www.idpf.org/2007/opf" | "http://www.idpf.org/2013/metadata"), name)
+                if name.eq_ignore_ascii_case("meta") =>
+            {
+                if let Some(property) = item.get_attr("property") {
+                    // EPUB3 <meta>, value in its text content
+                    let value = item.text.clone().unwrap_or_default();
+                    let lang = item.get_attr("lang");
+                    if let Some(refines) = item.get_attr("refines") {
+                        // refinement (subexpression in EPUB3 terminology)
+                        let tid = refines.strip_prefix('#').unwrap_or_else(|| &refines);
+                        let scheme = item.get_attr("scheme");
+                        let refinement = MetadataRefinement {
+                            property,
+                            value,
+                            lang,
+                            scheme,
+                        };
+                        if let Some(refs) = refinements.get_mut(tid) {
+                            refs.push(refinement);
+                        } else {
+                            refinements.insert(tid.to_string(), vec![refinement]);
+                        }
+                    } else {
+                        // primary
+                        let id = item.get_attr("id");
+                        metadata.push(MetadataItem {
+                            id,
+                            property,
+                            value,
+                            lang,
+                            refined: vec![],
+                        });
+                    }
+                } else if let (Some(property), Some(value)) =
+                    (item.get_attr("name"), item.get_attr("content"))
+                {
+                    // Legacy XHTML1.1 <meta>
+                    metadata.push(MetadataItem {
+                        id: None,
+                        property,
+                        value,
+                        lang: None,
+                        refined: vec![],
+                    });
+                }
             }
-            Component::Normal(s) => {
-                cpath.push(s);
+
+            _ => (),
+        }
+    }
+
+    // associate refinements
+    for item in &mut metadata {
+        if let Some(id) = &item.id {
+            if let Some(mut refs) = refinements.remove(id) {
+                item.refined.append(&mut refs);
             }
-            _ => {}
-        };
+        }
     }
+    metadata
+}
 
-    // If on Windows, replace all Windows path separators with Unix path separators
-    let path = if cfg!(windows) {
-        cpath.to_string_lossy().replace('\\', "/")
+/// Resolves the `href` found in the file `base` to its path in the archive,
+/// keeping the fragment if there's one
+fn resolve_href(base: &ArchivePath, href: &str) -> Option<PathBuf> {
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let path = if path.is_empty() {
+        base.clone()
     } else {
-        cpath.to_string_lossy().to_string()
+        base.resolve(path).ok()?
     };
-
-    format!("epub://{}", path)
+    Some(match fragment {
+        Some(fragment) => PathBuf::from(format!("{}#{}", path, fragment)),
+        None => path.into(),
+    })
 }
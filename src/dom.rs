@@ -0,0 +1,160 @@
+//! Owned tree of a xhtml document, keeping every text node in order.
+//!
+//! [`XMLNode`](crate::xmlutils::XMLNode) is enough for the package files,
+//! but the content documents need the text interleaved with the inline
+//! elements to extract, search or locate it.
+
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent;
+
+use crate::encoding;
+use crate::limits::Limits;
+use crate::xmlutils::{self, XMLError};
+
+/// Namespace of the `epub:` attributes
+pub const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Element {
+    pub name: OwnedName,
+    pub attrs: Vec<OwnedAttribute>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Returns the lowercase local name of the element
+    pub fn name(&self) -> String {
+        self.name.local_name.to_ascii_lowercase()
+    }
+
+    /// Returns the value of the attribute `name` without namespace
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|a| a.name.namespace.is_none() && a.name.local_name == name)
+            .map(|a| a.value.as_str())
+    }
+
+    /// Returns the value of the attribute `name` in the `namespace`
+    pub fn attr_ns(&self, namespace: &str, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|a| a.name.namespace_ref() == Some(namespace) && a.name.local_name == name)
+            .map(|a| a.value.as_str())
+    }
+
+    /// Returns the `epub:type` values of the element
+    pub fn epub_types(&self) -> impl Iterator<Item = &str> {
+        self.attr_ns(OPS_NAMESPACE, "type")
+            .unwrap_or_default()
+            .split_whitespace()
+    }
+}
+
+/// Parses the content document `content` of the `media_type`.
+///
+/// With the `html5` feature, the documents that aren't xml, like the
+/// `text/html` ones, are parsed as HTML5, and so are the broken xml ones
+/// when not `strict`. Otherwise see [`parse`].
+pub fn parse_document(
+    content: &[u8],
+    media_type: &str,
+    strict: bool,
+    limits: &Limits,
+) -> Result<Element, XMLError> {
+    #[cfg(feature = "html5")]
+    {
+        if !is_xml(media_type) {
+            return crate::html::parse(content, limits);
+        }
+        if !strict {
+            return match parse(content, true, limits) {
+                Err(e @ XMLError::LimitExceeded(_)) => Err(e),
+                Err(_) => crate::html::parse(content, limits),
+                root => root,
+            };
+        }
+    }
+    #[cfg(not(feature = "html5"))]
+    let _ = media_type;
+    parse(content, strict, limits)
+}
+
+/// Returns `true` if the `media_type` is a xml format, like
+/// `application/xhtml+xml` or `image/svg+xml`
+#[cfg(feature = "html5")]
+pub fn is_xml(media_type: &str) -> bool {
+    let media_type = media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    media_type.is_empty() || media_type.ends_with("+xml") || media_type.ends_with("/xml")
+}
+
+/// Parses the document `content`. If not `strict`, the elements read
+/// before a syntax error are kept.
+pub fn parse(content: &[u8], strict: bool, limits: &Limits) -> Result<Element, XMLError> {
+    let (content, _) = encoding::decode(content);
+    let reader = xmlutils::parser_config(content.as_bytes())
+        .max_entity_expansion_length(limits.max_entity_expansion)
+        .create_reader(content.as_bytes());
+
+    let mut parents: Vec<Element> = vec![];
+    let mut root: Option<Element> = None;
+    for event in reader {
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                limits.check_element(&name.local_name, parents.len(), attributes.len())?;
+                parents.push(Element {
+                    name,
+                    attrs: attributes,
+                    children: vec![],
+                });
+            }
+            Ok(XmlEvent::EndElement { .. }) => {
+                let Some(element) = parents.pop() else {
+                    continue;
+                };
+                match parents.last_mut() {
+                    Some(parent) => parent.children.push(Node::Element(element)),
+                    None => root = Some(element),
+                }
+            }
+            Ok(XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text)) => {
+                limits.check_text(&text)?;
+                if let Some(parent) = parents.last_mut() {
+                    parent.children.push(Node::Text(text));
+                }
+            }
+            Err(e) => {
+                limits.check_reader_error(&e)?;
+                if strict {
+                    return Err(e.into());
+                }
+                break;
+            }
+            Ok(XmlEvent::EndDocument) => break,
+            _ => {}
+        }
+    }
+
+    // closing the elements left open by a syntax error
+    while let Some(element) = parents.pop() {
+        match parents.last_mut() {
+            Some(parent) => parent.children.push(Node::Element(element)),
+            None => root = Some(element),
+        }
+    }
+    root.ok_or(XMLError::NoElements)
+}
@@ -0,0 +1,171 @@
+//! Detection of the character encoding of the resources, decoded to UTF-8
+//! before being parsed or returned as text.
+//!
+//! The encoding is read from the byte order mark, the first bytes of the
+//! UTF-16 documents without one, the XML declaration or the `@charset` rule
+//! of the stylesheets. The `<meta>` charset of the html documents is only
+//! used if the content isn't valid UTF-8, as some books keep the one of
+//! their source after being converted. The default is UTF-8.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::string::FromUtf8Error;
+
+use encoding_rs::{Encoding, REPLACEMENT, UTF_16BE, UTF_16LE, UTF_8};
+
+use crate::markup::{Scanner, TagKind};
+
+/// The bytes read to find the `<meta>` charset, like the browsers do
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Returns the encoding of the `content` and the length of its byte order
+/// mark
+pub fn detect(content: &[u8]) -> (&'static Encoding, usize) {
+    if let Some(found) = Encoding::for_bom(content) {
+        return found;
+    }
+    // ascii characters without a byte order mark
+    match content {
+        [a, 0, b, 0, ..] if *a != 0 && *b != 0 => return (UTF_16LE, 0),
+        [0, a, 0, b, ..] if *a != 0 && *b != 0 => return (UTF_16BE, 0),
+        _ => {}
+    }
+    let declared = declared_label(content)
+        .and_then(|span| label_encoding(&content[span]))
+        .or_else(|| {
+            std::str::from_utf8(content)
+                .is_err()
+                .then(|| meta_charset(content))
+                .flatten()
+        });
+    (declared.unwrap_or(UTF_8), 0)
+}
+
+/// Returns the `content` decoded to UTF-8, with the malformed sequences
+/// replaced, and the encoding it was decoded from
+pub fn decode(content: &[u8]) -> (Cow<'_, str>, &'static Encoding) {
+    let (encoding, bom) = detect(content);
+    let (text, _) = encoding.decode_without_bom_handling(&content[bom..]);
+    (text, encoding)
+}
+
+/// Returns the `content` decoded to UTF-8, failing if it's malformed UTF-8
+/// rather than replacing the invalid sequences. The other encodings are
+/// decoded as [`decode`] does.
+pub fn decode_string(mut content: Vec<u8>) -> Result<String, FromUtf8Error> {
+    match detect(&content) {
+        (encoding, bom) if encoding == UTF_8 => {
+            content.drain(..bom);
+            String::from_utf8(content)
+        }
+        (encoding, bom) => Ok(encoding
+            .decode_without_bom_handling(&content[bom..])
+            .0
+            .into_owned()),
+    }
+}
+
+/// Returns the decoded `text` with the encoding of its XML declaration or
+/// `@charset` rule changed to UTF-8
+pub fn declare_utf8(text: &str) -> Cow<'_, str> {
+    match declared_label(text.as_bytes()) {
+        Some(span) if label_encoding(&text.as_bytes()[span.clone()]) != Some(UTF_8) => {
+            let mut text = text.to_string();
+            text.replace_range(span, "UTF-8");
+            Cow::Owned(text)
+        }
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// Returns the `value` of the attribute `attr` of a `<meta>` with its
+/// charset changed to UTF-8
+pub fn meta_utf8(attr: &str, mut value: String) -> String {
+    if attr.eq_ignore_ascii_case("charset") {
+        return "utf-8".to_string();
+    }
+    if attr.eq_ignore_ascii_case("content") {
+        if let Some(span) = content_charset(&value) {
+            value.replace_range(span, "utf-8");
+        }
+    }
+    value
+}
+
+/// Returns the encoding of the `label`, ignoring the ones that can't be
+/// declared in an ascii compatible document
+fn label_encoding(label: &[u8]) -> Option<&'static Encoding> {
+    Encoding::for_label(label).filter(|encoding| {
+        *encoding != UTF_16LE && *encoding != UTF_16BE && *encoding != REPLACEMENT
+    })
+}
+
+/// Returns the span of the encoding of the XML declaration, or of the
+/// `@charset` rule of a stylesheet
+fn declared_label(content: &[u8]) -> Option<Range<usize>> {
+    if let Some(rest) = content.strip_prefix(b"@charset \"") {
+        let start = content.len() - rest.len();
+        return Some(start..start + rest.iter().position(|b| *b == b'"')?);
+    }
+    if !content.starts_with(b"<?xml") {
+        return None;
+    }
+    let declaration = &content[..content.windows(2).position(|w| w == b"?>")?];
+    let skip_whitespace = |i: usize| {
+        i + declaration[i..]
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count()
+    };
+    let mut start = skip_whitespace(declaration.windows(8).position(|w| w == b"encoding")? + 8);
+    if declaration.get(start) != Some(&b'=') {
+        return None;
+    }
+    start = skip_whitespace(start + 1);
+    let quote = *declaration
+        .get(start)
+        .filter(|q| matches!(q, b'"' | b'\''))?;
+    start += 1;
+    let end = start + declaration[start..].iter().position(|b| *b == quote)?;
+    Some(start..end)
+}
+
+/// Returns the encoding of the first `<meta>` declaring one, with its
+/// `charset` or the `content` of a `http-equiv="content-type"`
+fn meta_charset(content: &[u8]) -> Option<&'static Encoding> {
+    let prefix = String::from_utf8_lossy(&content[..content.len().min(PRESCAN_LENGTH)]);
+    Scanner::new(&prefix, true).find_map(|tag| {
+        let TagKind::Start { attrs, .. } = &tag.kind else {
+            return None;
+        };
+        if !tag.local_name().eq_ignore_ascii_case("meta") {
+            return None;
+        }
+        let value = |name: &str| {
+            attrs
+                .iter()
+                .find(|attr| attr.name.eq_ignore_ascii_case(name))
+                .map(|attr| &prefix[attr.value.clone()])
+        };
+        let label = value("charset").or_else(|| {
+            value("http-equiv").filter(|v| v.trim().eq_ignore_ascii_case("content-type"))?;
+            let content = value("content")?;
+            Some(&content[content_charset(content)?])
+        })?;
+        label_encoding(label.trim().as_bytes())
+    })
+}
+
+/// Returns the span of the charset in the `content` of a `<meta>`, like
+/// `text/html; charset=iso-8859-1`
+fn content_charset(content: &str) -> Option<Range<usize>> {
+    let start = content.to_ascii_lowercase().find("charset")? + "charset".len();
+    let rest = content[start..].trim_start();
+    let rest = rest.strip_prefix('=')?.trim_start();
+    let rest = rest.trim_start_matches(['"', '\'']);
+    let start = content.len() - rest.len();
+    let end = rest
+        .find(|c: char| c.is_ascii_whitespace() || matches!(c, ';' | '"' | '\''))
+        .unwrap_or(rest.len());
+    Some(start..start + end).filter(|span| !span.is_empty())
+}
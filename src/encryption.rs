@@ -0,0 +1,328 @@
+//! Encrypted and obfuscated resources of the epub.
+//!
+//! The resources listed in `META-INF/encryption.xml` can't be used as they
+//! are stored in the archive. Fonts obfuscated with the IDPF or the Adobe
+//! algorithm are de-obfuscated transparently by
+//! [`EpubDoc::get_resource`](crate::doc::EpubDoc::get_resource), and
+//! [`Obfuscation::obfuscate`] does the inverse operation to write them.
+//!
+//! Any other encryption means the book is DRM protected, the scheme can be
+//! found with [`EpubDoc::drm_report`](crate::doc::EpubDoc::drm_report).
+
+use sha1::{Digest, Sha1};
+
+use crate::archive::ArchivePath;
+use crate::ocf;
+use crate::xmlutils::{XMLError, XMLNode};
+
+/// Algorithm uri of the IDPF font obfuscation
+pub const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+
+/// Algorithm uri of the Adobe font obfuscation
+pub const ADOBE_OBFUSCATION: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+const ADEPT_NAMESPACE: &str = "http://ns.adobe.com/adept";
+const FAIRPLAY_ALGORITHM: &str = "http://itunes.apple.com/dataenc";
+
+/// A resource listed in `META-INF/encryption.xml`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedData {
+    /// Path of the resource in the archive
+    pub path: ArchivePath,
+    /// Uri of the algorithm used to encrypt the resource
+    pub algorithm: String,
+    /// Name of the key used, from `ds:KeyInfo/ds:KeyName`
+    pub key_name: Option<String>,
+    /// Uri of the key used, from `ds:KeyInfo/ds:RetrievalMethod`
+    pub retrieval_method: Option<String>,
+    /// Compression applied before the encryption, if any
+    pub compression: Option<Compression>,
+}
+
+/// The `Compression` encryption property of an encrypted resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    /// The compression method, `0` for stored and `8` for deflate
+    pub method: u32,
+    /// Size of the resource before the compression
+    pub original_length: Option<u64>,
+}
+
+impl EncryptedData {
+    /// Returns the font obfuscation used for this resource, or [`None`] if
+    /// it's encrypted with any other algorithm
+    #[must_use]
+    pub fn obfuscation(&self) -> Option<Obfuscation> {
+        Obfuscation::from_algorithm(&self.algorithm)
+    }
+}
+
+/// Font obfuscation algorithms
+///
+/// # Examples
+///
+/// ```
+/// use epub::encryption::Obfuscation;
+///
+/// let identifier = "urn:uuid:0f4a5d8e-3c0e-4b5b-9a59-2f8e7b2d1c3a";
+/// let font = b"OTTO font data".to_vec();
+///
+/// let key = Obfuscation::Idpf.key(identifier).unwrap();
+/// let mut data = font.clone();
+/// Obfuscation::Idpf.obfuscate(&key, &mut data);
+/// assert_ne!(data, font);
+/// Obfuscation::Idpf.deobfuscate(&key, &mut data);
+/// assert_eq!(data, font);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Obfuscation {
+    /// <http://www.idpf.org/2008/embedding>, keyed with the SHA-1 of the
+    /// unique identifier
+    Idpf,
+    /// <http://ns.adobe.com/pdf/enc#RC>, keyed with the bytes of the book
+    /// UUID
+    Adobe,
+}
+
+impl Obfuscation {
+    /// Returns the obfuscation for the algorithm `uri`
+    #[must_use]
+    pub fn from_algorithm(uri: &str) -> Option<Self> {
+        match uri {
+            IDPF_OBFUSCATION => Some(Self::Idpf),
+            ADOBE_OBFUSCATION => Some(Self::Adobe),
+            _ => None,
+        }
+    }
+
+    /// Returns the algorithm uri to declare in `META-INF/encryption.xml`
+    #[must_use]
+    pub const fn algorithm(self) -> &'static str {
+        match self {
+            Self::Idpf => IDPF_OBFUSCATION,
+            Self::Adobe => ADOBE_OBFUSCATION,
+        }
+    }
+
+    /// Number of bytes obfuscated at the start of the resource
+    #[must_use]
+    pub const fn header_len(self) -> usize {
+        match self {
+            Self::Idpf => 1040,
+            Self::Adobe => 1024,
+        }
+    }
+
+    /// Returns the key derived from the book `identifier`.
+    ///
+    /// For [`Self::Idpf`] this is the SHA-1 of the unique identifier with
+    /// the whitespace removed. For [`Self::Adobe`] the identifier must be
+    /// a UUID, `urn:uuid:` prefixed or not, otherwise [`None`] is returned.
+    #[must_use]
+    pub fn key(self, identifier: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Idpf => {
+                let identifier: String = identifier
+                    .chars()
+                    .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+                    .collect();
+                Some(Sha1::digest(identifier.as_bytes()).to_vec())
+            }
+            Self::Adobe => {
+                let uuid = identifier.trim();
+                let uuid = uuid.strip_prefix("urn:uuid:").unwrap_or(uuid);
+                let hex: Vec<u8> = uuid.bytes().filter(|b| *b != b'-').collect();
+                if hex.len() != 32 {
+                    return None;
+                }
+                hex.chunks(2)
+                    .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                    .collect()
+            }
+        }
+    }
+
+    /// De-obfuscates the resource `data` in place with the `key`
+    pub fn deobfuscate(self, key: &[u8], data: &mut [u8]) {
+        if key.is_empty() {
+            return;
+        }
+        let len = data.len().min(self.header_len());
+        for (byte, k) in data[..len].iter_mut().zip(key.iter().cycle()) {
+            *byte ^= k;
+        }
+    }
+
+    /// Obfuscates the resource `data` in place with the `key`, the inverse
+    /// of [`Self::deobfuscate`]
+    pub fn obfuscate(self, key: &[u8], data: &mut [u8]) {
+        // xor is its own inverse
+        self.deobfuscate(key, data);
+    }
+}
+
+/// Reads the resources listed in the parsed `META-INF/encryption.xml`. The
+/// items that can't be read are returned as errors so they can be
+/// reported.
+pub(crate) fn parse_encryption(root: &XMLNode) -> Vec<Result<EncryptedData, XMLError>> {
+    root.children
+        .iter()
+        .filter(|r| r.borrow().name.local_name == "EncryptedData")
+        .map(|r| {
+            let item = r.borrow();
+            encrypted_data(&item).map_err(|e| e.at(ocf::ENCRYPTION_FILE, Some(item.position)))
+        })
+        .collect()
+}
+
+fn encrypted_data(item: &XMLNode) -> Result<EncryptedData, XMLError> {
+    let algorithm = item
+        .find("EncryptionMethod")
+        .ok_or_else(|| XMLError::ElementNotFound("EncryptionMethod".into()))?
+        .borrow()
+        .get_attr("Algorithm")
+        .ok_or_else(|| XMLError::AttrNotFound("Algorithm".into()))?;
+    let uri = item
+        .find("CipherReference")
+        .ok_or_else(|| XMLError::ElementNotFound("CipherReference".into()))?
+        .borrow()
+        .get_attr("URI")
+        .ok_or_else(|| XMLError::AttrNotFound("URI".into()))?;
+    let key_name = item
+        .find("KeyName")
+        .and_then(|k| k.borrow().text.as_ref().map(|t| t.trim().to_string()));
+    let retrieval_method = item
+        .find("RetrievalMethod")
+        .and_then(|r| r.borrow().get_attr("URI"));
+    let compression = item.find("Compression").and_then(|c| {
+        let c = c.borrow();
+        Some(Compression {
+            method: c.get_attr("Method")?.parse().ok()?,
+            original_length: c.get_attr("OriginalLength").and_then(|l| l.parse().ok()),
+        })
+    });
+
+    Ok(EncryptedData {
+        path: ArchivePath::root().join(&uri)?,
+        algorithm,
+        key_name,
+        retrieval_method,
+        compression,
+    })
+}
+
+/// The protection scheme of an epub, see [`DrmReport`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DrmScheme {
+    /// Nothing is encrypted
+    None,
+    /// Only fonts are obfuscated, the book can be read
+    FontObfuscationOnly,
+    /// Adobe Digital Editions DRM
+    AdobeAdept,
+    /// Readium LCP DRM
+    ReadiumLcp,
+    /// Apple Books DRM
+    AppleFairPlay,
+    /// Some resources are encrypted with an unknown scheme
+    Unknown,
+}
+
+impl DrmScheme {
+    /// Returns `true` if the book can't be read without decrypting it
+    #[must_use]
+    pub const fn is_protected(self) -> bool {
+        !matches!(self, Self::None | Self::FontObfuscationOnly)
+    }
+}
+
+impl std::fmt::Display for DrmScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "No DRM",
+            Self::FontObfuscationOnly => "Font obfuscation only",
+            Self::AdobeAdept => "Adobe ADEPT DRM",
+            Self::ReadiumLcp => "Readium LCP DRM",
+            Self::AppleFairPlay => "Apple FairPlay DRM",
+            Self::Unknown => "Unknown DRM",
+        })
+    }
+}
+
+/// What's encrypted in an epub and how, see
+/// [`EpubDoc::drm_report`](crate::doc::EpubDoc::drm_report)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrmReport {
+    /// The protection scheme detected
+    pub scheme: DrmScheme,
+    /// The resources listed in `META-INF/encryption.xml`
+    pub resources: Vec<EncryptedData>,
+    /// `true` if the epub has a `META-INF/rights.xml` file
+    pub has_rights: bool,
+    /// `true` if the epub has a `META-INF/signatures.xml` file
+    pub has_signatures: bool,
+}
+
+impl DrmReport {
+    /// Returns `true` if the book can't be read without decrypting it
+    #[must_use]
+    pub const fn is_protected(&self) -> bool {
+        self.scheme.is_protected()
+    }
+
+    /// Returns the resources encrypted with something other than font
+    /// obfuscation
+    pub fn encrypted(&self) -> impl Iterator<Item = &EncryptedData> {
+        self.resources.iter().filter(|r| r.obfuscation().is_none())
+    }
+}
+
+impl std::fmt::Display for DrmReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encrypted = self.encrypted().count();
+        if encrypted == 0 {
+            write!(f, "{}", self.scheme)
+        } else {
+            write!(f, "{} ({} encrypted resources)", self.scheme, encrypted)
+        }
+    }
+}
+
+/// What's found in the archive to classify the protection scheme
+pub(crate) struct DrmEvidence<'a> {
+    pub resources: &'a [EncryptedData],
+    pub rights: Option<&'a str>,
+    pub lcp_license: bool,
+    pub fairplay: bool,
+}
+
+/// Classifies the protection scheme from the `evidence` found in the epub
+pub(crate) fn classify(evidence: &DrmEvidence<'_>) -> DrmScheme {
+    let encrypted: Vec<&EncryptedData> = evidence
+        .resources
+        .iter()
+        .filter(|r| r.obfuscation().is_none())
+        .collect();
+    let lcp_key = encrypted.iter().any(|r| {
+        r.retrieval_method
+            .as_deref()
+            .is_some_and(|uri| uri.contains("license.lcpl"))
+    });
+    let adept = evidence
+        .rights
+        .is_some_and(|rights| rights.contains(ADEPT_NAMESPACE));
+
+    if evidence.lcp_license || lcp_key {
+        DrmScheme::ReadiumLcp
+    } else if adept {
+        DrmScheme::AdobeAdept
+    } else if evidence.fairplay || encrypted.iter().any(|r| r.algorithm == FAIRPLAY_ALGORITHM) {
+        DrmScheme::AppleFairPlay
+    } else if !encrypted.is_empty() {
+        DrmScheme::Unknown
+    } else if evidence.resources.is_empty() {
+        DrmScheme::None
+    } else {
+        DrmScheme::FontObfuscationOnly
+    }
+}
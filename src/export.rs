@@ -0,0 +1,649 @@
+//! Export of the book to a single self-contained html document, to print
+//! it or to send it by email.
+//!
+//! The chapters of the linear spine are concatenated in order, each one in
+//! a `<section>`, after the table of contents rendered as a `<nav>`. The
+//! links to the chapters become links inside the document, and the ids are
+//! renamed when a previous chapter already has them. The stylesheets of the
+//! chapters are merged in the `<head>`, and the other resources, like the
+//! images and the fonts, are inlined as `data:` urls or written to a folder,
+//! see [`AssetMode`]. The scripts, in the html and svg elements, the event
+//! handlers and the `javascript:` links are left out.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::export::{AssetMode, HtmlOptions};
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let export = doc.export_html().unwrap();
+//! assert!(export.html.starts_with("<!DOCTYPE html>"));
+//! assert!(export.html.contains(r#"src="data:image/png;base64,"#));
+//!
+//! let options = HtmlOptions {
+//!     assets: AssetMode::Folder("book_files".into()),
+//!     ..HtmlOptions::default()
+//! };
+//! let export = doc.export_html_with(&options).unwrap();
+//! assert!(export.html.contains(r#"src="book_files/OEBPS/Images/portada.png""#));
+//! assert_eq!(export.assets[0].path, "book_files/OEBPS/Images/portada.png");
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use xml::namespace::NS_XML_URI;
+
+use crate::archive::ArchivePath;
+use crate::doc::{skip_broken, DocError, EpubDoc, NavPoint};
+use crate::dom::{Element, Node};
+use crate::encoding;
+use crate::markup::{escape_attr, escape_text};
+use crate::references::{self, XHTML_NAMESPACE};
+use crate::uri::{archive_reference, encode_path};
+
+/// The html elements without content or end tag
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// The elements left out of the export, in every namespace
+const DROPPED: &[&str] = &["base", "script"];
+
+/// The id of the table of contents
+const TOC_ID: &str = "toc";
+
+/// How the resources referenced by an export, like the images, are written
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AssetMode {
+    /// Inlined in the document as `data:` urls
+    #[default]
+    Inline,
+    /// Returned as [`Asset`]s to write in this folder, relative to the
+    /// exported document, with their path in the archive
+    Folder(String),
+}
+
+/// A resource referenced by an export, to write next to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Asset {
+    /// The path of the file, relative to the exported document
+    pub path: String,
+    pub mime: String,
+    pub content: Vec<u8>,
+}
+
+/// The options of [`EpubDoc::export_html_with`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    pub assets: AssetMode,
+    /// Leaves the table of contents out
+    pub skip_toc: bool,
+    /// Exports the spine items with `linear="no"` too, in the spine order
+    pub include_non_linear: bool,
+}
+
+/// A book exported to html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HtmlExport {
+    pub html: String,
+    /// The resources to write in the folder of [`AssetMode::Folder`], in
+    /// the order they are referenced. Empty if they are inlined.
+    pub assets: Vec<Asset>,
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Exports the book to a single html document, see
+    /// [`Self::export_html_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn export_html(&mut self) -> Result<HtmlExport, DocError> {
+        self.export_html_with(&HtmlOptions::default())
+    }
+
+    /// Exports the chapters of the linear spine to a single html document,
+    /// with the table of contents, their styles and the resources they
+    /// reference, see the [module](crate::export) documentation. The
+    /// chapters that can't be read or parsed are left out, the table of
+    /// contents doesn't link them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn export_html_with(&mut self, options: &HtmlOptions) -> Result<HtmlExport, DocError> {
+        let (chapters, spine) = self.export_chapters(options.include_non_linear)?;
+        Ok(Exporter::new(self, options, &chapters, spine).export(&chapters))
+    }
+
+    /// Returns the chapters of the spine to export, in order, skipping the
+    /// broken ones, and the paths of all the spine items
+    pub(crate) fn export_chapters(
+        &mut self,
+        include_non_linear: bool,
+    ) -> Result<(Vec<Chapter>, HashSet<ArchivePath>), DocError> {
+        let mut chapters = vec![];
+        let mut spine = HashSet::new();
+        for index in 0..self.spine.len() {
+            let path = self.spine_path(index);
+            spine.extend(path.clone());
+            if !self.spine[index].linear && !include_non_linear {
+                continue;
+            }
+            let Some(root) = skip_broken(self.chapter_dom(index))? else {
+                continue;
+            };
+            if let Some(path) = path {
+                chapters.push(Chapter { index, path, root });
+            }
+        }
+        Ok((chapters, spine))
+    }
+
+    /// Returns the path of the spine item `index`
+    pub(crate) fn spine_path(&self, index: usize) -> Option<ArchivePath> {
+        let item = self.resources.get(&self.spine.get(index)?.idref)?;
+        ArchivePath::new(&item.path.to_string_lossy()).ok()
+    }
+}
+
+/// A chapter to export, with its index in the spine
+pub(crate) struct Chapter {
+    pub index: usize,
+    pub path: ArchivePath,
+    pub root: Element,
+}
+
+impl Chapter {
+    /// Returns the child element `name` of the root
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.root.children.iter().find_map(|node| match node {
+            Node::Element(e) if e.name() == name => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn body(&self) -> &Element {
+        self.child("body").unwrap_or(&self.root)
+    }
+}
+
+/// The resources referenced by an export, read once
+pub(crate) struct Assets {
+    mode: AssetMode,
+    /// The urls of the resources, or [`None`] if they can't be read
+    urls: HashMap<ArchivePath, Option<String>>,
+    /// The resources to write in the folder of [`AssetMode::Folder`]
+    pub files: Vec<Asset>,
+}
+
+impl Assets {
+    pub fn new(mode: AssetMode) -> Self {
+        Self {
+            mode,
+            urls: HashMap::new(),
+            files: vec![],
+        }
+    }
+
+    /// Returns the url of the resource `path`, reading it the first time
+    pub fn url<R: Read + Seek>(
+        &mut self,
+        doc: &mut EpubDoc<R>,
+        path: &ArchivePath,
+    ) -> Option<String> {
+        if let Some(url) = self.urls.get(path) {
+            return url.clone();
+        }
+        let url = self.read(doc, path);
+        self.urls.insert(path.clone(), url.clone());
+        url
+    }
+
+    fn read<R: Read + Seek>(&mut self, doc: &mut EpubDoc<R>, path: &ArchivePath) -> Option<String> {
+        let content = doc.try_get_resource_by_path(path).ok()?;
+        let mime = doc
+            .get_resource_mime_by_path(path)
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        match &self.mode {
+            AssetMode::Inline => Some(format!("data:{};base64,{}", mime, STANDARD.encode(content))),
+            AssetMode::Folder(folder) => {
+                let folder = folder.trim_end_matches('/');
+                let (file, url) = if folder.is_empty() {
+                    (path.to_string(), encode_path(path))
+                } else {
+                    (
+                        format!("{}/{}", folder, path),
+                        format!("{}/{}", folder, encode_path(path)),
+                    )
+                };
+                self.files.push(Asset {
+                    path: file,
+                    mime,
+                    content,
+                });
+                Some(url)
+            }
+        }
+    }
+}
+
+struct Exporter<'a, R: Read + Seek> {
+    doc: RefCell<&'a mut EpubDoc<R>>,
+    options: &'a HtmlOptions,
+    /// The spine index of the exported chapters, by path
+    chapters: HashMap<ArchivePath, usize>,
+    /// The paths of the spine items, exported or not
+    spine: HashSet<ArchivePath>,
+    /// The ids in the export, by spine index and id in the chapter
+    ids: HashMap<(usize, String), String>,
+    assets: RefCell<Assets>,
+}
+
+impl<'a, R: Read + Seek> Exporter<'a, R> {
+    fn new(
+        doc: &'a mut EpubDoc<R>,
+        options: &'a HtmlOptions,
+        chapters: &[Chapter],
+        spine: HashSet<ArchivePath>,
+    ) -> Self {
+        let mut used: HashSet<String> = chapters.iter().map(|c| anchor(c.index)).collect();
+        used.insert(TOC_ID.to_string());
+        let mut ids = HashMap::new();
+        for chapter in chapters {
+            let body = chapter.body();
+            if let Some(id) = body.attr("id") {
+                ids.insert((chapter.index, id.to_string()), anchor(chapter.index));
+            }
+            for id in element_ids(body) {
+                ids.entry((chapter.index, id.to_string()))
+                    .or_insert_with(|| unique_id(&mut used, id, chapter.index));
+            }
+        }
+
+        Self {
+            doc: RefCell::new(doc),
+            options,
+            chapters: chapters.iter().map(|c| (c.path.clone(), c.index)).collect(),
+            spine,
+            ids,
+            assets: RefCell::new(Assets::new(options.assets.clone())),
+        }
+    }
+
+    fn export(self, chapters: &[Chapter]) -> HtmlExport {
+        let mut body = String::new();
+        if !self.options.skip_toc {
+            self.write_toc(&mut body);
+        }
+        let mut styles = vec![];
+        let mut linked = HashSet::new();
+        for chapter in chapters {
+            if let Some(head) = chapter.child("head") {
+                self.collect_styles(head, &chapter.path, &mut styles, &mut linked);
+            }
+            self.write_section(&mut body, chapter);
+        }
+
+        let doc = self.doc.borrow();
+        let mut html = String::from("<!DOCTYPE html>\n<html");
+        if let Some(language) = doc.mdata("language") {
+            push_attr(&mut html, "lang", &language.value);
+        }
+        html.push_str(">\n<head>\n<meta charset=\"utf-8\">\n<title>");
+        html.push_str(&escape_text(&doc.get_title().unwrap_or_default()));
+        html.push_str("</title>\n");
+        if !styles.is_empty() {
+            html.push_str("<style>\n");
+            html.push_str(&styles.join("\n").replace("</style", "<\\/style"));
+            html.push_str("\n</style>\n");
+        }
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&body);
+        html.push_str("</body>\n</html>\n");
+        drop(doc);
+
+        HtmlExport {
+            html,
+            assets: self.assets.into_inner().files,
+        }
+    }
+
+    /// Returns the id of the element `fragment` of the chapter `index` in
+    /// the export, or the one of the chapter if there's no such element
+    fn anchor(&self, index: usize, fragment: Option<&str>) -> String {
+        fragment
+            .map(|fragment| percent_decode_str(fragment).decode_utf8_lossy())
+            .and_then(|fragment| self.ids.get(&(index, fragment.into_owned())))
+            .cloned()
+            .unwrap_or_else(|| anchor(index))
+    }
+
+    /// Returns the reference replacing the `href` found in the entry `base`:
+    /// a link inside the document for the exported chapters, the url of the
+    /// asset for the other resources, or an empty one for the chapters left
+    /// out and the scripts.
+    fn reference(&self, base: &ArchivePath, href: &str) -> String {
+        if is_script_url(href) {
+            return String::new();
+        }
+        let Some((path, fragment)) = archive_reference(base, href) else {
+            return href.to_string();
+        };
+        if let Some(index) = self.chapters.get(&path) {
+            return format!("#{}", self.anchor(*index, fragment));
+        }
+        if self.spine.contains(&path) {
+            return String::new();
+        }
+        let Some(url) = self.asset_url(&path) else {
+            return href.to_string();
+        };
+        match fragment {
+            Some(fragment) => format!("{}#{}", url, fragment),
+            None => url,
+        }
+    }
+
+    /// Returns the url of the resource `path`, reading it the first time
+    fn asset_url(&self, path: &ArchivePath) -> Option<String> {
+        self.assets
+            .borrow_mut()
+            .url(&mut self.doc.borrow_mut(), path)
+    }
+
+    /// Collects the styles of the `head` of the chapter `base`, the linked
+    /// stylesheets once
+    fn collect_styles(
+        &self,
+        head: &Element,
+        base: &ArchivePath,
+        styles: &mut Vec<String>,
+        linked: &mut HashSet<ArchivePath>,
+    ) {
+        for element in child_elements(head) {
+            let css = match element.name().as_str() {
+                "link" if is_stylesheet(element) => {
+                    let Some((path, _)) = element
+                        .attr("href")
+                        .and_then(|href| archive_reference(base, href))
+                    else {
+                        continue;
+                    };
+                    if !linked.insert(path.clone()) {
+                        continue;
+                    }
+                    let Ok(content) = self.doc.borrow_mut().try_get_resource_by_path(&path) else {
+                        continue;
+                    };
+                    let (css, _) = encoding::decode(&content);
+                    self.rewrite_css(&path, without_charset(&css))
+                }
+                "style" => self.rewrite_css(base, &text(element)),
+                _ => continue,
+            };
+            if !styles.contains(&css) {
+                styles.push(css);
+            }
+        }
+    }
+
+    fn rewrite_css(&self, base: &ArchivePath, css: &str) -> String {
+        references::rewrite_css(css, &|href| self.reference(base, href))
+    }
+
+    fn write_toc(&self, out: &mut String) {
+        let doc = self.doc.borrow();
+        if doc.toc.is_empty() {
+            return;
+        }
+        out.push_str("<nav");
+        push_attr(out, "id", TOC_ID);
+        out.push_str(">\n");
+        if !doc.toc_title.is_empty() {
+            out.push_str("<h1>");
+            out.push_str(&escape_text(&doc.toc_title));
+            out.push_str("</h1>\n");
+        }
+        self.write_navpoints(out, &doc.toc);
+        out.push_str("</nav>\n");
+    }
+
+    /// Writes the `points` as a list, without link for the chapters left out
+    fn write_navpoints(&self, out: &mut String, points: &[NavPoint]) {
+        out.push_str("<ol>\n");
+        for point in points {
+            let content = point.content.to_string_lossy();
+            let (path, fragment) = match content.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (content.as_ref(), None),
+            };
+            let index = ArchivePath::new(path)
+                .ok()
+                .and_then(|path| self.chapters.get(&path));
+            out.push_str("<li>");
+            if let Some(index) = index {
+                out.push_str("<a");
+                push_attr(out, "href", &format!("#{}", self.anchor(*index, fragment)));
+                out.push('>');
+                out.push_str(&escape_text(&point.label));
+                out.push_str("</a>");
+            } else {
+                out.push_str("<span>");
+                out.push_str(&escape_text(&point.label));
+                out.push_str("</span>");
+            }
+            if !point.children.is_empty() {
+                out.push('\n');
+                self.write_navpoints(out, &point.children);
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ol>\n");
+    }
+
+    /// Writes the body of the `chapter` as a `<section>`, with the
+    /// attributes of the body and the language of the document
+    fn write_section(&self, out: &mut String, chapter: &Chapter) {
+        let body = chapter.body();
+        out.push_str("<section");
+        push_attr(out, "id", &anchor(chapter.index));
+        self.write_attrs(out, chapter, body, false);
+        if language(body).is_none() {
+            if let Some(language) = language(&chapter.root) {
+                push_attr(out, "lang", language);
+            }
+        }
+        out.push_str(">\n");
+        for node in &body.children {
+            self.write_node(out, chapter, node);
+        }
+        out.push_str("\n</section>\n");
+    }
+
+    fn write_node(&self, out: &mut String, chapter: &Chapter, node: &Node) {
+        match node {
+            Node::Text(text) => out.push_str(&escape_text(text).replace('>', "&gt;")),
+            Node::Element(element) => self.write_element(out, chapter, element),
+        }
+    }
+
+    fn write_element(&self, out: &mut String, chapter: &Chapter, element: &Element) {
+        let html = matches!(
+            element.name.namespace.as_deref(),
+            None | Some(XHTML_NAMESPACE)
+        );
+        let name = if html {
+            element.name()
+        } else {
+            // the svg and mathml elements are in their namespace in html
+            element.name.local_name.clone()
+        };
+        if DROPPED.contains(&element.name().as_str()) {
+            return;
+        }
+
+        out.push('<');
+        out.push_str(&name);
+        self.write_attrs(out, chapter, element, true);
+        if html && VOID.contains(&name.as_str()) {
+            out.push('>');
+            return;
+        }
+        if !html && element.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        if html && name == "style" {
+            let css = self.rewrite_css(&chapter.path, &text(element));
+            out.push_str(&css.replace("</style", "<\\/style"));
+        } else {
+            for node in &element.children {
+                self.write_node(out, chapter, node);
+            }
+        }
+        out.push_str("</");
+        out.push_str(&name);
+        out.push('>');
+    }
+
+    /// Writes the attributes of the `element` with their references
+    /// replaced, and the `id` if `with_id`. The event handlers are left out.
+    fn write_attrs(&self, out: &mut String, chapter: &Chapter, element: &Element, with_id: bool) {
+        let has_lang = element.attr("lang").is_some();
+        for attr in &element.attrs {
+            let local_name = attr.name.local_name.as_str();
+            let name = match (attr.name.namespace.as_deref(), attr.name.prefix.as_deref()) {
+                (None, _) if local_name.to_ascii_lowercase().starts_with("on") => continue,
+                (Some(NS_XML_URI), _) if local_name == "lang" && !has_lang => "lang".to_string(),
+                (Some(_), Some(prefix)) => format!("{}:{}", prefix, local_name),
+                _ => local_name.to_string(),
+            };
+            let value = if name == "id" {
+                if !with_id {
+                    continue;
+                }
+                self.ids
+                    .get(&(chapter.index, attr.value.clone()))
+                    .map_or(attr.value.as_str(), String::as_str)
+                    .to_string()
+            } else {
+                let value = references::rewrite_attr(
+                    element.name.borrow(),
+                    attr.name.borrow(),
+                    &attr.value,
+                    &|href| self.reference(&chapter.path, href),
+                );
+                // a link to a chapter left out
+                if value.is_empty() && !attr.value.is_empty() {
+                    continue;
+                }
+                value
+            };
+            push_attr(out, &name, &value);
+        }
+    }
+}
+
+/// Returns the id of the section of the spine item `index`
+fn anchor(index: usize) -> String {
+    format!("chapter-{}", index)
+}
+
+/// Returns `id` if it isn't `used` yet, or a new one with the spine `index`
+fn unique_id(used: &mut HashSet<String>, id: &str, index: usize) -> String {
+    let mut unique = id.to_string();
+    let mut n = 1;
+    while !used.insert(unique.clone()) {
+        unique = if n == 1 {
+            format!("{}-{}", id, index)
+        } else {
+            format!("{}-{}-{}", id, index, n)
+        };
+        n += 1;
+    }
+    unique
+}
+
+/// Returns the ids of the descendants of `element`
+pub(crate) fn element_ids(element: &Element) -> Vec<&str> {
+    let mut ids = vec![];
+    for child in child_elements(element) {
+        ids.extend(child.attr("id"));
+        ids.extend(element_ids(child));
+    }
+    ids
+}
+
+pub(crate) fn child_elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(|node| match node {
+        Node::Element(e) => Some(e),
+        Node::Text(_) => None,
+    })
+}
+
+/// Returns the text of the element, without the one of its children
+pub(crate) fn text(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            Node::Text(text) => Some(text.as_str()),
+            Node::Element(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the `lang` or `xml:lang` of the element
+pub(crate) fn language(element: &Element) -> Option<&str> {
+    element
+        .attr("lang")
+        .or_else(|| element.attr_ns(NS_XML_URI, "lang"))
+}
+
+/// Returns `true` if the `href` runs a script, like `javascript:` urls,
+/// with the whitespace the browsers ignore in it
+pub(crate) fn is_script_url(href: &str) -> bool {
+    let href: String = href
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .take(11)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    href.starts_with("javascript:") || href.starts_with("vbscript:")
+}
+
+fn is_stylesheet(link: &Element) -> bool {
+    let rel = link.attr("rel").unwrap_or_default();
+    rel.split_whitespace()
+        .any(|r| r.eq_ignore_ascii_case("stylesheet"))
+        && !rel
+            .split_whitespace()
+            .any(|r| r.eq_ignore_ascii_case("alternate"))
+}
+
+/// Returns the `css` without its `@charset` rule, only valid at the start
+/// of a stylesheet
+fn without_charset(css: &str) -> &str {
+    if css.starts_with("@charset ") {
+        css.find(';').map_or(css, |i| css[i + 1..].trim_start())
+    } else {
+        css
+    }
+}
+
+fn push_attr(out: &mut String, name: &str, value: &str) {
+    out.push(' ');
+    out.push_str(name);
+    out.push_str("=\"");
+    out.push_str(&escape_attr(value, '"'));
+    out.push('"');
+}
@@ -0,0 +1,89 @@
+//! Tolerant parsing of the content documents with the HTML5 algorithm of
+//! html5ever, for the `text/html` resources and the broken xhtml ones.
+//!
+//! The tree is converted to the [`dom`](crate::dom) one, with the names of
+//! the xml parser: the html elements in the xhtml namespace, and the
+//! `epub:type` attributes in the ops namespace.
+
+use html5ever::tendril::TendrilSink;
+use html5ever::{parse_document, Attribute, ParseOpts, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
+
+use crate::dom::{Element, Node, OPS_NAMESPACE};
+use crate::encoding;
+use crate::limits::Limits;
+use crate::xmlutils::XMLError;
+
+/// Parses the document `content` as HTML5, it never fails on a syntax error
+pub fn parse(content: &[u8], limits: &Limits) -> Result<Element, XMLError> {
+    let (text, _) = encoding::decode(content);
+    let dom = parse_document(RcDom::default(), ParseOpts::default()).one(text.as_ref());
+
+    let root = dom
+        .document
+        .children
+        .borrow()
+        .iter()
+        .find(|node| matches!(node.data, NodeData::Element { .. }))
+        .cloned()
+        .ok_or(XMLError::NoElements)?;
+    match convert(&root, 0, limits)? {
+        Some(Node::Element(element)) => Ok(element),
+        _ => Err(XMLError::NoElements),
+    }
+}
+
+/// Converts the `node` found at `depth`, returning [`None`] for the
+/// comments and processing instructions
+fn convert(node: &Handle, depth: usize, limits: &Limits) -> Result<Option<Node>, XMLError> {
+    match &node.data {
+        NodeData::Text { contents } => {
+            let text = contents.borrow().to_string();
+            limits.check_text(&text)?;
+            Ok(Some(Node::Text(text)))
+        }
+        NodeData::Element { name, attrs, .. } => {
+            let attrs = attrs.borrow();
+            limits.check_element(&name.local, depth, attrs.len())?;
+            let mut children = vec![];
+            for child in node.children.borrow().iter() {
+                children.extend(convert(child, depth + 1, limits)?);
+            }
+            Ok(Some(Node::Element(Element {
+                name: element_name(name),
+                attrs: attrs.iter().map(attribute).collect(),
+                children,
+            })))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn element_name(name: &QualName) -> OwnedName {
+    OwnedName {
+        local_name: name.local.to_string(),
+        namespace: Some(name.ns.to_string()).filter(|ns| !ns.is_empty()),
+        prefix: name.prefix.as_ref().map(ToString::to_string),
+    }
+}
+
+/// Converts an attribute, the html parser keeps the unknown prefixes like
+/// `epub:` in the local name
+fn attribute(attr: &Attribute) -> OwnedAttribute {
+    let mut name = element_name(&attr.name);
+    if name.namespace.is_none() {
+        if let Some(("epub", local)) = name.local_name.split_once(':') {
+            name = OwnedName {
+                local_name: local.to_string(),
+                namespace: Some(OPS_NAMESPACE.to_string()),
+                prefix: Some("epub".to_string()),
+            };
+        }
+    }
+    OwnedAttribute {
+        name,
+        value: attr.value.to_string(),
+    }
+}
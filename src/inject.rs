@@ -0,0 +1,73 @@
+//! Elements injected in the `<head>` of the documents rewritten by
+//! [`EpubDoc::get_current_with_epub_uris`](crate::doc::EpubDoc::get_current_with_epub_uris),
+//! like the styles and the scripts of a reader.
+//!
+//! The documents without a `<head>` get one before their `<body>`.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::inject::{Injection, Placement};
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! doc.inject(Injection::Viewport("width=device-width".into()), Placement::Start);
+//! doc.inject(Injection::Stylesheet("app://reader/theme.css".into()), Placement::End);
+//! doc.inject(Injection::ScriptUrl("app://reader/shell.js".into()), Placement::End);
+//!
+//! let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+//! assert!(current.contains(r#"<link rel="stylesheet" type="text/css" href="app://reader/theme.css""#));
+//! ```
+
+/// Where an [`Injection`] goes in the `<head>`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    /// Before the elements of the document, so its styles take precedence
+    Start,
+    /// After the elements of the document, taking precedence over its styles
+    #[default]
+    End,
+}
+
+/// An element injected in the `<head>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Injection {
+    /// A `<style>` element with this css
+    Style(String),
+    /// A `<link rel="stylesheet">` to this url, like a virtual resource
+    /// served by the reader. The url is used as is.
+    Stylesheet(String),
+    /// A `<script>` element with this code
+    Script(String),
+    /// A `<script>` loading this url, used as is
+    ScriptUrl(String),
+    /// A `<meta name="viewport">` with this content, like
+    /// `width=device-width, initial-scale=1`
+    Viewport(String),
+}
+
+impl Injection {
+    /// Returns the name, the attributes and the text of the element. The
+    /// scripts always have a text, so they aren't written as empty elements.
+    pub(crate) fn element(&self) -> (&'static str, Vec<(&'static str, &str)>, Option<&str>) {
+        match self {
+            Self::Style(css) => ("style", vec![("type", "text/css")], Some(css)),
+            Self::Stylesheet(url) => (
+                "link",
+                vec![("rel", "stylesheet"), ("type", "text/css"), ("href", url)],
+                None,
+            ),
+            Self::Script(code) => ("script", vec![("type", "text/javascript")], Some(code)),
+            Self::ScriptUrl(url) => (
+                "script",
+                vec![("type", "text/javascript"), ("src", url)],
+                Some(""),
+            ),
+            Self::Viewport(content) => (
+                "meta",
+                vec![("name", "viewport"), ("content", content)],
+                None,
+            ),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Readium LCP decryption, with the `lcp` feature.
+//!
+//! A LCP protected epub has its license in `META-INF/license.lcpl`. The
+//! user passphrase unlocks the content key of the license, and the
+//! resources listed in `META-INF/encryption.xml` are decrypted with it.
+//! Only the basic profile is supported, and the license signature isn't
+//! checked against the provider certificate.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use epub::doc::EpubDoc;
+//!
+//! let mut doc = EpubDoc::new("lcp.epub").unwrap();
+//! let license = doc.lcp_license().unwrap().unwrap();
+//! println!("Passphrase hint: {}", license.text_hint);
+//!
+//! doc.unlock_lcp("the user passphrase").unwrap();
+//! let chapter = doc.get_current_str();
+//! ```
+
+use aes::Aes256;
+use base64::Engine;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Seek};
+
+use crate::doc::{DocError, EpubDoc};
+
+/// Path of the license in the archive
+pub const LICENSE_FILE: &str = crate::ocf::LCP_LICENSE_FILE;
+
+/// The only encryption profile supported
+pub const BASIC_PROFILE: &str = "http://readium.org/lcp/basic-profile";
+
+/// Algorithm uri of the content key and the resources encryption
+pub const AES256_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes256-cbc";
+
+/// Algorithm uri of the user key derivation
+pub const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+
+const BLOCK_SIZE: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LcpError {
+    #[error("License not found")]
+    LicenseNotFound,
+    #[error("Invalid license: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("License field not found: {0}")]
+    FieldNotFound(&'static str),
+    #[error("Invalid base64 in the license: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Unsupported encryption profile: {0}")]
+    UnsupportedProfile(String),
+    #[error("Unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("Can't decrypt the data")]
+    Decryption,
+}
+
+/// A LCP license, from `META-INF/license.lcpl`
+#[derive(Clone, Debug)]
+pub struct License {
+    /// Unique id of the license
+    pub id: String,
+    /// Uri of the license provider
+    pub provider: String,
+    /// Uri of the encryption profile
+    pub profile: String,
+    /// Hint to show the user when asking for the passphrase
+    pub text_hint: String,
+    /// Url of a page to recover the passphrase, from the `hint` link
+    pub hint_url: Option<String>,
+    content_key: Vec<u8>,
+    key_check: Vec<u8>,
+}
+
+impl License {
+    /// Parses the license `json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the license is invalid, or uses an unsupported
+    /// profile or algorithm.
+    pub fn parse(json: &[u8]) -> Result<Self, LcpError> {
+        let license: Value = serde_json::from_slice(json)?;
+        let encryption = &license["encryption"];
+
+        let profile = string(encryption, "profile")?;
+        if profile != BASIC_PROFILE {
+            return Err(LcpError::UnsupportedProfile(profile));
+        }
+        let content_key_algorithm = string(&encryption["content_key"], "algorithm")?;
+        if content_key_algorithm != AES256_CBC {
+            return Err(LcpError::UnsupportedAlgorithm(content_key_algorithm));
+        }
+        let user_key_algorithm = string(&encryption["user_key"], "algorithm")?;
+        if user_key_algorithm != SHA256 {
+            return Err(LcpError::UnsupportedAlgorithm(user_key_algorithm));
+        }
+
+        let hint_url = license["links"].as_array().and_then(|links| {
+            links
+                .iter()
+                .find(|l| l["rel"] == "hint")
+                .and_then(|l| l["href"].as_str())
+                .map(String::from)
+        });
+
+        Ok(Self {
+            id: string(&license, "id")?,
+            provider: string(&license, "provider")?,
+            profile,
+            text_hint: string(&encryption["user_key"], "text_hint")?,
+            hint_url,
+            content_key: base64(&encryption["content_key"], "encrypted_value")?,
+            key_check: base64(&encryption["user_key"], "key_check")?,
+        })
+    }
+
+    /// Derives the user key from the `passphrase`, the SHA-256 of the
+    /// passphrase in the basic profile
+    #[must_use]
+    pub fn user_key(passphrase: &str) -> [u8; 32] {
+        Sha256::digest(passphrase.as_bytes()).into()
+    }
+
+    /// Unlocks the content key with the user `passphrase`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LcpError::WrongPassphrase`] if the key check of the
+    /// license doesn't match.
+    pub fn unlock(&self, passphrase: &str) -> Result<ContentKey, LcpError> {
+        self.unlock_with_user_key(&Self::user_key(passphrase))
+    }
+
+    /// Unlocks the content key with the `user_key`, for apps storing the
+    /// user key instead of the passphrase.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LcpError::WrongPassphrase`] if the key check of the
+    /// license doesn't match.
+    pub fn unlock_with_user_key(&self, user_key: &[u8; 32]) -> Result<ContentKey, LcpError> {
+        let key_check =
+            decrypt(user_key, &self.key_check).map_err(|_| LcpError::WrongPassphrase)?;
+        if key_check != self.id.as_bytes() {
+            return Err(LcpError::WrongPassphrase);
+        }
+        let content_key = decrypt(user_key, &self.content_key)?;
+        let content_key = content_key.try_into().map_err(|_| LcpError::Decryption)?;
+        Ok(ContentKey(content_key))
+    }
+}
+
+/// The key to decrypt the resources, unlocked from the [`License`]
+#[derive(Clone)]
+pub struct ContentKey([u8; 32]);
+
+impl ContentKey {
+    /// Decrypts a resource, `data` is the initialization vector followed
+    /// by the encrypted content
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LcpError::Decryption`] if the data isn't encrypted with
+    /// this key.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, LcpError> {
+        decrypt(&self.0, data)
+    }
+}
+
+impl std::fmt::Debug for ContentKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never printing the key
+        f.write_str("ContentKey(..)")
+    }
+}
+
+/// AES-256-CBC decryption of `data`, with the initialization vector in
+/// the first block and PKCS#7 padding
+fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, LcpError> {
+    if data.len() < 2 * BLOCK_SIZE || !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(LcpError::Decryption);
+    }
+    let (iv, content) = data.split_at(BLOCK_SIZE);
+    cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| LcpError::Decryption)?
+        .decrypt_padded_vec_mut::<Pkcs7>(content)
+        .map_err(|_| LcpError::Decryption)
+}
+
+fn string(value: &Value, field: &'static str) -> Result<String, LcpError> {
+    value[field]
+        .as_str()
+        .map(String::from)
+        .ok_or(LcpError::FieldNotFound(field))
+}
+
+fn base64(value: &Value, field: &'static str) -> Result<Vec<u8>, LcpError> {
+    let encoded = string(value, field)?;
+    Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?)
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Returns the LCP license of the epub, if it's protected with LCP.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the license can't be read or parsed.
+    pub fn lcp_license(&mut self) -> Result<Option<License>, DocError> {
+        if !self.archive.contains(LICENSE_FILE) {
+            return Ok(None);
+        }
+        let content = self.archive.get_entry(LICENSE_FILE)?;
+        Ok(Some(License::parse(&content)?))
+    }
+
+    /// Unlocks the LCP protected epub with the user `passphrase`, so the
+    /// resources are decrypted by [`Self::get_resource`] and the other
+    /// resource accessors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LcpError::LicenseNotFound`] if the epub doesn't have a
+    /// license and [`LcpError::WrongPassphrase`] if the passphrase doesn't
+    /// unlock it.
+    pub fn unlock_lcp(&mut self, passphrase: &str) -> Result<(), DocError> {
+        self.unlock_lcp_with_user_key(&License::user_key(passphrase))
+    }
+
+    /// Unlocks the LCP protected epub with the `user_key` derived from the
+    /// passphrase, see [`License::user_key`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`LcpError::LicenseNotFound`] if the epub doesn't have a
+    /// license and [`LcpError::WrongPassphrase`] if the key doesn't unlock
+    /// it.
+    pub fn unlock_lcp_with_user_key(&mut self, user_key: &[u8; 32]) -> Result<(), DocError> {
+        let license = self.lcp_license()?.ok_or(LcpError::LicenseNotFound)?;
+        self.lcp_key = Some(license.unlock_with_user_key(user_key)?);
+        self.search_index = None;
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(
     clippy::module_name_repetitions,
-    clippy::let_underscore_drop,
+    let_underscore_drop,
 
     // for MSRV
     clippy::unnested_or_patterns,
@@ -111,7 +111,30 @@
 //! let resp = f.write_all(&cover_data);
 //! ```
 
+mod css;
+mod dom;
+mod encoding;
+mod entities;
+#[cfg(feature = "html5")]
+mod html;
+mod markup;
+mod references;
 mod xmlutils;
 
 pub mod archive;
+pub mod cfi;
 pub mod doc;
+pub mod encryption;
+pub mod export;
+pub mod inject;
+#[cfg(feature = "lcp")]
+pub mod lcp;
+pub mod limits;
+pub mod markdown;
+pub mod ocf;
+pub mod positions;
+pub mod search;
+pub mod stats;
+pub mod text;
+pub mod uri;
+pub mod validate;
@@ -0,0 +1,151 @@
+//! Limits to protect against zip bombs and resource exhaustion.
+//!
+//! The epub files read may come from untrusted sources, so the sizes
+//! declared in the zip archive and the complexity of the XML documents are
+//! checked against [`Limits`] before and while reading them.
+
+/// Entries smaller than this aren't checked against
+/// [`Limits::max_compression_ratio`]
+pub const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Configurable limits for reading epub files
+///
+/// # Examples
+///
+/// ```
+/// use epub::doc::{EpubDoc, EpubOptions};
+/// use epub::limits::Limits;
+///
+/// let options = EpubOptions {
+///     limits: Limits {
+///         max_entry_size: 10 * 1024 * 1024,
+///         ..Limits::default()
+///     },
+///     ..EpubOptions::default()
+/// };
+/// let doc = EpubDoc::new_with_options("test.epub", options);
+/// assert!(doc.is_ok());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum uncompressed size of a single entry of the zip archive
+    pub max_entry_size: u64,
+    /// Maximum uncompressed size of all the entries of the zip archive
+    pub max_total_size: u64,
+    /// Maximum uncompressed/compressed size ratio of an entry of the zip
+    /// archive. Entries smaller than [`RATIO_CHECK_MIN_SIZE`] are not
+    /// checked, small files of repeated text can have a high ratio.
+    pub max_compression_ratio: u64,
+    /// Maximum number of entries in the zip archive
+    pub max_entries: usize,
+    /// Maximum nesting of elements in a XML document
+    pub max_xml_depth: usize,
+    /// Maximum number of attributes of a XML element
+    pub max_xml_attributes: usize,
+    /// Maximum size in bytes of a text node of a XML document
+    pub max_xml_text_size: usize,
+    /// Maximum size in bytes of the text an entity declared in the DTD of a
+    /// XML document expands to. The entities nested more than 10 levels
+    /// deep are rejected too.
+    pub max_entity_expansion: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_entry_size: 256 * 1024 * 1024,
+            max_total_size: 1024 * 1024 * 1024,
+            max_compression_ratio: 100,
+            max_entries: 10_000,
+            max_xml_depth: 256,
+            max_xml_attributes: 256,
+            max_xml_text_size: 16 * 1024 * 1024,
+            max_entity_expansion: 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all, only for trusted files
+    #[must_use]
+    pub const fn unlimited() -> Self {
+        Self {
+            max_entry_size: u64::MAX,
+            max_total_size: u64::MAX,
+            max_compression_ratio: u64::MAX,
+            max_entries: usize::MAX,
+            max_xml_depth: usize::MAX,
+            max_xml_attributes: usize::MAX,
+            max_xml_text_size: usize::MAX,
+            max_entity_expansion: usize::MAX,
+        }
+    }
+
+    /// Checks a XML element found at `depth` with `attributes` attributes
+    pub(crate) fn check_element(
+        &self,
+        element: &str,
+        depth: usize,
+        attributes: usize,
+    ) -> Result<(), LimitError> {
+        if depth >= self.max_xml_depth {
+            return Err(LimitError::XmlDepth {
+                limit: self.max_xml_depth,
+            });
+        }
+        if attributes > self.max_xml_attributes {
+            return Err(LimitError::XmlAttributes {
+                element: element.to_string(),
+                limit: self.max_xml_attributes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks a XML reader error, the expansion of the entities being
+    /// stopped by the reader itself
+    pub(crate) fn check_reader_error(&self, error: &xml::reader::Error) -> Result<(), LimitError> {
+        // the reader only tells the reason in its message
+        match error.kind() {
+            xml::reader::ErrorKind::Syntax(message) if message == "Entity too big" => {
+                Err(LimitError::EntityExpansion {
+                    limit: self.max_entity_expansion,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a XML text node
+    pub(crate) fn check_text(&self, text: &str) -> Result<(), LimitError> {
+        if text.len() > self.max_xml_text_size {
+            return Err(LimitError::XmlTextSize {
+                limit: self.max_xml_text_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The limit that was exceeded reading the epub
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum LimitError {
+    #[error("Entry {name} is larger than {limit} bytes")]
+    EntrySize { name: String, limit: u64 },
+    #[error("Entry {name} is larger than its declared size of {declared} bytes")]
+    SizeMismatch { name: String, declared: u64 },
+    #[error("The archive uncompressed size is larger than {limit} bytes")]
+    TotalSize { limit: u64 },
+    #[error("Entry {name} has a compression ratio larger than {limit}")]
+    CompressionRatio { name: String, limit: u64 },
+    #[error("The archive has more than {limit} entries")]
+    Entries { limit: usize },
+    #[error("XML elements nested deeper than {limit} levels")]
+    XmlDepth { limit: usize },
+    #[error("XML element <{element}> has more than {limit} attributes")]
+    XmlAttributes { element: String, limit: usize },
+    #[error("XML text larger than {limit} bytes")]
+    XmlTextSize { limit: usize },
+    #[error("XML entity expanding to more than {limit} bytes")]
+    EntityExpansion { limit: usize },
+}
@@ -0,0 +1,1060 @@
+//! Export of the book to Markdown, for the tools ingesting books as text
+//! with their structure.
+//!
+//! The chapters of the linear spine are converted to Markdown, with the
+//! tables and the strikethrough of GitHub Flavored Markdown, in one file per
+//! spine item or per entry of the table of contents, see
+//! [`MarkdownOptions::split_by_toc`]. The notes marked with `epub:type` or
+//! the equivalent ARIA `role` become footnotes of the files referencing
+//! them, the links between the chapters are rewritten to the files, and
+//! the images are written to a folder, see [`MarkdownOptions::assets`].
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::markdown::MarkdownOptions;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let export = doc.export_markdown().unwrap();
+//! assert_eq!(export.files[0].path, "titlepage.md");
+//! assert!(export.files[0].markdown.contains("(images/OEBPS/Images/portada.png)"));
+//! assert_eq!(export.assets[0].path, "images/OEBPS/Images/portada.png");
+//!
+//! let options = MarkdownOptions {
+//!     split_by_toc: true,
+//!     ..MarkdownOptions::default()
+//! };
+//! let export = doc.export_markdown_with(&options).unwrap();
+//! assert_eq!(export.files.len(), doc.toc.len());
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+
+use percent_encoding::percent_decode_str;
+
+use crate::archive::ArchivePath;
+use crate::doc::{DocError, EpubDoc, NavPoint};
+use crate::dom::{Element, Node};
+use crate::export::{
+    child_elements, element_ids, is_script_url, text, Asset, AssetMode, Assets, Chapter,
+};
+use crate::references::XLINK_NAMESPACE;
+use crate::text::{is_block, is_note, is_skipped};
+use crate::uri::archive_reference;
+
+/// The folder of the images by default
+const IMAGES: &str = "images";
+
+/// The blocks converted by their own rules, the other ones only contain
+/// blocks or text
+const CONVERTED: &[&str] = &[
+    "blockquote",
+    "dl",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "ol",
+    "p",
+    "pre",
+    "table",
+    "ul",
+];
+
+/// The characters escaped in the text
+const ESCAPED: &[char] = &['\\', '`', '*', '_', '[', ']', '<'];
+
+const HARD_BREAK: &str = "\\\n";
+
+/// The options of [`EpubDoc::export_markdown_with`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// How the images are written, in the `images` folder by default
+    pub assets: AssetMode,
+    /// Writes one file per entry of the table of contents rather than one
+    /// per spine item. The content before the first entry goes in the file
+    /// of the first one.
+    pub split_by_toc: bool,
+    /// Exports the spine items with `linear="no"` too, in the spine order
+    pub include_non_linear: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            assets: AssetMode::Folder(IMAGES.to_string()),
+            split_by_toc: false,
+            include_non_linear: false,
+        }
+    }
+}
+
+/// A Markdown file of an export
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarkdownFile {
+    /// The name of the file, like `chapter1.md`
+    pub path: String,
+    /// The label of its entry in the table of contents, or the title of
+    /// its document
+    pub title: String,
+    pub markdown: String,
+}
+
+/// A book exported to Markdown
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MarkdownExport {
+    /// The files in the reading order, written in the same folder
+    pub files: Vec<MarkdownFile>,
+    /// The resources to write in the folder of [`AssetMode::Folder`], in
+    /// the order they are referenced
+    pub assets: Vec<Asset>,
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Exports the book to Markdown, see [`Self::export_markdown_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn export_markdown(&mut self) -> Result<MarkdownExport, DocError> {
+        self.export_markdown_with(&MarkdownOptions::default())
+    }
+
+    /// Exports the chapters of the linear spine to Markdown files, with
+    /// their notes as footnotes and the images they reference, see the
+    /// [module](crate::markdown) documentation. The chapters that can't be
+    /// read or parsed are left out, the links to them are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn export_markdown_with(
+        &mut self,
+        options: &MarkdownOptions,
+    ) -> Result<MarkdownExport, DocError> {
+        let (chapters, spine) = self.export_chapters(options.include_non_linear)?;
+        // the notes are often in a non-linear spine item
+        let mut others = vec![];
+        for index in 0..self.spine.len() {
+            if chapters.iter().any(|chapter| chapter.index == index) {
+                continue;
+            }
+            if let (Some(path), Ok(root)) = (self.spine_path(index), self.chapter_dom(index)) {
+                others.push(Chapter { index, path, root });
+            }
+        }
+        let mut converter = Converter::new(self, options, spine);
+        converter.collect(&chapters, &others);
+        if options.split_by_toc {
+            converter.split_by_toc(&chapters);
+        }
+        if converter.files.is_empty() {
+            converter.split_by_spine(&chapters);
+        }
+        Ok(converter.convert(&chapters))
+    }
+}
+
+/// An element of a chapter, by path and id
+type Key = (ArchivePath, String);
+
+/// A Markdown file being written
+#[derive(Default)]
+struct File {
+    path: String,
+    title: String,
+    blocks: Vec<String>,
+    /// The footnote definitions, in the order they are referenced
+    footnotes: Vec<String>,
+    /// The number of the footnotes, by note
+    numbers: HashMap<Key, usize>,
+}
+
+struct Converter<'a, R: Read + Seek> {
+    doc: &'a mut EpubDoc<R>,
+    assets: Assets,
+    /// The paths of the spine items, exported or not
+    spine: HashSet<ArchivePath>,
+    /// The targets of the links, by id of the link or of its closest
+    /// ancestor with one
+    links: HashMap<Key, Key>,
+    /// The elements targeted by a link, written with an anchor
+    anchors: HashSet<Key>,
+    /// The notes targeted by a link, written as footnotes
+    notes: HashMap<Key, Element>,
+    /// The files starting at an element, or at the start of a chapter with
+    /// an empty id, when split by the table of contents
+    starts: HashMap<Key, usize>,
+    /// The file of the start of the exported chapters
+    chapter_files: HashMap<ArchivePath, usize>,
+    /// The file of the elements, when split by the table of contents
+    id_files: HashMap<Key, usize>,
+    files: Vec<File>,
+    /// The file being written
+    current: usize,
+}
+
+impl<'a, R: Read + Seek> Converter<'a, R> {
+    fn new(
+        doc: &'a mut EpubDoc<R>,
+        options: &MarkdownOptions,
+        spine: HashSet<ArchivePath>,
+    ) -> Self {
+        Self {
+            doc,
+            assets: Assets::new(options.assets.clone()),
+            spine,
+            links: HashMap::new(),
+            anchors: HashSet::new(),
+            notes: HashMap::new(),
+            starts: HashMap::new(),
+            chapter_files: HashMap::new(),
+            id_files: HashMap::new(),
+            files: vec![],
+            current: 0,
+        }
+    }
+
+    /// Collects the links of the `chapters` and the notes they reference,
+    /// in the `others` too
+    fn collect(&mut self, chapters: &[Chapter], others: &[Chapter]) {
+        for chapter in chapters.iter().chain(others) {
+            self.collect_links(&chapter.path, chapter.body(), None);
+        }
+        for chapter in chapters.iter().chain(others) {
+            self.collect_notes(&chapter.path, chapter.body());
+        }
+    }
+
+    fn collect_links(&mut self, base: &ArchivePath, element: &Element, id: Option<&str>) {
+        for child in child_elements(element) {
+            let id = child.attr("id").or(id);
+            let target = child
+                .attr("href")
+                .filter(|_| child.name() == "a")
+                .and_then(|href| archive_reference(base, href));
+            if let Some((path, Some(fragment))) = target {
+                let target = (path, fragment_id(fragment));
+                if let Some(id) = id {
+                    self.links
+                        .entry((base.clone(), id.to_string()))
+                        .or_insert_with(|| target.clone());
+                }
+                self.anchors.insert(target);
+            }
+            self.collect_links(base, child, id);
+        }
+    }
+
+    fn collect_notes(&mut self, base: &ArchivePath, element: &Element) {
+        for child in child_elements(element) {
+            if is_note_body(child) {
+                if let Some(id) = child.attr("id") {
+                    let key = (base.clone(), id.to_string());
+                    if self.anchors.contains(&key) {
+                        self.notes.insert(key, child.clone());
+                        continue;
+                    }
+                }
+            }
+            self.collect_notes(base, child);
+        }
+    }
+
+    /// Adds a file per entry of the table of contents targeting an exported
+    /// chapter, and finds the file of every element
+    fn split_by_toc(&mut self, chapters: &[Chapter]) {
+        let mut entries = vec![];
+        toc_entries(&self.doc.toc, &mut entries);
+        for (path, fragment, label) in entries {
+            let Some(chapter) = chapters.iter().find(|chapter| chapter.path == path) else {
+                continue;
+            };
+            // a missing element is the start of the chapter
+            let id = fragment
+                .filter(|id| element_ids(chapter.body()).contains(&id.as_str()))
+                .unwrap_or_default();
+            let key = (path, id);
+            if !self.starts.contains_key(&key) {
+                self.starts.insert(key, self.files.len());
+                self.add_file(&slug(&label), label);
+            }
+        }
+
+        let mut current = 0;
+        for chapter in chapters {
+            if let Some(file) = self.starts.get(&(chapter.path.clone(), String::new())) {
+                current = *file;
+            }
+            self.chapter_files.insert(chapter.path.clone(), current);
+            self.assign_files(&chapter.path, chapter.body(), &mut current);
+        }
+    }
+
+    fn assign_files(&mut self, base: &ArchivePath, element: &Element, current: &mut usize) {
+        for child in child_elements(element) {
+            if let Some(id) = child.attr("id") {
+                let key = (base.clone(), id.to_string());
+                if let Some(file) = self.starts.get(&key) {
+                    *current = *file;
+                }
+                self.id_files.insert(key, *current);
+            }
+            self.assign_files(base, child, current);
+        }
+    }
+
+    /// Adds a file per chapter, titled by its entry in the table of
+    /// contents or its `<title>`
+    fn split_by_spine(&mut self, chapters: &[Chapter]) {
+        let mut entries = vec![];
+        toc_entries(&self.doc.toc, &mut entries);
+        for chapter in chapters {
+            let label = entries
+                .iter()
+                .filter(|(path, _, _)| *path == chapter.path)
+                .min_by_key(|(_, fragment, _)| fragment.is_some())
+                .map(|(_, _, label)| label.clone());
+            let title = label.unwrap_or_else(|| {
+                chapter
+                    .child("head")
+                    .and_then(|head| child_elements(head).find(|e| e.name() == "title"))
+                    .map(|title| text(title).trim().to_string())
+                    .unwrap_or_default()
+            });
+            let name = chapter.path.as_str().rsplit('/').next().unwrap_or_default();
+            let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            self.chapter_files
+                .insert(chapter.path.clone(), self.files.len());
+            self.add_file(stem, title);
+        }
+    }
+
+    /// Adds a file named `name`, or with a number after it if taken
+    fn add_file(&mut self, name: &str, title: String) {
+        let name = if name.is_empty() { "section" } else { name };
+        let mut path = format!("{}.md", name);
+        let mut n = 2;
+        while self.files.iter().any(|file| file.path == path) {
+            path = format!("{}-{}.md", name, n);
+            n += 1;
+        }
+        self.files.push(File {
+            path,
+            title,
+            ..File::default()
+        });
+    }
+
+    fn convert(mut self, chapters: &[Chapter]) -> MarkdownExport {
+        for chapter in chapters {
+            if let Some(file) = self.chapter_files.get(&chapter.path) {
+                self.current = *file;
+            }
+            self.container(&chapter.path, chapter.body(), true);
+        }
+
+        let files = self
+            .files
+            .into_iter()
+            .map(|file| {
+                let mut markdown = file.blocks.join("\n\n");
+                if !file.footnotes.is_empty() {
+                    if !markdown.is_empty() {
+                        markdown.push_str("\n\n");
+                    }
+                    markdown.push_str(&file.footnotes.join("\n\n"));
+                }
+                if !markdown.is_empty() {
+                    markdown.push('\n');
+                }
+                MarkdownFile {
+                    path: file.path,
+                    title: file.title,
+                    markdown,
+                }
+            })
+            .collect();
+        MarkdownExport {
+            files,
+            assets: self.assets.files,
+        }
+    }
+
+    /// Converts the children of the `element` of the chapter `base` to
+    /// blocks. At the `top` of the chapter, they are written to the current
+    /// file instead, which changes at the start of the entries of the table
+    /// of contents.
+    fn container(&mut self, base: &ArchivePath, element: &Element, top: bool) -> Vec<String> {
+        let mut blocks = vec![];
+        let mut inline = Inline::default();
+        for node in &element.children {
+            let child = match node {
+                Node::Text(text) => {
+                    inline.text(text);
+                    continue;
+                }
+                Node::Element(child) => child,
+            };
+            if self.is_left_out(base, child) {
+                continue;
+            }
+            let container = is_block(child) && !CONVERTED.contains(&child.name().as_str());
+            if top {
+                if let Some(file) = self.start(base, child, !container) {
+                    self.push_block(&mut blocks, top, std::mem::take(&mut inline).finish());
+                    self.current = file;
+                }
+            }
+            if !is_block(child) {
+                self.inline(base, child, &mut inline);
+                continue;
+            }
+
+            self.push_block(&mut blocks, top, std::mem::take(&mut inline).finish());
+            if !matches!(
+                child.name().as_str(),
+                "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+            ) {
+                if let Some(anchor) = self.anchor(base, child) {
+                    self.push_block(&mut blocks, top, anchor);
+                }
+            }
+            if container {
+                let nested = self.container(base, child, top);
+                blocks.extend(nested);
+            } else {
+                let block = self.block(base, child);
+                self.push_block(&mut blocks, top, block);
+            }
+        }
+        self.push_block(&mut blocks, top, inline.finish());
+        blocks
+    }
+
+    fn push_block(&mut self, blocks: &mut Vec<String>, top: bool, block: String) {
+        if block.is_empty() {
+            return;
+        }
+        if top {
+            self.files[self.current].blocks.push(block);
+        } else {
+            blocks.push(block);
+        }
+    }
+
+    /// Returns the file starting at the `element`, or at its first
+    /// descendant starting one if `deep`
+    fn start(&self, base: &ArchivePath, element: &Element, deep: bool) -> Option<usize> {
+        if self.starts.is_empty() {
+            return None;
+        }
+        let own = element
+            .attr("id")
+            .filter(|id| !id.is_empty())
+            .and_then(|id| self.starts.get(&(base.clone(), id.to_string())));
+        if own.is_some() || !deep {
+            return own.copied();
+        }
+        child_elements(element).find_map(|child| self.start(base, child, true))
+    }
+
+    /// Returns `true` if the element has no Markdown, or is a note written
+    /// as a footnote
+    fn is_left_out(&self, base: &ArchivePath, element: &Element) -> bool {
+        is_skipped(element)
+            || element
+                .attr("id")
+                .is_some_and(|id| self.notes.contains_key(&(base.clone(), id.to_string())))
+    }
+
+    /// Returns the anchor of the element if it's the target of a link,
+    /// other than a reference to a note
+    fn anchor(&self, base: &ArchivePath, element: &Element) -> Option<String> {
+        let key = (base.clone(), element.attr("id")?.to_string());
+        if !self.anchors.contains(&key) || self.is_noteref(&key) {
+            return None;
+        }
+        Some(format!(
+            "<a id=\"{}\"></a>",
+            key.1.replace('&', "&amp;").replace('"', "&quot;")
+        ))
+    }
+
+    /// Returns `true` if the element is, or contains, a reference to a note
+    fn is_noteref(&self, key: &Key) -> bool {
+        self.links
+            .get(key)
+            .is_some_and(|target| self.notes.contains_key(target))
+    }
+
+    fn block(&mut self, base: &ArchivePath, element: &Element) -> String {
+        let name = element.name();
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let text = self.inline_content(base, element).replace(HARD_BREAK, " ");
+                if text.is_empty() {
+                    return text;
+                }
+                let level = usize::from(name.as_bytes()[1] - b'0');
+                format!("{} {}", "#".repeat(level), text)
+            }
+            "p" => self.inline_content(base, element),
+            "ul" | "ol" => self.list(base, element),
+            "blockquote" => quote(&self.container(base, element, false).join("\n\n")),
+            "pre" => code_block(element),
+            "table" => self.table(base, element),
+            "dl" => self.definitions(base, element),
+            "hr" => "---".to_string(),
+            _ => self.container(base, element, false).join("\n\n"),
+        }
+    }
+
+    /// Returns the content of the element as a paragraph
+    fn inline_content(&mut self, base: &ArchivePath, element: &Element) -> String {
+        let mut inline = Inline::default();
+        if let Some(anchor) = self.anchor(base, element) {
+            inline.raw(&anchor);
+        }
+        self.children(base, element, &mut inline);
+        inline.finish()
+    }
+
+    fn children(&mut self, base: &ArchivePath, element: &Element, out: &mut Inline) {
+        for node in &element.children {
+            match node {
+                Node::Text(text) => out.text(text),
+                Node::Element(child) => self.inline(base, child, out),
+            }
+        }
+    }
+
+    fn inline(&mut self, base: &ArchivePath, element: &Element, out: &mut Inline) {
+        if self.is_left_out(base, element) {
+            return;
+        }
+        let name = element.name();
+        if name != "a" {
+            if let Some(anchor) = self.anchor(base, element) {
+                out.raw(&anchor);
+            }
+        }
+        match name.as_str() {
+            "br" => out.raw(HARD_BREAK),
+            "em" | "i" | "cite" | "dfn" | "var" => self.delimited(base, element, out, "*"),
+            "strong" | "b" => self.delimited(base, element, out, "**"),
+            "del" | "s" | "strike" => self.delimited(base, element, out, "~~"),
+            "code" | "kbd" | "samp" | "tt" => out.raw(&code_span(&text_content(element))),
+            "sup" | "sub" => {
+                let mut inner = Inline::default();
+                self.children(base, element, &mut inner);
+                let inner = inner.finish();
+                // the number of a note is replaced by its footnote
+                if inner.starts_with("[^") && !inner.contains(' ') {
+                    out.raw(&inner);
+                } else if !inner.is_empty() {
+                    out.raw(&format!("<{0}>{1}</{0}>", name, inner));
+                }
+            }
+            "a" => self.link(base, element, out),
+            "img" => {
+                let alt = element.attr("alt").unwrap_or_default();
+                self.image(base, element.attr("src"), alt, element.attr("title"), out);
+            }
+            "svg" => self.svg_images(base, element, out),
+            _ if is_block(element) => {
+                out.text(" ");
+                self.children(base, element, out);
+                out.text(" ");
+            }
+            _ => self.children(base, element, out),
+        }
+    }
+
+    /// Writes the content of the element between the `delimiter`, with the
+    /// whitespace at its ends outside
+    fn delimited(
+        &mut self,
+        base: &ArchivePath,
+        element: &Element,
+        out: &mut Inline,
+        delimiter: &str,
+    ) {
+        let mut inner = Inline::default();
+        self.children(base, element, &mut inner);
+        out.wrap(&inner.markdown, |content| {
+            format!("{}{}{}", delimiter, content, delimiter)
+        });
+    }
+
+    fn link(&mut self, base: &ArchivePath, element: &Element, out: &mut Inline) {
+        let href = element.attr("href");
+        if let Some((path, Some(fragment))) = href.and_then(|href| archive_reference(base, href)) {
+            let key = (path, fragment_id(fragment));
+            if self.notes.contains_key(&key) {
+                let footnote = self.footnote(&key);
+                out.raw(&footnote);
+                return;
+            }
+            // the link back from a note
+            if self.is_noteref(&key) {
+                return;
+            }
+        }
+        let mut inner = Inline::default();
+        if let Some(anchor) = self.anchor(base, element) {
+            out.raw(&anchor);
+        }
+        self.children(base, element, &mut inner);
+        let url = href.and_then(|href| self.url(base, href));
+        out.wrap(&inner.markdown, |content| {
+            url.as_ref().map_or_else(
+                || content.to_string(),
+                |url| format!("[{}]({})", content, destination(url)),
+            )
+        });
+    }
+
+    /// Returns the url replacing the `href` found in the chapter `base`: a
+    /// link to the file of the exported chapters, the url of the asset for
+    /// the other resources, or [`None`] for the chapters left out and the
+    /// scripts
+    fn url(&mut self, base: &ArchivePath, href: &str) -> Option<String> {
+        if is_script_url(href) {
+            return None;
+        }
+        let Some((path, fragment)) = archive_reference(base, href) else {
+            return Some(href.to_string());
+        };
+        let file = fragment
+            .and_then(|fragment| self.id_files.get(&(path.clone(), fragment_id(fragment))))
+            .or_else(|| self.chapter_files.get(&path));
+        let mut url = if let Some(file) = file {
+            if *file == self.current && fragment.is_some() {
+                String::new()
+            } else {
+                self.files[*file].path.clone()
+            }
+        } else if self.spine.contains(&path) {
+            return None;
+        } else {
+            match self.assets.url(self.doc, &path) {
+                Some(url) => url,
+                None => return Some(href.to_string()),
+            }
+        };
+        if let Some(fragment) = fragment {
+            url.push('#');
+            url.push_str(fragment);
+        }
+        Some(url)
+    }
+
+    /// Returns the reference to the footnote of the note `key` in the
+    /// current file, adding it the first time
+    fn footnote(&mut self, key: &Key) -> String {
+        let file = self.current;
+        if let Some(number) = self.files[file].numbers.get(key) {
+            return format!("[^{}]", number);
+        }
+        let number = self.files[file].numbers.len() + 1;
+        self.files[file].numbers.insert(key.clone(), number);
+        let slot = self.files[file].footnotes.len();
+        self.files[file].footnotes.push(String::new());
+
+        let note = self.notes[key].clone();
+        let content = self.container(&key.0, &note, false).join("\n\n");
+        self.files[file].footnotes[slot] = indent(&format!("[^{}]: {}", number, content), 4);
+        format!("[^{}]", number)
+    }
+
+    fn image(
+        &mut self,
+        base: &ArchivePath,
+        src: Option<&str>,
+        alt: &str,
+        title: Option<&str>,
+        out: &mut Inline,
+    ) {
+        let Some(url) = src.and_then(|src| self.url(base, src)) else {
+            return;
+        };
+        let mut alt_text = Inline::default();
+        alt_text.text(alt);
+        let mut image = format!("![{}]({}", alt_text.finish(), destination(&url));
+        if let Some(title) = title.filter(|title| !title.is_empty()) {
+            image.push_str(" \"");
+            image.push_str(&title.replace('\\', "\\\\").replace('"', "\\\""));
+            image.push('"');
+        }
+        image.push(')');
+        out.raw(&image);
+    }
+
+    /// Writes the `<image>`s of an svg, like the covers
+    fn svg_images(&mut self, base: &ArchivePath, element: &Element, out: &mut Inline) {
+        for child in child_elements(element) {
+            if child.name() == "image" {
+                let href = child
+                    .attr_ns(XLINK_NAMESPACE, "href")
+                    .or_else(|| child.attr("href"));
+                self.image(base, href, "", None, out);
+            } else {
+                self.svg_images(base, child, out);
+            }
+        }
+    }
+
+    fn list(&mut self, base: &ArchivePath, list: &Element) -> String {
+        let ordered = list.name() == "ol";
+        let mut number: usize = list
+            .attr("start")
+            .and_then(|start| start.trim().parse().ok())
+            .unwrap_or(1);
+        let mut items: Vec<String> = vec![];
+        // the width of the marker of the last item
+        let mut width = 0;
+        for item in child_elements(list) {
+            if self.is_left_out(base, item) {
+                continue;
+            }
+            let content = match item.name().as_str() {
+                "li" => self.container(base, item, false).join("\n\n"),
+                // a list directly in a list belongs to the previous item
+                "ul" | "ol" if !items.is_empty() => {
+                    let nested = self.list(base, item);
+                    if let Some(last) = items.last_mut() {
+                        last.push('\n');
+                        last.push_str(&indent(&format!("{}{}", " ".repeat(width), nested), width));
+                    }
+                    continue;
+                }
+                "ul" | "ol" => self.list(base, item),
+                _ => continue,
+            };
+            let marker = if ordered {
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+            number += 1;
+            width = marker.len();
+            if content.is_empty() {
+                items.push(marker.trim_end().to_string());
+            } else {
+                items.push(indent(&format!("{}{}", marker, content), width));
+            }
+        }
+        items.join("\n")
+    }
+
+    /// Returns the table as a GitHub table, its first row as the header
+    fn table(&mut self, base: &ArchivePath, table: &Element) -> String {
+        let mut caption = String::new();
+        let mut rows = vec![];
+        for child in child_elements(table) {
+            match child.name().as_str() {
+                "caption" => caption = self.inline_content(base, child),
+                "thead" | "tbody" | "tfoot" => {
+                    for row in child_elements(child).filter(|row| row.name() == "tr") {
+                        rows.push(self.row(base, row));
+                    }
+                }
+                "tr" => rows.push(self.row(base, child)),
+                _ => {}
+            }
+        }
+        let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+        if columns == 0 {
+            return caption;
+        }
+
+        let mut lines = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            let cells: Vec<&str> = (0..columns)
+                .map(|column| row.get(column).map_or("", String::as_str))
+                .collect();
+            lines.push(format!("| {} |", cells.join(" | ")));
+            if i == 0 {
+                lines.push(format!("|{}|", vec![" --- "; columns].join("|")));
+            }
+        }
+        let table = lines.join("\n");
+        if caption.is_empty() {
+            table
+        } else {
+            format!("{}\n\n{}", caption, table)
+        }
+    }
+
+    /// Returns the cells of the row on a single line, with the spanned
+    /// columns empty
+    fn row(&mut self, base: &ArchivePath, row: &Element) -> Vec<String> {
+        let mut cells = vec![];
+        for cell in child_elements(row) {
+            if !matches!(cell.name().as_str(), "td" | "th") {
+                continue;
+            }
+            let content = self.container(base, cell, false).join("<br>");
+            cells.push(
+                content
+                    .replace(HARD_BREAK, "<br>")
+                    .replace('\n', " ")
+                    .replace('|', "\\|"),
+            );
+            let span: usize = cell
+                .attr("colspan")
+                .and_then(|span| span.trim().parse().ok())
+                .unwrap_or(1);
+            for _ in 1..span.min(1000) {
+                cells.push(String::new());
+            }
+        }
+        cells
+    }
+
+    /// Returns the terms of a definition list in bold, followed by their
+    /// definitions
+    fn definitions(&mut self, base: &ArchivePath, list: &Element) -> String {
+        let mut blocks = vec![];
+        for child in child_elements(list) {
+            match child.name().as_str() {
+                "dt" => {
+                    let term = self.inline_content(base, child);
+                    if !term.is_empty() {
+                        blocks.push(format!("**{}**", term));
+                    }
+                }
+                "dd" => blocks.extend(self.container(base, child, false)),
+                _ => {}
+            }
+        }
+        blocks.join("\n\n")
+    }
+}
+
+/// The inline content of a block being written
+#[derive(Default)]
+struct Inline {
+    markdown: String,
+}
+
+impl Inline {
+    /// Writes the `text` escaped, with its whitespace collapsed
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c') {
+                if !self.markdown.ends_with([' ', '\n']) {
+                    self.markdown.push(' ');
+                }
+            } else {
+                if ESCAPED.contains(&c) {
+                    self.markdown.push('\\');
+                }
+                self.markdown.push(c);
+            }
+        }
+    }
+
+    fn raw(&mut self, markdown: &str) {
+        self.markdown.push_str(markdown);
+    }
+
+    /// Writes the `inner` Markdown formatted by `format`, with the
+    /// whitespace at its ends outside
+    fn wrap(&mut self, inner: &str, format: impl Fn(&str) -> String) {
+        let content = inner.trim_matches(' ');
+        if inner.starts_with(' ') {
+            self.text(" ");
+        }
+        if !content.is_empty() {
+            self.raw(&format(content));
+        }
+        if inner.ends_with(' ') && !content.is_empty() {
+            self.text(" ");
+        }
+    }
+
+    /// Returns the paragraph, without the whitespace and line breaks at its
+    /// ends, and with its lines escaped where they would start a block
+    fn finish(self) -> String {
+        let mut markdown = self.markdown.trim_matches(' ');
+        loop {
+            if let Some(rest) = markdown.strip_suffix(HARD_BREAK) {
+                markdown = rest.trim_end_matches(' ');
+            } else if let Some(rest) = markdown.strip_prefix(HARD_BREAK) {
+                markdown = rest.trim_start_matches(' ');
+            } else {
+                break;
+            }
+        }
+        markdown
+            .split('\n')
+            .map(escape_line_start)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Returns the `line` with its first character escaped if it would start a
+/// block, like a heading or a list item
+fn escape_line_start(line: &str) -> String {
+    if line.starts_with(['#', '>', '+', '-', '=']) {
+        return format!("\\{}", line);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && line[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+/// Returns the `text` as a code span, with its whitespace collapsed
+fn code_span(text: &str) -> String {
+    let code = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if code.is_empty() {
+        return code;
+    }
+    let fence = "`".repeat(longest_run(&code, '`') + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+/// Returns the `<pre>` as a fenced code block, with the language of the
+/// `language-*` class of its `<code>`
+fn code_block(pre: &Element) -> String {
+    let code = text_content(pre);
+    // like in html, the first newline is ignored
+    let code = code
+        .strip_prefix('\n')
+        .unwrap_or(&code)
+        .trim_end_matches('\n');
+    let language = child_elements(pre)
+        .find(|child| child.name() == "code")
+        .and_then(|code| code.attr("class"))
+        .and_then(|class| {
+            class.split_whitespace().find_map(|c| {
+                c.strip_prefix("language-")
+                    .or_else(|| c.strip_prefix("lang-"))
+            })
+        })
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+    format!("{}{}\n{}\n{}", fence, language, code, fence)
+}
+
+/// Returns the length of the longest run of `c` in the `text`
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Returns the text of the element and its descendants, with the line
+/// breaks
+fn text_content(element: &Element) -> String {
+    let mut content = String::new();
+    for node in &element.children {
+        match node {
+            Node::Text(text) => content.push_str(text),
+            Node::Element(child) if child.name() == "br" => content.push('\n'),
+            Node::Element(child) => content.push_str(&text_content(child)),
+        }
+    }
+    content
+}
+
+/// Returns the `markdown` as a block quote
+fn quote(markdown: &str) -> String {
+    markdown
+        .split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                ">".to_string()
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the `markdown` with the lines after the first indented by
+/// `width`
+fn indent(markdown: &str, width: usize) -> String {
+    let indentation = " ".repeat(width);
+    let mut lines = markdown.split('\n');
+    let mut indented = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        indented.push('\n');
+        if !line.is_empty() {
+            indented.push_str(&indentation);
+        }
+        indented.push_str(line);
+    }
+    indented
+}
+
+/// Returns the `url` as a link destination
+fn destination(url: &str) -> String {
+    if url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+/// Returns the id targeted by the fragment of an url
+fn fragment_id(fragment: &str) -> String {
+    percent_decode_str(fragment)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Returns `true` if the element is a footnote or an endnote, rather than a
+/// list of them or a reference to one
+fn is_note_body(element: &Element) -> bool {
+    is_note(element)
+        && !element
+            .epub_types()
+            .chain(element.attr("role").unwrap_or_default().split_whitespace())
+            .any(|t| t.ends_with("noteref") || t.ends_with("notes"))
+}
+
+/// Returns the `label` of an entry of the table of contents as a file name
+fn slug(label: &str) -> String {
+    let mut slug = String::new();
+    for c in label.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Collects the path, id and label of the `points` and their children, in
+/// order
+fn toc_entries(points: &[NavPoint], entries: &mut Vec<(ArchivePath, Option<String>, String)>) {
+    for point in points {
+        let content = point.content.to_string_lossy();
+        let (path, fragment) = match content.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment_id(fragment))),
+            None => (content.as_ref(), None),
+        };
+        if let Ok(path) = ArchivePath::new(path) {
+            entries.push((path, fragment, point.label.clone()));
+        }
+        toc_entries(&point.children, entries);
+    }
+}
@@ -0,0 +1,322 @@
+//! A scanner of the tags of a xml document, with the spans of their
+//! attributes, to patch a document without reformatting it.
+//!
+//! The xml documents are expected to be well-formed, checked by the parser
+//! before. The html ones follow the tokenization rules of HTML5 closely
+//! enough to find the tags: unquoted values, and no tags in the content of
+//! the raw text elements like `<script>`. The comments, processing
+//! instructions, CDATA sections and the doctype are skipped, and the text
+//! between the tags is left to the caller.
+
+use std::ops::Range;
+
+/// A start or end tag
+pub struct Tag<'a> {
+    /// The span of the whole tag, from `<` to `>` included
+    pub span: Range<usize>,
+    /// The qualified name, like `svg:image`
+    pub name: &'a str,
+    pub kind: TagKind<'a>,
+}
+
+pub enum TagKind<'a> {
+    Start {
+        attrs: Vec<Attr<'a>>,
+        /// `true` for the empty element tags, like `<br/>`
+        empty: bool,
+    },
+    End,
+}
+
+impl Tag<'_> {
+    /// Returns the name without the prefix
+    pub fn local_name(&self) -> &str {
+        self.name
+            .split_once(':')
+            .map_or(self.name, |(_, local)| local)
+    }
+}
+
+/// An attribute of a start tag
+pub struct Attr<'a> {
+    /// The qualified name, like `xlink:href`
+    pub name: &'a str,
+    /// The span of the value, without the quotes
+    pub value: Range<usize>,
+    /// The quote around the value, `"` or `'`, or [`None`] if unquoted
+    pub quote: Option<char>,
+}
+
+impl Attr<'_> {
+    /// Returns the `value` escaped to replace the value of the attribute,
+    /// quoted if it wasn't
+    pub fn escape(&self, value: &str) -> String {
+        self.quote.map_or_else(
+            || format!("\"{}\"", escape_attr(value, '"')),
+            |quote| escape_attr(value, quote),
+        )
+    }
+}
+
+/// The html elements whose content is text, even if it looks like a tag
+const RAW_TEXT: &[&str] = &[
+    "script", "style", "textarea", "title", "xmp", "iframe", "noembed", "noframes",
+];
+
+/// Iterates over the tags of a document
+pub struct Scanner<'a> {
+    text: &'a str,
+    position: usize,
+    /// `true` to follow the html rules
+    html: bool,
+    /// The raw text element whose content is next
+    raw_text: Option<&'a str>,
+}
+
+impl<'a> Scanner<'a> {
+    pub const fn new(text: &'a str, html: bool) -> Self {
+        Self {
+            text,
+            position: 0,
+            html,
+            raw_text: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    /// Moves after the next `pattern`, or to the end if there's none
+    fn skip_past(&mut self, pattern: &str) {
+        self.position = self.text[self.position..]
+            .find(pattern)
+            .map_or(self.text.len(), |i| self.position + i + pattern.len());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(char::is_ascii_whitespace) {
+            self.position += c.len_utf8();
+        }
+    }
+
+    /// Consumes a name, ending at a whitespace, `=`, `/` or `>`
+    fn name(&mut self) -> &'a str {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_whitespace() || matches!(c, '=' | '/' | '>') {
+                break;
+            }
+            self.position += c.len_utf8();
+        }
+        &self.text[start..self.position]
+    }
+
+    /// Moves to the end tag of the raw text element `name`
+    fn skip_raw_text(&mut self, name: &str) {
+        let rest = &self.text[self.position..];
+        self.position += rest
+            .match_indices("</")
+            .map(|(i, _)| i)
+            .find(|i| {
+                rest[i + 2..]
+                    .get(..name.len())
+                    .is_some_and(|n| n.eq_ignore_ascii_case(name))
+            })
+            .unwrap_or(rest.len());
+    }
+
+    /// Skips a doctype, with its internal subset
+    fn skip_declaration(&mut self) {
+        let mut brackets = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                '"' | '\'' => {
+                    self.position += 1;
+                    self.skip_past(if c == '"' { "\"" } else { "'" });
+                    continue;
+                }
+                '<' if self.text[self.position..].starts_with("<!--") => {
+                    self.skip_past("-->");
+                    continue;
+                }
+                '[' => brackets += 1,
+                ']' => brackets = brackets.saturating_sub(1),
+                '>' if brackets == 0 => {
+                    self.position += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.position += c.len_utf8();
+        }
+    }
+
+    /// Consumes the attributes of a start tag and its end, returning the
+    /// attributes and whether it's an empty element tag
+    fn attributes(&mut self) -> (Vec<Attr<'a>>, bool) {
+        let mut attrs = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return (attrs, false),
+                Some('>') => {
+                    self.position += 1;
+                    return (attrs, false);
+                }
+                Some('/') => {
+                    self.position += 1;
+                    if self.peek() == Some('>') {
+                        self.position += 1;
+                        return (attrs, true);
+                    }
+                }
+                Some(_) => {
+                    let start = self.position;
+                    let name = self.name();
+                    self.skip_whitespace();
+                    if self.peek() == Some('=') {
+                        self.position += 1;
+                        self.skip_whitespace();
+                        if let Some(quote @ ('"' | '\'')) = self.peek() {
+                            self.position += 1;
+                            let value_start = self.position;
+                            let end = self.text[value_start..]
+                                .find(quote)
+                                .map_or(self.text.len(), |i| value_start + i);
+                            self.position = (end + 1).min(self.text.len());
+                            attrs.push(Attr {
+                                name,
+                                value: value_start..end,
+                                quote: Some(quote),
+                            });
+                        } else {
+                            let value_start = self.position;
+                            let end = self.text[value_start..]
+                                .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                                .map_or(self.text.len(), |i| value_start + i);
+                            self.position = end;
+                            attrs.push(Attr {
+                                name,
+                                value: value_start..end,
+                                quote: None,
+                            });
+                        }
+                    }
+                    if self.position == start {
+                        // not an attribute, moving on
+                        self.position += self.peek().map_or(1, char::len_utf8);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        if let Some(name) = self.raw_text.take() {
+            self.skip_raw_text(name);
+        }
+        loop {
+            let start = self.position + self.text[self.position..].find('<')?;
+            let rest = &self.text[start..];
+            self.position = start;
+            let markup = |c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?');
+            if self.html && !rest[1..].starts_with(markup) {
+                // a `<` in the text, like `1 < 2`
+                self.position += 1;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->");
+            } else if rest.starts_with("<![CDATA[") {
+                self.skip_past("]]>");
+            } else if rest.starts_with("<?") {
+                // a bogus comment in html
+                self.skip_past(if self.html { ">" } else { "?>" });
+            } else if rest.starts_with("<!") {
+                self.skip_declaration();
+            } else if rest.starts_with("</") {
+                self.position += 2;
+                let name = self.name();
+                self.skip_past(">");
+                return Some(Tag {
+                    span: start..self.position,
+                    name,
+                    kind: TagKind::End,
+                });
+            } else {
+                self.position += 1;
+                let name = self.name();
+                let (attrs, mut empty) = self.attributes();
+                if self.html && RAW_TEXT.iter().any(|e| e.eq_ignore_ascii_case(name)) {
+                    // `/>` doesn't end the html elements
+                    empty = false;
+                    self.raw_text = Some(name);
+                }
+                return Some(Tag {
+                    span: start..self.position,
+                    name,
+                    kind: TagKind::Start { attrs, empty },
+                });
+            }
+        }
+    }
+}
+
+/// Copies a text with some of its spans replaced, in order
+pub struct Patch<'a> {
+    text: &'a str,
+    out: String,
+    /// The text before this position is already in `out`
+    copied: usize,
+}
+
+impl<'a> Patch<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            out: String::with_capacity(text.len()),
+            copied: 0,
+        }
+    }
+
+    /// Replaces the `span`, that must start after the previous ones
+    pub fn replace(&mut self, span: Range<usize>, with: &str) {
+        self.out.push_str(&self.text[self.copied..span.start]);
+        self.out.push_str(with);
+        self.copied = span.end;
+    }
+
+    pub fn insert(&mut self, position: usize, with: &str) {
+        self.replace(position..position, with);
+    }
+
+    pub fn finish(mut self) -> String {
+        self.out.push_str(&self.text[self.copied..]);
+        self.out
+    }
+}
+
+/// Escapes a text to be used in an attribute value between `quote`
+pub fn escape_attr(value: &str, quote: char) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' if quote == '"' => escaped.push_str("&quot;"),
+            '\'' if quote == '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes a text to be used as character data
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace("]]>", "]]&gt;")
+}
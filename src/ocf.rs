@@ -0,0 +1,222 @@
+//! Optional files of the `META-INF` directory of the container.
+//!
+//! Besides `container.xml` and `encryption.xml`, the OCF spec defines
+//! `signatures.xml`, `rights.xml`, `metadata.xml` and `manifest.xml`. They
+//! can be read with the accessors of [`EpubDoc`](crate::doc::EpubDoc), like
+//! [`EpubDoc::signatures`](crate::doc::EpubDoc::signatures). The paths of
+//! all the files of the `META-INF` directory are found here.
+
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+use crate::archive::ArchivePath;
+use crate::xmlutils::XMLNode;
+
+/// Path of the container file in the archive
+pub const CONTAINER_FILE: &str = "META-INF/container.xml";
+
+/// Path of the encryption file in the archive
+pub const ENCRYPTION_FILE: &str = "META-INF/encryption.xml";
+
+/// Path of the signatures file in the archive
+pub const SIGNATURES_FILE: &str = "META-INF/signatures.xml";
+
+/// Path of the rights file in the archive
+pub const RIGHTS_FILE: &str = "META-INF/rights.xml";
+
+/// Path of the container metadata file in the archive
+pub const METADATA_FILE: &str = "META-INF/metadata.xml";
+
+/// Path of the ODF manifest file in the archive
+pub const MANIFEST_FILE: &str = "META-INF/manifest.xml";
+
+/// Path of the Readium LCP license in the archive
+pub const LCP_LICENSE_FILE: &str = "META-INF/license.lcpl";
+
+/// Path of the Apple Books DRM info in the archive
+pub const FAIRPLAY_FILE: &str = "META-INF/sinf.xml";
+
+/// A XML-DSig signature of `META-INF/signatures.xml`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// The `Id` attribute of the signature
+    pub id: Option<String>,
+    /// The references signed, from `SignedInfo` and the `Manifest` objects
+    pub references: Vec<SignatureReference>,
+}
+
+/// A reference to the signed data, with its digest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureReference {
+    /// The `URI` attribute, a path in the archive or a `#id` in the
+    /// signatures file
+    pub uri: String,
+    /// Uri of the digest algorithm
+    pub digest_method: String,
+    /// The expected digest, base64 encoded
+    pub digest_value: String,
+    /// Uris of the transforms applied to the data before the digest
+    pub transforms: Vec<String>,
+}
+
+impl SignatureReference {
+    /// Returns the path of the archive entry referenced, or [`None`] if the
+    /// reference points inside the signatures file or outside the archive
+    #[must_use]
+    pub fn path(&self) -> Option<ArchivePath> {
+        if self.uri.is_empty() || self.uri.starts_with('#') {
+            return None;
+        }
+        ArchivePath::root().join(&self.uri).ok()
+    }
+
+    /// Checks the digest of the referenced `data`
+    ///
+    /// Transforms, like XML canonicalization, aren't supported, the digest
+    /// of a reference with transforms is [`DigestStatus::Unsupported`].
+    #[must_use]
+    pub fn verify(&self, data: &[u8]) -> DigestStatus {
+        if !self.transforms.is_empty() {
+            return DigestStatus::Unsupported;
+        }
+        let Some(digest) = digest(&self.digest_method, data) else {
+            return DigestStatus::Unsupported;
+        };
+        let value: String = self
+            .digest_value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        match base64::engine::general_purpose::STANDARD.decode(value) {
+            Ok(expected) if expected == digest => DigestStatus::Valid,
+            _ => DigestStatus::Mismatch,
+        }
+    }
+}
+
+/// The result of checking the digest of a [`SignatureReference`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DigestStatus {
+    /// The digest matches the referenced data
+    Valid,
+    /// The digest doesn't match, the data was modified
+    Mismatch,
+    /// The referenced entry isn't in the archive
+    Missing,
+    /// The digest algorithm or the transforms aren't supported, or the
+    /// reference isn't an archive entry
+    Unsupported,
+}
+
+/// A [`SignatureReference`] with the result of checking its digest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedReference {
+    pub reference: SignatureReference,
+    pub status: DigestStatus,
+}
+
+/// The `META-INF/rights.xml` file. Its format depends on the DRM scheme,
+/// so only the root element is parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rights {
+    /// Local name of the root element
+    pub element: String,
+    /// Namespace of the root element, it identifies the DRM scheme
+    pub namespace: Option<String>,
+    /// The whole file content
+    pub content: String,
+}
+
+/// An entry of the ODF `META-INF/manifest.xml` file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The `full-path` attribute, `/` for the container itself
+    pub full_path: String,
+    /// The `media-type` attribute
+    pub media_type: String,
+}
+
+fn digest(method: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let digest = match method {
+        "http://www.w3.org/2000/09/xmldsig#sha1" => Sha1::digest(data).to_vec(),
+        "http://www.w3.org/2001/04/xmldsig-more#sha224" => Sha224::digest(data).to_vec(),
+        "http://www.w3.org/2001/04/xmlenc#sha256" => Sha256::digest(data).to_vec(),
+        "http://www.w3.org/2001/04/xmldsig-more#sha384" => Sha384::digest(data).to_vec(),
+        "http://www.w3.org/2001/04/xmlenc#sha512" => Sha512::digest(data).to_vec(),
+        _ => return None,
+    };
+    Some(digest)
+}
+
+/// Reads the signatures of the parsed `META-INF/signatures.xml`
+pub(crate) fn parse_signatures(root: &XMLNode) -> Vec<Signature> {
+    root.children
+        .iter()
+        .filter(|r| r.borrow().name.local_name == "Signature")
+        .map(|r| {
+            let signature = r.borrow();
+            let mut references = vec![];
+            collect_references(&signature, &mut references);
+            Signature {
+                id: signature.get_attr("Id"),
+                references,
+            }
+        })
+        .collect()
+}
+
+fn collect_references(node: &XMLNode, references: &mut Vec<SignatureReference>) {
+    for r in &node.children {
+        let child = r.borrow();
+        if child.name.local_name != "Reference" {
+            collect_references(&child, references);
+            continue;
+        }
+        let digest_method = child
+            .find("DigestMethod")
+            .and_then(|m| m.borrow().get_attr("Algorithm"))
+            .unwrap_or_default();
+        let digest_value = child
+            .find("DigestValue")
+            .and_then(|v| v.borrow().text.clone())
+            .unwrap_or_default();
+        let transforms = child.find("Transforms").map_or_else(Vec::new, |t| {
+            t.borrow()
+                .children
+                .iter()
+                .filter_map(|t| t.borrow().get_attr("Algorithm"))
+                .collect()
+        });
+        references.push(SignatureReference {
+            uri: child.get_attr("URI").unwrap_or_default(),
+            digest_method,
+            digest_value,
+            transforms,
+        });
+    }
+}
+
+/// Reads the parsed `META-INF/rights.xml`
+pub(crate) fn parse_rights(root: &XMLNode, content: String) -> Rights {
+    Rights {
+        element: root.name.local_name.clone(),
+        namespace: root.name.namespace.clone(),
+        content,
+    }
+}
+
+/// Reads the file entries of the parsed `META-INF/manifest.xml`
+pub(crate) fn parse_manifest(root: &XMLNode) -> Vec<ManifestEntry> {
+    root.children
+        .iter()
+        .filter(|r| r.borrow().name.local_name == "file-entry")
+        .filter_map(|r| {
+            let entry = r.borrow();
+            Some(ManifestEntry {
+                full_path: entry.get_attr("full-path")?,
+                media_type: entry.get_attr("media-type").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+//! Synthetic positions, stable "page" numbers for the books without a
+//! page list.
+//!
+//! Like the positions of Readium, every spine item is split in chunks of
+//! [`POSITION_LENGTH`] characters of its [text](EpubDoc::chapter_text),
+//! with at least one position per item, except for the items that can't be
+//! read or parsed, which have none. They don't depend on the font or the
+//! screen size, so they're the same on every device.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let positions = doc.positions().unwrap();
+//! let position = positions.get(3).unwrap();
+//! println!("page {} of {}", position.number, positions.len());
+//!
+//! let cfi = doc.position_cfi(position).unwrap();
+//! assert_eq!(doc.cfi_position(&positions, &cfi).unwrap(), Some(position));
+//! ```
+
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::cfi::Cfi;
+use crate::doc::{skip_broken, DocError, EpubDoc};
+use crate::text::TextOptions;
+
+/// Characters of text in every position
+pub const POSITION_LENGTH: usize = 1024;
+
+/// A position in the book
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The position number, starting from 1
+    pub number: usize,
+    /// The spine item
+    pub spine_index: usize,
+    /// Byte offset in the text of the chapter where the position starts, as
+    /// returned by [`EpubDoc::chapter_text`]
+    pub text_offset: usize,
+}
+
+/// The positions of a book, in reading order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Positions {
+    positions: Vec<Position>,
+}
+
+impl Positions {
+    /// Returns the number of positions
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the position `number`, starting from 1
+    #[must_use]
+    pub fn get(&self, number: usize) -> Option<&Position> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.positions.get(index))
+    }
+
+    /// Returns the position containing the `text_offset` of the spine item
+    #[must_use]
+    pub fn find(&self, spine_index: usize, text_offset: usize) -> Option<&Position> {
+        let end = self
+            .positions
+            .partition_point(|p| (p.spine_index, p.text_offset) <= (spine_index, text_offset));
+        end.checked_sub(1)
+            .map(|index| &self.positions[index])
+            .filter(|p| p.spine_index == spine_index)
+    }
+
+    /// Returns the positions of the spine item
+    pub fn chapter(&self, spine_index: usize) -> impl Iterator<Item = &Position> {
+        self.positions
+            .iter()
+            .filter(move |p| p.spine_index == spine_index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Position> {
+        self.positions.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Positions {
+    type Item = &'a Position;
+    type IntoIter = std::slice::Iter<'a, Position>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Returns the positions of every spine item. The spine items that
+    /// can't be read or parsed are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn positions(&mut self) -> Result<Positions, DocError> {
+        let mut positions = vec![];
+        for spine_index in 0..self.spine.len() {
+            let Some(text) = skip_broken(self.chapter_text(spine_index))? else {
+                continue;
+            };
+            let offsets = text
+                .char_indices()
+                .step_by(POSITION_LENGTH)
+                .map(|(offset, _)| offset);
+            let first = positions.len();
+            for text_offset in offsets {
+                positions.push(Position {
+                    number: positions.len() + 1,
+                    spine_index,
+                    text_offset,
+                });
+            }
+            if positions.len() == first {
+                positions.push(Position {
+                    number: first + 1,
+                    spine_index,
+                    text_offset: 0,
+                });
+            }
+        }
+        Ok(Positions { positions })
+    }
+
+    /// Returns the CFI of the start of the `position`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chapter can't be read or parsed.
+    pub fn position_cfi(&mut self, position: &Position) -> Result<Cfi, DocError> {
+        self.generate_text_cfi(position.spine_index, position.text_offset)
+    }
+
+    /// Returns the position containing the location of the `cfi`, or the
+    /// start of a range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the CFI can't be resolved.
+    pub fn cfi_position<'a>(
+        &mut self,
+        positions: &'a Positions,
+        cfi: &Cfi,
+    ) -> Result<Option<&'a Position>, DocError> {
+        let location = self.resolve_cfi(cfi)?;
+        Ok(positions.find(location.spine_index, location.text_offset))
+    }
+
+    /// Returns the position of a resource path with an optional fragment,
+    /// like the [`NavPoint`](crate::doc::NavPoint) content. The fragment,
+    /// percent-decoded, can be an element id or a CFI. Returns [`None`] if
+    /// the resource isn't in the spine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chapter can't be read or parsed, or the CFI
+    /// can't be resolved.
+    pub fn fragment_position<'a>(
+        &mut self,
+        positions: &'a Positions,
+        path: &Path,
+    ) -> Result<Option<&'a Position>, DocError> {
+        let path = path.to_string_lossy();
+        let (resource, fragment) = path.split_once('#').unwrap_or((&path, ""));
+        let Some(spine_index) = self.resource_uri_to_chapter(&resource.into()) else {
+            return Ok(None);
+        };
+        let fragment = percent_encoding::percent_decode_str(fragment).decode_utf8_lossy();
+
+        if fragment.starts_with("epubcfi(") {
+            return self.cfi_position(positions, &Cfi::parse(&fragment)?);
+        }
+        let extract = self.chapter_extract(spine_index, TextOptions::default())?;
+        let text_offset = extract
+            .elements
+            .iter()
+            .find(|e| !fragment.is_empty() && e.id.as_deref() == Some(&*fragment))
+            .map_or(0, |e| e.offset);
+        Ok(positions.find(spine_index, text_offset))
+    }
+
+    /// Changes the current chapter to the one of the `position`, and the
+    /// current location to its start. The renderer can then scroll to
+    /// [`Self::get_current_cfi`].
+    ///
+    /// Returns [`false`] if the position is out of bounds
+    pub fn set_current_position(&mut self, position: &Position) -> bool {
+        if !self.set_current_page(position.spine_index) {
+            return false;
+        }
+        self.current_offset = position.text_offset;
+        true
+    }
+
+    /// Returns the position of the current location, the start of the
+    /// current chapter unless it was set with [`Self::set_current_position`]
+    #[must_use]
+    pub fn get_current_position<'a>(&self, positions: &'a Positions) -> Option<&'a Position> {
+        positions.find(self.get_current_page(), self.current_offset)
+    }
+
+    /// Returns the CFI of the current location, see
+    /// [`Self::get_current_position`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chapter can't be read or parsed.
+    pub fn get_current_cfi(&mut self) -> Result<Cfi, DocError> {
+        self.generate_text_cfi(self.get_current_page(), self.current_offset)
+    }
+}
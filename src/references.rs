@@ -0,0 +1,90 @@
+//! The references to other resources in a xhtml document: the url
+//! attributes, `srcset`, and the `url()` and `@import` of the styles.
+
+use xml::name::Name;
+
+use crate::css;
+
+pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+pub const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+pub const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// Returns the `value` of the attribute with its references replaced by
+/// `rewrite`, or the value unchanged if the attribute isn't a reference
+pub fn rewrite_attr(
+    element: Name,
+    attr: Name,
+    value: &str,
+    rewrite: &dyn Fn(&str) -> String,
+) -> String {
+    let element_name = element.local_name.to_ascii_lowercase();
+    let svg = element.namespace == Some(SVG_NAMESPACE);
+
+    if attr.namespace == Some(XLINK_NAMESPACE) && attr.local_name == "href" {
+        return rewrite_link(svg && element_name != "a", value, rewrite);
+    }
+    if attr.namespace.is_some() {
+        return value.to_string();
+    }
+
+    match (element_name.as_str(), attr.local_name) {
+        (_, "style") => rewrite_css(value, rewrite),
+        (_, "href") if svg => rewrite_link(element_name != "a", value, rewrite),
+        ("a" | "area" | "link" | "image", "href")
+        | (
+            "img" | "source" | "audio" | "video" | "track" | "embed" | "iframe" | "script"
+            | "input" | "frame",
+            "src",
+        )
+        | ("video", "poster")
+        | ("object", "data") => rewrite(value),
+        ("img" | "source", "srcset") => rewrite_srcset(value, rewrite),
+        _ => value.to_string(),
+    }
+}
+
+/// Rewrites a link, leaving the references inside the document untouched
+/// when `local` is `true`
+fn rewrite_link(local: bool, value: &str, rewrite: &dyn Fn(&str) -> String) -> String {
+    if local && value.trim_start().starts_with('#') {
+        value.to_string()
+    } else {
+        rewrite(value)
+    }
+}
+
+/// Rewrites the urls of a `srcset` attribute, keeping the descriptors.
+/// The urls can contain commas, like the `data:` urls, so they end at the
+/// first whitespace.
+pub fn rewrite_srcset(value: &str, rewrite: &dyn Fn(&str) -> String) -> String {
+    let mut candidates = vec![];
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        let candidate = if let Some(url) = url.strip_suffix(',') {
+            rest = after;
+            rewrite(url.trim_end_matches(','))
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            let descriptor = after[..end].trim();
+            rest = &after[end..];
+            if descriptor.is_empty() {
+                rewrite(url)
+            } else {
+                format!("{} {}", rewrite(url), descriptor)
+            }
+        };
+        candidates.push(candidate);
+    }
+    candidates.join(", ")
+}
+
+/// Rewrites the `url()` and `@import` references of a style
+pub fn rewrite_css(css: &str, rewrite: &dyn Fn(&str) -> String) -> String {
+    css::rewrite_references(css, rewrite)
+}
@@ -0,0 +1,245 @@
+//! Full-text search of the book.
+//!
+//! The text of every spine item is extracted once into a [`SearchIndex`],
+//! then searched without reading the archive again.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::search::SearchOptions;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let options = SearchOptions {
+//!     case_insensitive: true,
+//!     ..SearchOptions::default()
+//! };
+//! let hits = doc.search("TODO ES MÍO", options).unwrap();
+//! assert_eq!(hits[0].spine_index, 1);
+//! assert!(hits[0].snippet.contains("Todo es mío"));
+//! ```
+
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+use crate::cfi::{Cfi, Step};
+use crate::doc::{skip_broken, DocError, EpubDoc};
+use crate::text::{Extract, TextOptions};
+
+/// Characters of context kept on each side of a hit in its snippet
+const SNIPPET_CONTEXT: usize = 40;
+
+/// How the query is matched against the text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Ignores the case, `Mío` matches `mío`
+    pub case_insensitive: bool,
+    /// Ignores the accents and other combining marks, `mio` matches `mío`
+    pub diacritic_insensitive: bool,
+    /// Only matches whole words, `mío` doesn't match `míos`
+    pub whole_word: bool,
+}
+
+/// A match of the query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchHit {
+    /// The spine item where the query was found
+    pub spine_index: usize,
+    /// Byte range of the match in the text of the chapter, as returned by
+    /// [`EpubDoc::chapter_text`]
+    pub range: Range<usize>,
+    /// The `id` of the nearest element at or before the match, to link to
+    /// it as a fragment
+    pub element_id: Option<String>,
+    /// The range CFI of the match
+    pub cfi: Cfi,
+    /// The match with some surrounding text, in a single line
+    pub snippet: String,
+}
+
+/// The text of every spine item, ready to be searched
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+    chapters: Vec<Chapter>,
+}
+
+#[derive(Clone, Debug)]
+struct Chapter {
+    spine_index: usize,
+    /// Package document steps of the spine item, for the CFIs
+    steps: Vec<Step>,
+    extract: Extract,
+}
+
+impl SearchIndex {
+    /// Extracts the text of every spine item of the `doc`. The spine items
+    /// that can't be read or parsed, like the missing files, are left out
+    /// of the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the package document can't be read or parsed, or
+    /// if a limit is exceeded.
+    pub fn build<R: Read + Seek>(doc: &mut EpubDoc<R>) -> Result<Self, DocError> {
+        let spine = doc.spine_steps()?;
+        let mut chapters = vec![];
+        for index in 0..doc.spine.len() {
+            let extract = doc.chapter_extract(index, TextOptions::default());
+            let Some(extract) = skip_broken(extract)? else {
+                continue;
+            };
+            chapters.push(Chapter {
+                spine_index: index,
+                steps: spine.get(index).cloned().unwrap_or_default(),
+                extract,
+            });
+        }
+        Ok(Self { chapters })
+    }
+
+    /// Returns every match of the `query`, in reading order. An empty
+    /// query doesn't match anything.
+    #[must_use]
+    pub fn search(&self, query: &str, options: SearchOptions) -> Vec<SearchHit> {
+        let query = normalize(query, options).text;
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut hits = vec![];
+        for chapter in &self.chapters {
+            let extract = &chapter.extract;
+            let text = normalize(&extract.text, options);
+            for range in text.find_all(&query, options.whole_word) {
+                hits.push(SearchHit {
+                    spine_index: chapter.spine_index,
+                    element_id: extract.anchor_at(range.start).map(String::from),
+                    cfi: extract.cfi_range(&chapter.steps, range.clone()),
+                    snippet: snippet(&extract.text, &range),
+                    range,
+                });
+            }
+        }
+        hits
+    }
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Searches the `query` in the whole book, see [`SearchIndex::search`].
+    ///
+    /// The text is extracted on the first search and kept for the next
+    /// ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the package document can't be read or parsed, or
+    /// if a limit is exceeded.
+    pub fn search(
+        &mut self,
+        query: &str,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit>, DocError> {
+        if self.search_index.is_none() {
+            self.search_index = Some(SearchIndex::build(self)?);
+        }
+        Ok(self
+            .search_index
+            .as_ref()
+            .map(|index| index.search(query, options))
+            .unwrap_or_default())
+    }
+}
+
+/// A text folded for matching, with the position of every byte in the
+/// original text
+struct Normalized {
+    text: String,
+    origins: Vec<usize>,
+    source_len: Vec<usize>,
+}
+
+impl Normalized {
+    /// Returns the ranges in the original text of the `query` matches
+    fn find_all(&self, query: &str, whole_word: bool) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        let mut from = 0;
+        while let Some(position) = self.text[from..].find(query) {
+            let start = from + position;
+            let end = start + query.len();
+            if whole_word && !self.is_word(start, end) {
+                let skipped = self.text[start..].chars().next().map_or(1, char::len_utf8);
+                from = start + skipped;
+                continue;
+            }
+            let last = end - 1;
+            ranges.push(self.origins[start]..self.origins[last] + self.source_len[last]);
+            from = end;
+        }
+        ranges
+    }
+
+    /// Returns `true` if the text between `start` and `end` isn't part of
+    /// a longer word
+    fn is_word(&self, start: usize, end: usize) -> bool {
+        let before = self.text[..start].chars().next_back();
+        let after = self.text[end..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    }
+
+    fn push(&mut self, c: char, origin: usize, source_len: usize) {
+        self.text.push(c);
+        for _ in 0..c.len_utf8() {
+            self.origins.push(origin);
+            self.source_len.push(source_len);
+        }
+    }
+}
+
+fn normalize(text: &str, options: SearchOptions) -> Normalized {
+    let mut normalized = Normalized {
+        text: String::with_capacity(text.len()),
+        origins: Vec::with_capacity(text.len()),
+        source_len: Vec::with_capacity(text.len()),
+    };
+    for (origin, c) in text.char_indices() {
+        let len = c.len_utf8();
+        let mut fold = |folded: char| {
+            if options.case_insensitive {
+                for lower in folded.to_lowercase() {
+                    normalized.push(lower, origin, len);
+                }
+            } else {
+                normalized.push(folded, origin, len);
+            }
+        };
+        if options.diacritic_insensitive {
+            decompose_canonical(c, |d| {
+                if !is_combining_mark(d) {
+                    fold(d);
+                }
+            });
+        } else {
+            fold(c);
+        }
+    }
+    normalized
+}
+
+/// Returns the `range` of the `text` with some context around it
+fn snippet(text: &str, range: &Range<usize>) -> String {
+    let start = text[..range.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[range.end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(i, _)| range.end + i);
+    text[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
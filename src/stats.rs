@@ -0,0 +1,218 @@
+//! Word and character counts, and reading time estimates.
+//!
+//! The words are found from the scripts of the text, not from its
+//! language: the language of the book and the `xml:lang` of its documents
+//! aren't used. They are the runs of letters and digits, except for the
+//! scripts written without spaces between words:
+//!
+//! - each Chinese or Japanese character counts as a word, so their reading
+//!   time is better estimated with [`CJK_CHARACTERS_PER_MINUTE`];
+//! - the words of the Thai, Lao, Khmer and Myanmar runs, where the spaces
+//!   separate phrases, are estimated from their length, as there's no
+//!   dictionary to find them.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::stats::WORDS_PER_MINUTE;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let stats = doc.book_stats().unwrap();
+//! println!(
+//!     "{} words, {} min",
+//!     stats.words(),
+//!     stats.reading_time(WORDS_PER_MINUTE).as_secs() / 60
+//! );
+//! ```
+
+use std::io::{Read, Seek};
+use std::time::Duration;
+
+use crate::doc::{skip_broken, DocError, EpubDoc};
+
+/// The average silent reading speed of adults
+pub const WORDS_PER_MINUTE: u32 = 238;
+
+/// The average reading speed of Chinese and Japanese texts, in characters
+/// per minute, to pass as the words per minute since each character counts
+/// as a word
+pub const CJK_CHARACTERS_PER_MINUTE: u32 = 300;
+
+/// The characters per word, vowel signs and tone marks included, estimated
+/// in the scripts written without spaces between words
+const UNSPACED_WORD_LENGTH: usize = 5;
+
+/// Counts of a spine item
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChapterStats {
+    pub spine_index: usize,
+    /// `false` for the spine items with `linear="no"`
+    pub linear: bool,
+    pub words: usize,
+    /// Characters without the whitespace
+    pub characters: usize,
+}
+
+impl ChapterStats {
+    /// Returns the time to read the chapter at `words_per_minute`
+    #[must_use]
+    pub fn reading_time(&self, words_per_minute: u32) -> Duration {
+        reading_time(self.words, words_per_minute)
+    }
+}
+
+/// Counts of every spine item
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BookStats {
+    pub chapters: Vec<ChapterStats>,
+}
+
+impl BookStats {
+    /// Returns the words of the linear spine items
+    #[must_use]
+    pub fn words(&self) -> usize {
+        self.linear().map(|c| c.words).sum()
+    }
+
+    /// Returns the characters of the linear spine items
+    #[must_use]
+    pub fn characters(&self) -> usize {
+        self.linear().map(|c| c.characters).sum()
+    }
+
+    /// Returns the time to read the linear spine items at
+    /// `words_per_minute`
+    #[must_use]
+    pub fn reading_time(&self, words_per_minute: u32) -> Duration {
+        reading_time(self.words(), words_per_minute)
+    }
+
+    fn linear(&self) -> impl Iterator<Item = &ChapterStats> {
+        self.chapters.iter().filter(|c| c.linear)
+    }
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Returns the counts of the spine item `index`, from its
+    /// [text](Self::chapter_text).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, or an error if it can't be read or parsed.
+    pub fn chapter_stats(&mut self, index: usize) -> Result<ChapterStats, DocError> {
+        let text = self.chapter_text(index)?;
+        Ok(ChapterStats {
+            spine_index: index,
+            linear: self.spine[index].linear,
+            words: count_words(&text),
+            characters: count_characters(&text),
+        })
+    }
+
+    /// Returns the counts of every spine item. The spine items that can't
+    /// be read or parsed have no words.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn book_stats(&mut self) -> Result<BookStats, DocError> {
+        let mut chapters = vec![];
+        for index in 0..self.spine.len() {
+            let stats = skip_broken(self.chapter_stats(index))?;
+            chapters.push(stats.unwrap_or(ChapterStats {
+                spine_index: index,
+                linear: self.spine[index].linear,
+                words: 0,
+                characters: 0,
+            }));
+        }
+        Ok(BookStats { chapters })
+    }
+}
+
+/// Returns the number of words of the `text`, found from its scripts, see
+/// the [module](crate::stats) documentation
+///
+/// ```
+/// use epub::stats::count_words;
+///
+/// assert_eq!(count_words("It's a well-known fact."), 4);
+/// assert_eq!(count_words("吾輩は猫である"), 7);
+/// assert_eq!(count_words("Hello 世界"), 3);
+/// assert_eq!(count_words("ภาษาไทยง่ายนิดเดียว"), 4);
+/// ```
+#[must_use]
+pub fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    let mut joiner = false;
+    // the length of the current run of an unspaced script
+    let mut unspaced: usize = 0;
+    for c in text.chars() {
+        if is_unspaced(c) {
+            unspaced += 1;
+            continue;
+        }
+        words += unspaced.div_ceil(UNSPACED_WORD_LENGTH);
+        unspaced = 0;
+        if is_cjk(c) {
+            words += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+            }
+            in_word = true;
+        } else if in_word && !joiner && matches!(c, '\'' | '’' | '-' | '‐') {
+            // "it's" and "well-known" are one word
+            joiner = true;
+            continue;
+        } else {
+            in_word = false;
+        }
+        joiner = false;
+    }
+    words + unspaced.div_ceil(UNSPACED_WORD_LENGTH)
+}
+
+/// Returns the number of characters of the `text`, without whitespace
+#[must_use]
+pub fn count_characters(text: &str) -> usize {
+    text.chars().filter(|c| !c.is_whitespace()).count()
+}
+
+/// Returns `true` for the characters of the scripts written without spaces
+/// between words: Han ideographs, Hiragana and Katakana
+const fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Returns `true` for the characters of the scripts written without spaces
+/// between words, other than [`is_cjk`]: Thai, Lao, Khmer and Myanmar. Their
+/// digits are counted as in the other scripts.
+fn is_unspaced(c: char) -> bool {
+    let script = matches!(c,
+        '\u{0E00}'..='\u{0EFF}'
+        | '\u{1000}'..='\u{109F}'
+        | '\u{1780}'..='\u{17FF}'
+        | '\u{19E0}'..='\u{19FF}'
+        | '\u{A9E0}'..='\u{A9FF}'
+        | '\u{AA60}'..='\u{AA7F}'
+    );
+    script && !c.is_numeric()
+}
+
+fn reading_time(words: usize, words_per_minute: u32) -> Duration {
+    let words = u64::try_from(words).unwrap_or(u64::MAX);
+    Duration::from_millis(words.saturating_mul(60_000) / u64::from(words_per_minute.max(1)))
+}
@@ -0,0 +1,406 @@
+//! Plain text extraction of the chapters, for indexing or text to speech.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::text::TextOptions;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! let text = doc.chapter_text(1).unwrap();
+//! assert!(text.starts_with("Todo es mío"));
+//!
+//! let options = TextOptions {
+//!     skip_notes: true,
+//!     ..TextOptions::default()
+//! };
+//! let book = doc.book_text_with(options).unwrap();
+//! assert!(book.contains(&text));
+//! ```
+
+use std::io::{Read, Seek};
+
+use crate::cfi::Step;
+use crate::doc::{skip_broken, DocError, EpubDoc, ParseMode};
+use crate::dom::{self, Element, Node};
+
+/// What to leave out of the extracted text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Skips the footnotes and endnotes, and the references to them,
+    /// marked with `epub:type` or the equivalent ARIA `role`
+    pub skip_notes: bool,
+    /// Skips the spine items with `linear="no"` in
+    /// [`EpubDoc::book_text_with`]
+    pub skip_non_linear: bool,
+}
+
+/// Elements whose content is never text
+const SKIPPED: &[&str] = &["head", "script", "style", "template"];
+
+/// Elements that start a new line
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// `epub:type` and `role` values of the notes
+const NOTES: &[&str] = &[
+    "footnote",
+    "footnotes",
+    "endnote",
+    "endnotes",
+    "rearnote",
+    "rearnotes",
+    "noteref",
+    "doc-footnote",
+    "doc-endnote",
+    "doc-endnotes",
+    "doc-noteref",
+];
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Returns the plain text of the spine item `index`, see
+    /// [`Self::chapter_text_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, or an error if it can't be read or parsed.
+    pub fn chapter_text(&mut self, index: usize) -> Result<String, DocError> {
+        self.chapter_text_with(index, TextOptions::default())
+    }
+
+    /// Returns the plain text of the spine item `index`.
+    ///
+    /// Paragraphs, headings and the other blocks are separated by line
+    /// breaks, the whitespace inside them is collapsed, except in `<pre>`,
+    /// and scripts and styles are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocError::SpineIndexOutOfBounds`] if there's no such
+    /// chapter, or an error if it can't be read or parsed.
+    pub fn chapter_text_with(
+        &mut self,
+        index: usize,
+        options: TextOptions,
+    ) -> Result<String, DocError> {
+        Ok(self.chapter_extract(index, options)?.text)
+    }
+
+    /// Returns the plain text of the whole book, see [`Self::book_text_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn book_text(&mut self) -> Result<String, DocError> {
+        self.book_text_with(TextOptions::default())
+    }
+
+    /// Returns the plain text of every chapter in the spine, separated by
+    /// an empty line. The chapters that can't be read or parsed are left
+    /// out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a limit is exceeded.
+    pub fn book_text_with(&mut self, options: TextOptions) -> Result<String, DocError> {
+        let mut chapters = vec![];
+        for index in 0..self.spine.len() {
+            if options.skip_non_linear && !self.spine[index].linear {
+                continue;
+            }
+            let Some(text) = skip_broken(self.chapter_text_with(index, options))? else {
+                continue;
+            };
+            if !text.is_empty() {
+                chapters.push(text);
+            }
+        }
+        Ok(chapters.join("\n\n"))
+    }
+
+    /// Extracts the text of the spine item `index` with its anchors
+    pub(crate) fn chapter_extract(
+        &mut self,
+        index: usize,
+        options: TextOptions,
+    ) -> Result<Extract, DocError> {
+        let root = self.chapter_dom(index)?;
+        Ok(element_text(&root, options))
+    }
+
+    /// Parses the spine item `index`, keeping what can be read of a broken
+    /// document unless in strict mode, see [`dom::parse_document`]
+    pub(crate) fn chapter_dom(&mut self, index: usize) -> Result<Element, DocError> {
+        let idref = self.spine.get(index).map(|item| item.idref.clone()).ok_or(
+            DocError::SpineIndexOutOfBounds {
+                index,
+                len: self.spine.len(),
+            },
+        )?;
+        let (path, mime) = self
+            .resources
+            .get(&idref)
+            .map(|r| (r.path.clone(), r.mime.clone()))
+            .ok_or(DocError::ResourceNotFound { id: idref })?;
+        let content = self.try_get_resource_by_path(&path)?;
+        let strict = self.options.mode == ParseMode::Strict;
+        let root = dom::parse_document(&content, &mime, strict, &self.options.limits);
+        Ok(root.map_err(|e| e.in_file(&path))?)
+    }
+}
+
+/// Returns `true` if the element is a note or a reference to a note
+pub(crate) fn is_note(element: &Element) -> bool {
+    element.epub_types().any(|t| NOTES.contains(&t))
+        || element
+            .attr("role")
+            .is_some_and(|role| role.split_whitespace().any(|r| NOTES.contains(&r)))
+}
+
+/// Returns `true` if the element starts a new line of text
+pub(crate) fn is_block(element: &Element) -> bool {
+    BLOCKS.contains(&element.name().as_str())
+}
+
+/// Returns `true` if the element content isn't text
+pub(crate) fn is_skipped(element: &Element) -> bool {
+    SKIPPED.contains(&element.name().as_str())
+}
+
+/// The text of a document, with the positions in the document it comes
+/// from
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Extract {
+    pub text: String,
+    /// Every element extracted, in document order
+    pub elements: Vec<ElementStart>,
+    /// Steps from the root element to every text chunk extracted
+    pub nodes: Vec<Vec<Step>>,
+    /// Runs of text copied unchanged from a text chunk, in order
+    pub segments: Vec<Segment>,
+}
+
+/// An element of the document, with the offset in the text where it starts
+#[derive(Clone, Debug)]
+pub(crate) struct ElementStart {
+    pub offset: usize,
+    pub steps: Vec<Step>,
+    pub id: Option<String>,
+}
+
+/// Text copied unchanged from the chunk `node`, from the UTF-16 offset
+/// `source` of the chunk to the byte `offset` of the text
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Segment {
+    pub offset: usize,
+    pub node: usize,
+    pub source: usize,
+}
+
+impl Extract {
+    /// Returns the id of the last element started at or before `offset`
+    pub fn anchor_at(&self, offset: usize) -> Option<&str> {
+        let index = self.elements.partition_point(|e| e.offset <= offset);
+        self.elements[..index]
+            .iter()
+            .rev()
+            .find_map(|e| e.id.as_deref())
+    }
+}
+
+fn element_text(root: &Element, options: TextOptions) -> Extract {
+    let mut writer = TextWriter::default();
+    writer.element(root, options);
+    writer.finish()
+}
+
+/// The text between two elements, the odd steps of a CFI
+struct Chunk {
+    step: usize,
+    node: Option<usize>,
+    source: usize,
+}
+
+/// Builds the text line by line, collapsing whitespace
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    line: String,
+    pre: usize,
+    steps: Vec<Step>,
+    extract: Extract,
+    /// Node, source and offset of the next character if it continues the
+    /// last segment
+    next: Option<(usize, usize, usize)>,
+}
+
+impl TextWriter {
+    fn element(&mut self, element: &Element, options: TextOptions) {
+        let name = element.name();
+        if is_skipped(element) || (options.skip_notes && is_note(element)) {
+            return;
+        }
+        self.extract.elements.push(ElementStart {
+            offset: self.offset(),
+            steps: self.steps.clone(),
+            id: element.attr("id").map(String::from),
+        });
+        if name == "br" {
+            self.break_line(true);
+            return;
+        }
+
+        let block = is_block(element);
+        let pre = name == "pre";
+        if block {
+            self.break_line(false);
+        }
+        if pre {
+            self.pre += 1;
+        }
+        let mut chunk = Chunk {
+            step: 1,
+            node: None,
+            source: 0,
+        };
+        for child in &element.children {
+            match child {
+                Node::Element(e) => {
+                    let step = chunk.step + 1;
+                    self.steps.push(Step::new(step, e.attr("id")));
+                    self.element(e, options);
+                    self.steps.pop();
+                    chunk = Chunk {
+                        step: step + 1,
+                        node: None,
+                        source: 0,
+                    };
+                }
+                Node::Text(t) => self.text(t, &mut chunk),
+            }
+        }
+        if matches!(name.as_str(), "td" | "th") {
+            self.space();
+        }
+        if pre {
+            self.pre -= 1;
+        }
+        if block {
+            self.break_line(false);
+        }
+    }
+
+    fn text(&mut self, text: &str, chunk: &mut Chunk) {
+        for c in text.chars() {
+            if self.pre > 0 && c == '\n' {
+                self.break_line(true);
+            } else if self.pre > 0 || !c.is_whitespace() {
+                self.push(c, c, chunk);
+            } else if self.needs_space() {
+                self.push(c, ' ', chunk);
+            }
+            chunk.source += c.len_utf16();
+        }
+    }
+
+    /// Writes `c` for the `source` character of the `chunk`, starting a
+    /// new segment unless it follows the last one
+    fn push(&mut self, source: char, c: char, chunk: &mut Chunk) {
+        let offset = self.offset();
+        let node = *chunk.node.get_or_insert_with(|| {
+            let mut steps = self.steps.clone();
+            steps.push(Step::new(chunk.step, None));
+            self.extract.nodes.push(steps);
+            self.extract.nodes.len() - 1
+        });
+        if self.next != Some((node, chunk.source, offset)) || source != c {
+            self.extract.segments.push(Segment {
+                offset,
+                node,
+                source: chunk.source,
+            });
+        }
+        self.next =
+            (source == c).then(|| (node, chunk.source + c.len_utf16(), offset + c.len_utf8()));
+        self.line.push(c);
+    }
+
+    fn offset(&self) -> usize {
+        self.text.len() + self.line.len()
+    }
+
+    fn needs_space(&self) -> bool {
+        !self.line.is_empty() && !self.line.ends_with(' ')
+    }
+
+    fn space(&mut self) {
+        if self.needs_space() {
+            self.line.push(' ');
+        }
+    }
+
+    /// Ends the current line, keeping it even if empty when `forced`
+    fn break_line(&mut self, forced: bool) {
+        let line = self.line.trim_end();
+        if !line.is_empty() || forced {
+            self.text.push_str(line);
+            self.text.push('\n');
+        }
+        self.line.clear();
+    }
+
+    fn finish(mut self) -> Extract {
+        self.break_line(false);
+        let text = self.text.trim_matches('\n');
+        let leading = self.text.len() - self.text.trim_start_matches('\n').len();
+        // the trimmed whitespace can move an offset out of the text
+        let fix = |offset: usize| {
+            let mut offset = offset.saturating_sub(leading).min(text.len());
+            while !text.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            offset
+        };
+        let mut extract = self.extract;
+        for element in &mut extract.elements {
+            element.offset = fix(element.offset);
+        }
+        for segment in &mut extract.segments {
+            segment.offset = fix(segment.offset);
+        }
+        extract.text = text.to_string();
+        extract
+    }
+}
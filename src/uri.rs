@@ -0,0 +1,190 @@
+//! The urls of the resources in the rewritten documents, see
+//! [`EpubDoc::get_current_with_epub_uris`](crate::doc::EpubDoc::get_current_with_epub_uris).
+//!
+//! The references are rewritten to `epub://` urls by default, a
+//! [`UriResolver`] can build any other url, like the ones of a local http
+//! server, of a custom scheme or blob urls.
+//!
+//! # Examples
+//!
+//! ```
+//! use epub::doc::EpubDoc;
+//! use epub::uri::BaseUrl;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! doc.set_uri_resolver(BaseUrl::new("http://127.0.0.1:8080/book/123/"));
+//! let current = doc.get_resource_by_path_with_epub_uris("OEBPS/Text/titlepage.xhtml").unwrap();
+//! let current = String::from_utf8(current).unwrap();
+//! assert!(current.contains("http://127.0.0.1:8080/book/123/OEBPS/Images/portada.png"));
+//! ```
+//!
+//! Any closure taking the path and the fragment is a resolver too:
+//!
+//! ```
+//! use epub::archive::ArchivePath;
+//! use epub::doc::EpubDoc;
+//!
+//! let mut doc = EpubDoc::new("test.epub").unwrap();
+//! doc.set_uri_resolver(|path: &ArchivePath, fragment: Option<&str>| {
+//!     let mut uri = format!("app://book/{}", epub::uri::encode_path(path));
+//!     if let Some(fragment) = fragment {
+//!         uri.push('#');
+//!         uri.push_str(fragment);
+//!     }
+//!     uri
+//! });
+//! ```
+
+use std::fmt;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::archive::{self, ArchivePath};
+
+/// Characters escaped in the paths of the urls
+const URI_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Builds the urls replacing the references of the rewritten documents
+pub trait UriResolver: Send + Sync {
+    /// Returns the url of the archive entry `path`, pointing to the
+    /// `fragment` if there's one
+    fn resource_uri(&self, path: &ArchivePath, fragment: Option<&str>) -> String;
+
+    /// Returns the url replacing the reference `href` found in the archive
+    /// entry `base`.
+    ///
+    /// By default, the urls with a scheme like `https:`, `mailto:` or
+    /// `data:`, and the references outside the archive are kept. The others
+    /// are resolved by [`archive_reference`] and passed to
+    /// [`Self::resource_uri`].
+    fn resolve(&self, base: &ArchivePath, href: &str) -> String {
+        match archive_reference(base, href) {
+            Some((path, fragment)) => self.resource_uri(&path, fragment),
+            None => href.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for dyn UriResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UriResolver")
+    }
+}
+
+impl<F> UriResolver for F
+where
+    F: Fn(&ArchivePath, Option<&str>) -> String + Send + Sync,
+{
+    fn resource_uri(&self, path: &ArchivePath, fragment: Option<&str>) -> String {
+        self(path, fragment)
+    }
+}
+
+/// The default resolver, building `epub://` urls like
+/// `epub://OEBPS/Images/cover.png`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EpubScheme;
+
+impl UriResolver for EpubScheme {
+    fn resource_uri(&self, path: &ArchivePath, fragment: Option<&str>) -> String {
+        with_fragment(format!("epub://{}", encode_path(path)), fragment)
+    }
+}
+
+/// A resolver appending the paths to a base url, like
+/// `http://127.0.0.1:8080/book/123/`
+#[derive(Clone, Debug)]
+pub struct BaseUrl {
+    base: String,
+}
+
+impl BaseUrl {
+    /// The `base` is used as is, it should end with a `/`
+    pub fn new(base: impl Into<String>) -> Self {
+        Self { base: base.into() }
+    }
+}
+
+impl UriResolver for BaseUrl {
+    fn resource_uri(&self, path: &ArchivePath, fragment: Option<&str>) -> String {
+        with_fragment(format!("{}{}", self.base, encode_path(path)), fragment)
+    }
+}
+
+/// Returns the archive entry referenced by `href` from the entry `base`,
+/// with the fragment if there's one.
+///
+/// Returns [`None`] for the urls with a scheme and the absolute and protocol
+/// relative paths. The path is percent-decoded once, and its `..` segments
+/// leaving the archive are clamped at its root, like in
+/// [`ArchivePath::join`]. The query is dropped, it has no meaning inside the
+/// archive, and an empty path is the `base` itself.
+///
+/// # Examples
+///
+/// ```
+/// use epub::archive::ArchivePath;
+/// use epub::uri::archive_reference;
+///
+/// let base = ArchivePath::new("OEBPS/Text/c1.xhtml").unwrap();
+/// let (path, fragment) = archive_reference(&base, "httpdocs/a.png?v=2#top").unwrap();
+/// assert_eq!(path.as_str(), "OEBPS/Text/httpdocs/a.png");
+/// assert_eq!(fragment, Some("top"));
+///
+/// let (path, _) = archive_reference(&base, "#note").unwrap();
+/// assert_eq!(path, base);
+///
+/// let (path, _) = archive_reference(&base, "../../../100%25.png").unwrap();
+/// assert_eq!(path.as_str(), "100%.png");
+///
+/// assert_eq!(archive_reference(&base, "https://example.com/a.png"), None);
+/// assert_eq!(archive_reference(&base, "mailto:someone@example.com"), None);
+/// assert_eq!(archive_reference(&base, "data:image/png;base64,AAAA"), None);
+/// assert_eq!(archive_reference(&base, "//example.com/a.png"), None);
+/// ```
+#[must_use]
+pub fn archive_reference<'a>(
+    base: &ArchivePath,
+    href: &'a str,
+) -> Option<(ArchivePath, Option<&'a str>)> {
+    let href = href.trim_matches(|c: char| c.is_ascii_whitespace() || c.is_ascii_control());
+    if archive::has_scheme(href) {
+        return None;
+    }
+
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    let path = if path.is_empty() {
+        base.clone()
+    } else {
+        base.resolve(path).ok()?
+    };
+    Some((path, fragment))
+}
+
+/// Returns the `path` percent encoded to be used in an url
+#[must_use]
+pub fn encode_path(path: &ArchivePath) -> String {
+    utf8_percent_encode(path.as_str(), URI_PATH).to_string()
+}
+
+fn with_fragment(mut uri: String, fragment: Option<&str>) -> String {
+    if let Some(fragment) = fragment {
+        uri.push('#');
+        uri.push_str(fragment);
+    }
+    uri
+}
@@ -0,0 +1,476 @@
+//! Epubcheck-style validation of the epub structure.
+//!
+//! [`EpubDoc::validate`] inspects the zip container and the package
+//! document and returns a list of [`Diagnostic`]s instead of failing on the
+//! first problem. The codes follow the message ids used by
+//! [epubcheck](https://github.com/w3c/epubcheck) so reports can be compared.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use xml::common::TextPosition;
+
+use crate::archive::PathError;
+use crate::doc::{DocError, EpubDoc};
+use crate::xmlutils::{self, XMLError, XMLNode};
+
+const MIMETYPE: &str = "application/epub+zip";
+
+/// How serious a [`Diagnostic`] is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The epub is readable but doesn't follow the spec
+    Warning,
+    /// The epub breaks the spec and reading systems may fail with it
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "WARNING"),
+            Self::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// The kind of problem found, see [`Code::as_str`] for the stable code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Code {
+    /// The mimetype file is missing or isn't the first file in the archive
+    MimetypeNotFirst,
+    /// The mimetype file is compressed or has an unexpected content
+    MimetypeInvalid,
+    /// A XML file of the package can't be parsed
+    XmlParse,
+    /// A required element or attribute is missing
+    MissingAttribute,
+    /// A manifest item points to a file that isn't in the archive
+    ResourceMissing,
+    /// A spine reference points to an id that isn't in the manifest
+    IdrefNotFound,
+    /// The same id is used by more than one element of the package document
+    DuplicateId,
+    /// A file in the archive isn't declared in the manifest
+    UndeclaredResource,
+    /// The package document doesn't declare the `unique-identifier`
+    UniqueIdentifierMissing,
+    /// The `unique-identifier` doesn't match any `dc:identifier`
+    UniqueIdentifierNotFound,
+    /// A reference is an absolute path or otherwise points outside the
+    /// container
+    InvalidPath,
+}
+
+impl Code {
+    /// Returns the epubcheck message id for this code
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use epub::validate::Code;
+    /// assert_eq!("OPF-049", Code::IdrefNotFound.as_str());
+    /// ```
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::MimetypeNotFirst => "PKG-006",
+            Self::MimetypeInvalid => "PKG-007",
+            Self::XmlParse => "RSC-016",
+            Self::MissingAttribute | Self::DuplicateId => "RSC-005",
+            Self::ResourceMissing => "RSC-001",
+            Self::IdrefNotFound => "OPF-049",
+            Self::UndeclaredResource => "OPF-003",
+            Self::UniqueIdentifierMissing => "OPF-048",
+            Self::UniqueIdentifierNotFound => "OPF-030",
+            Self::InvalidPath => "RSC-026",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A problem found validating the epub
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    /// The file in the archive where the problem was found
+    pub path: Option<PathBuf>,
+    /// 1-based line in `path`
+    pub line: Option<u64>,
+    /// 1-based column in `path`
+    pub column: Option<u64>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, code: Code, message: String) -> Self {
+        Self {
+            severity,
+            code,
+            path: None,
+            line: None,
+            column: None,
+            message,
+        }
+    }
+
+    pub(crate) fn error(code: Code, message: String) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub(crate) fn warning(code: Code, message: String) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub(crate) fn at<P: AsRef<Path>>(mut self, path: P, position: Option<TextPosition>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        if let Some(pos) = position {
+            self.line = Some(pos.row + 1);
+            self.column = Some(pos.column + 1);
+        }
+        self
+    }
+
+    /// Builds a [`Code::XmlParse`] error for the file `path`
+    pub(crate) fn xml_error<P: AsRef<Path>>(path: P, e: &XMLError) -> Self {
+        let path = e.path().unwrap_or_else(|| path.as_ref());
+        let code = match e.inner() {
+            XMLError::AttrNotFound(_) | XMLError::ElementNotFound(_) => Code::MissingAttribute,
+            XMLError::InvalidPath(_) => Code::InvalidPath,
+            _ => Code::XmlParse,
+        };
+        let message = e.inner().to_string();
+        Self::error(code, message).at(path, e.position())
+    }
+
+    /// Builds the diagnostic for an error found opening the epub
+    pub(crate) fn from_doc_error(root_file: &Path, e: &DocError) -> Self {
+        match e {
+            DocError::SpecViolation(diagnostic) => diagnostic.clone(),
+            DocError::XmlError(e) => Self::xml_error(root_file, e),
+            DocError::MissingManifest { path }
+            | DocError::MissingSpine { path }
+            | DocError::MissingMetadata { path } => {
+                Self::error(Code::MissingAttribute, e.to_string()).at(path, None)
+            }
+            DocError::TocNotFound { path, .. } => {
+                Self::error(Code::IdrefNotFound, e.to_string()).at(path, None)
+            }
+            DocError::ResourceNotFound { .. } => Self::error(Code::IdrefNotFound, e.to_string()),
+            e => Self::error(Code::ResourceMissing, e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.severity, self.code)?;
+        if let Some(path) = &self.path {
+            write!(f, ": {}", path.display())?;
+            if let (Some(line), Some(column)) = (self.line, self.column) {
+                write!(f, "({},{})", line, column)?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl<R: Read + Seek> EpubDoc<R> {
+    /// Validates the epub container and package document.
+    ///
+    /// This doesn't stop on the first problem, every check is run and the
+    /// list of problems found is returned. An empty list means that the
+    /// epub passed all the checks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use epub::doc::EpubDoc;
+    /// use epub::validate::Code;
+    ///
+    /// let mut doc = EpubDoc::new("test.epub").unwrap();
+    /// let diagnostics = doc.validate();
+    /// // test.epub doesn't store the mimetype as the first file
+    /// assert_eq!(Code::MimetypeNotFirst, diagnostics[0].code);
+    /// for d in diagnostics {
+    ///     println!("{}", d);
+    /// }
+    /// ```
+    pub fn validate(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        self.check_mimetype(&mut diagnostics);
+        self.check_package(&mut diagnostics);
+        diagnostics
+    }
+
+    fn check_mimetype(&mut self, diagnostics: &mut Vec<Diagnostic>) {
+        if self.archive.files.first().map(String::as_str) != Some("mimetype") {
+            diagnostics.push(
+                Diagnostic::error(
+                    Code::MimetypeNotFirst,
+                    "Mimetype file entry is missing or is not the first file in the archive".into(),
+                )
+                .at("mimetype", None),
+            );
+        }
+
+        if !self.archive.contains("mimetype") {
+            return;
+        }
+
+        if !self.archive.is_stored("mimetype").unwrap_or(true) {
+            diagnostics.push(
+                Diagnostic::error(
+                    Code::MimetypeInvalid,
+                    "Mimetype file should not be compressed".into(),
+                )
+                .at("mimetype", None),
+            );
+        }
+
+        let content = self.archive.get_entry("mimetype").unwrap_or_default();
+        if content != MIMETYPE.as_bytes() {
+            diagnostics.push(
+                Diagnostic::error(
+                    Code::MimetypeInvalid,
+                    format!("Mimetype file should only contain the string {}", MIMETYPE),
+                )
+                .at("mimetype", None),
+            );
+        }
+    }
+
+    fn check_package(&mut self, diagnostics: &mut Vec<Diagnostic>) {
+        let root_file = self.root_file.clone();
+        let Some(root) = self.parse_for_validation(&root_file, diagnostics) else {
+            return;
+        };
+        let root = root.borrow();
+
+        check_duplicate_ids(&root, &root_file, diagnostics);
+        check_unique_identifier(&root, &root_file, diagnostics);
+
+        let Some(manifest) = root.find("manifest") else {
+            diagnostics.push(
+                Diagnostic::error(
+                    Code::MissingAttribute,
+                    "Package document is missing the manifest element".into(),
+                )
+                .at(&root_file, None),
+            );
+            return;
+        };
+        let manifest_ids = self.check_manifest(&manifest.borrow(), diagnostics);
+
+        if let Some(spine) = root.find("spine") {
+            self.check_spine(&spine.borrow(), &manifest_ids, diagnostics);
+        } else {
+            diagnostics.push(
+                Diagnostic::error(
+                    Code::MissingAttribute,
+                    "Package document is missing the spine element".into(),
+                )
+                .at(&root_file, None),
+            );
+        }
+    }
+
+    /// Checks the manifest items and the files in the archive not declared
+    /// in the manifest. Returns the list of manifest ids.
+    fn check_manifest(
+        &self,
+        manifest: &XMLNode,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> HashSet<String> {
+        let mut manifest_ids = HashSet::new();
+        let mut declared = HashSet::new();
+
+        for r in &manifest.children {
+            let item = r.borrow();
+            for attr in ["id", "href", "media-type"] {
+                if item.get_attr(attr).is_none() {
+                    diagnostics.push(
+                        Diagnostic::error(
+                            Code::MissingAttribute,
+                            format!("Manifest item is missing the \"{}\" attribute", attr),
+                        )
+                        .at(&self.root_file, Some(item.position)),
+                    );
+                }
+            }
+            if let Some(id) = item.get_attr("id") {
+                manifest_ids.insert(id);
+            }
+            let Some(href) = item.get_attr("href") else {
+                continue;
+            };
+            let path = match self.href_path(&href) {
+                Ok(path) => path,
+                Err(PathError::External(_)) => continue,
+                Err(e) => {
+                    diagnostics.push(
+                        Diagnostic::error(Code::InvalidPath, e.to_string())
+                            .at(&self.root_file, Some(item.position)),
+                    );
+                    continue;
+                }
+            };
+            if !self.archive.contains(&path) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        Code::ResourceMissing,
+                        format!("Referenced resource \"{}\" could not be found", href),
+                    )
+                    .at(&self.root_file, Some(item.position)),
+                );
+            }
+            declared.insert(path.to_string());
+        }
+
+        let root_file = self.root_file.to_string_lossy();
+        for file in &self.archive.files {
+            if file.ends_with('/')
+                || file == "mimetype"
+                || file.starts_with("META-INF/")
+                || *file == root_file
+                || declared.contains(file)
+            {
+                continue;
+            }
+            diagnostics.push(
+                Diagnostic::warning(
+                    Code::UndeclaredResource,
+                    format!(
+                        "Item \"{}\" is in the container but not declared in the manifest",
+                        file
+                    ),
+                )
+                .at(file, None),
+            );
+        }
+
+        manifest_ids
+    }
+
+    fn check_spine(
+        &mut self,
+        spine: &XMLNode,
+        manifest_ids: &HashSet<String>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        for r in &spine.children {
+            let item = r.borrow();
+            match item.get_attr("idref") {
+                Some(idref) if !manifest_ids.contains(&idref) => diagnostics.push(
+                    Diagnostic::error(
+                        Code::IdrefNotFound,
+                        format!("Itemref idref \"{}\" was not found in the manifest", idref),
+                    )
+                    .at(&self.root_file, Some(item.position)),
+                ),
+                Some(_) => {}
+                None => diagnostics.push(
+                    Diagnostic::error(
+                        Code::MissingAttribute,
+                        "Spine itemref is missing the \"idref\" attribute".into(),
+                    )
+                    .at(&self.root_file, Some(item.position)),
+                ),
+            }
+        }
+
+        if let Some(toc) = spine.get_attr("toc") {
+            if !manifest_ids.contains(&toc) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        Code::IdrefNotFound,
+                        format!("Spine toc \"{}\" was not found in the manifest", toc),
+                    )
+                    .at(&self.root_file, Some(spine.position)),
+                );
+            } else if let Some(ncx) = self.resources.get(&toc).map(|r| r.path.clone()) {
+                self.parse_for_validation(&ncx, diagnostics);
+            }
+        }
+    }
+
+    fn parse_for_validation(
+        &mut self,
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<std::cell::RefCell<XMLNode>> {
+        let content = match self.archive.get_entry(path) {
+            Ok(content) => content,
+            Err(e) => {
+                diagnostics
+                    .push(Diagnostic::error(Code::ResourceMissing, e.to_string()).at(path, None));
+                return None;
+            }
+        };
+
+        match xmlutils::XMLReader::parse(&content, true, &self.options.limits) {
+            Ok(root) => Some(root),
+            Err(e) => {
+                diagnostics.push(Diagnostic::xml_error(path, &e));
+                None
+            }
+        }
+    }
+}
+
+fn check_duplicate_ids(root: &XMLNode, path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    fn walk(node: &XMLNode, path: &Path, seen: &mut HashSet<String>, out: &mut Vec<Diagnostic>) {
+        if let Some(id) = node.get_attr("id") {
+            if seen.contains(&id) {
+                out.push(
+                    Diagnostic::error(Code::DuplicateId, format!("Duplicate id \"{}\"", id))
+                        .at(path, Some(node.position)),
+                );
+            } else {
+                seen.insert(id);
+            }
+        }
+        for child in &node.children {
+            walk(&child.borrow(), path, seen, out);
+        }
+    }
+
+    walk(root, path, &mut HashSet::new(), diagnostics);
+}
+
+fn check_unique_identifier(root: &XMLNode, path: &Path, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(uid) = root.get_attr("unique-identifier") else {
+        diagnostics.push(
+            Diagnostic::error(
+                Code::UniqueIdentifierMissing,
+                "Package tag is missing its required unique-identifier attribute".into(),
+            )
+            .at(path, Some(root.position)),
+        );
+        return;
+    };
+
+    let found = root.find("metadata").is_some_and(|metadata| {
+        metadata.borrow().children.iter().any(|r| {
+            let item = r.borrow();
+            item.name.local_name == "identifier" && item.get_attr("id").as_ref() == Some(&uid)
+        })
+    });
+    if !found {
+        diagnostics.push(
+            Diagnostic::error(
+                Code::UniqueIdentifierNotFound,
+                format!("The unique-identifier \"{}\" was not found", uid),
+            )
+            .at(path, Some(root.position)),
+        );
+    }
+}
@@ -1,19 +1,30 @@
+use encoding_rs::UTF_8;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::rc::Weak;
 use xml::attribute::OwnedAttribute;
+use xml::common::{Position, TextPosition};
+use xml::name::{Name, OwnedName};
 use xml::reader::Error as ReaderError;
 use xml::reader::EventReader;
-use xml::reader::ParserConfig;
+use xml::reader::{ParserConfig, ParserConfig2};
 
 use xml::reader::XmlEvent as ReaderEvent;
-use xml::writer::XmlEvent as WriterEvent;
 
 use std::fmt;
-use xml::writer::EmitterConfig;
 use xml::writer::Error as EmitterError;
 
 use std::borrow::Cow;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::archive::PathError;
+use crate::dom::OPS_NAMESPACE;
+use crate::inject::{Injection, Placement};
+use crate::limits::{LimitError, Limits};
+use crate::markup::{escape_attr, escape_text, Attr, Patch, Scanner, Tag, TagKind};
+use crate::references::{SVG_NAMESPACE, XHTML_NAMESPACE, XLINK_NAMESPACE};
+use crate::{encoding, entities};
 
 // Using RefCell because we need to edit the children vec during the parsing.
 // Using rc because a Node will be referenced by its parent and by its childs.
@@ -28,53 +39,264 @@ pub enum XMLError {
     Emitter(#[from] EmitterError),
     #[error("Attribute Not Found: {0}")]
     AttrNotFound(String),
+    #[error("Element Not Found: {0}")]
+    ElementNotFound(String),
     #[error("Invalid State; this is a bug")]
     InvalidState,
     #[error("No XML Elements Found")]
     NoElements,
     #[error("XML content is empty")]
     NoContent,
+    #[error("Limit Exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
+    #[error("Invalid Path: {0}")]
+    InvalidPath(#[from] PathError),
+    /// Any of the above errors, found in the file `path` of the epub
+    #[error("{}: {source}", location(path, position.as_ref()))]
+    InFile {
+        path: PathBuf,
+        /// The position in the file, if known
+        position: Option<TextPosition>,
+        source: Box<Self>,
+    },
+}
+
+fn location(path: &Path, position: Option<&TextPosition>) -> String {
+    position.map_or_else(
+        || path.display().to_string(),
+        |position| format!("{}:{}", path.display(), position),
+    )
+}
+
+impl XMLError {
+    /// Attaches the file `path` to this error. The position in the file is
+    /// taken from the XML reader error if there's one.
+    #[must_use]
+    pub fn in_file<P: AsRef<Path>>(self, path: P) -> Self {
+        let position = self.position();
+        self.at(path, position)
+    }
+
+    /// Attaches the file `path` and `position` to this error.
+    #[must_use]
+    pub fn at<P: AsRef<Path>>(self, path: P, position: Option<TextPosition>) -> Self {
+        let source = match self {
+            Self::InFile { source, .. } => source,
+            e => Box::new(e),
+        };
+        Self::InFile {
+            path: path.as_ref().to_path_buf(),
+            position,
+            source,
+        }
+    }
+
+    /// Returns the file of the epub where the error was found, if known
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::InFile { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without the file information
+    pub(crate) fn inner(&self) -> &Self {
+        match self {
+            Self::InFile { source, .. } => source,
+            e => e,
+        }
+    }
+
+    /// Returns the position in the file where the error was found, if known
+    #[must_use]
+    pub fn position(&self) -> Option<TextPosition> {
+        match self {
+            Self::Reader(e) => Some(e.position()),
+            Self::InFile { position, .. } => *position,
+            _ => None,
+        }
+    }
+}
+
+/// The entities of a document: the ones of xml, the named character
+/// references of html, and the ones declared in the internal subset of its
+/// doctype, that take precedence
+pub struct Entities {
+    declared: Vec<(String, String)>,
+}
+
+impl Entities {
+    /// Reads the entities declared in the doctype of the document `content`
+    pub fn of(content: &[u8]) -> Self {
+        Self {
+            declared: declared_entities(&String::from_utf8_lossy(content)),
+        }
+    }
+
+    /// Returns the value of the entity `name`, without the `&` and the `;`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.declared
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .or_else(|| entities::get(name))
+    }
+
+    /// Returns the configuration of the XML parsers, with the html entities
+    /// that the document doesn't declare. xml-rs reads the declared ones.
+    ///
+    /// The documents are decoded to UTF-8 before being parsed, so their
+    /// declared encoding is ignored.
+    pub fn parser_config(&self) -> ParserConfig2 {
+        entities::ENTITIES
+            .iter()
+            .filter(|(name, _)| !self.declared.iter().any(|(n, _)| n == name))
+            .fold(ParserConfig::new(), |config, (name, value)| {
+                config.add_entity(*name, *value)
+            })
+            .override_encoding(Some(xml::Encoding::Utf8))
+            .ignore_invalid_encoding_declarations(true)
+    }
+
+    /// Returns the `text` with its character and entity references
+    /// replaced, or [`None`] if it has an unknown entity
+    pub fn decode<'a>(&self, text: &'a str) -> Option<Cow<'a, str>> {
+        decode_references(text, |name| self.get(name))
+    }
+}
+
+/// The configuration of the XML parsers for the decoded document
+/// `content`, with the html entities
+pub fn parser_config(content: &[u8]) -> ParserConfig2 {
+    Entities::of(content).parser_config()
+}
+
+/// Returns the `text` with its character references and the entities known
+/// by `entity` replaced, or [`None`] if it has an unknown one
+fn decode_references<'a, 'e>(
+    text: &'a str,
+    entity: impl Fn(&str) -> Option<&'e str>,
+) -> Option<Cow<'a, str>> {
+    if !text.contains('&') {
+        return Some(Cow::Borrowed(text));
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let end = rest[start..].find(';')? + start;
+        let name = &rest[start + 1..end];
+        if let Some(code) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+            decoded.push(char::from_u32(u32::from_str_radix(code, 16).ok()?)?);
+        } else if let Some(code) = name.strip_prefix('#') {
+            decoded.push(char::from_u32(code.parse().ok()?)?);
+        } else {
+            decoded.push_str(entity(name)?);
+        }
+        rest = &rest[end + 1..];
+    }
+    decoded.push_str(rest);
+    Some(Cow::Owned(decoded))
+}
+
+/// Returns the general entities declared in the internal subset of the
+/// doctype of `text`. The values can refer to the html entities but not to
+/// the declared ones, so they can't grow exponentially, and the external
+/// entities are ignored.
+fn declared_entities(text: &str) -> Vec<(String, String)> {
+    let mut entities: Vec<(String, String)> = vec![];
+    if !text.contains("<!ENTITY") {
+        return entities;
+    }
+    let Some(start) = text.find("<!DOCTYPE") else {
+        return entities;
+    };
+    let doctype = &text[start..];
+    // the subset starts before the end of the doctype
+    let Some(open) = doctype
+        .find(['[', '>'])
+        .filter(|i| doctype[*i..].starts_with('['))
+    else {
+        return entities;
+    };
+
+    let mut rest = &doctype[open + 1..];
+    while let Some(next) = rest.find(['<', ']']) {
+        rest = &rest[next..];
+        if rest.starts_with(']') {
+            break;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |i| &comment[i + 3..]);
+            continue;
+        }
+        let Some(declaration) = rest.strip_prefix("<!ENTITY") else {
+            rest = &rest[1..];
+            continue;
+        };
+        rest = declaration;
+
+        let declaration = declaration.trim_start();
+        // parameter entities are only used inside the doctype
+        if declaration.starts_with('%') {
+            continue;
+        }
+        let name_end = declaration
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(declaration.len());
+        let (name, value) = declaration.split_at(name_end);
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            continue;
+        };
+        let Some(end) = value[1..].find(quote) else {
+            break;
+        };
+        let raw = &value[1..=end];
+        rest = &value[end + 2..];
+
+        // the character references are replaced in the literal value, and
+        // the other references when the entity is used
+        let literal =
+            decode_references(raw, |_| None).map_or_else(|| raw.to_string(), Cow::into_owned);
+        let value = decode_references(&literal, entities::get)
+            .map_or_else(|| literal.clone(), Cow::into_owned);
+        // the first declaration wins
+        if !entities.iter().any(|(n, _)| n == name) {
+            entities.push((name.to_string(), value));
+        }
+    }
+    entities
 }
 
 pub struct XMLReader<'a> {
     reader: EventReader<&'a [u8]>,
+    strict: bool,
+    limits: Limits,
 }
 
-impl<'a> XMLReader<'a> {
-    pub fn parse(content: &[u8]) -> Result<RefCell<XMLNode>, XMLError> {
+impl XMLReader<'_> {
+    /// Parses the document, failing on the first syntax error if `strict`,
+    /// or keeping the elements read before the error otherwise. Exceeding
+    /// the `limits` is always an error.
+    pub fn parse(
+        content: &[u8],
+        strict: bool,
+        limits: &Limits,
+    ) -> Result<RefCell<XMLNode>, XMLError> {
         // The operations below require at least 4 bytes to not panic
         if content.is_empty() || content.len() < 4 {
             return Err(XMLError::NoContent);
         }
 
-        let content_str;
-        //If there is a UTF-8 BOM marker, ignore it
-        let content_slice = if content[0..3] == [0xefu8, 0xbbu8, 0xbfu8] {
-            &content[3..]
-        } else if content[0..2] == [0xfeu8, 0xffu8] || content[0..2] == [0xffu8, 0xfeu8] {
-            //handle utf-16
-            let (big_byte, small_byte) = if content[0] == 0xfeu8 {
-                (1, 0) //big endian utf-16
-            } else {
-                (0, 1) //little endian utf-16
-            };
-            let content_u16: Vec<u16> = content[2..]
-                .chunks_exact(2)
-                .into_iter()
-                .map(|a| u16::from_ne_bytes([a[big_byte], a[small_byte]]))
-                .collect();
-            content_str = String::from_utf16_lossy(content_u16.as_slice());
-            content_str.as_bytes()
-        } else {
-            content
-        };
-
+        let (content, _) = encoding::decode(content);
         let reader = XMLReader {
-            reader: ParserConfig::new()
-                .add_entity("nbsp", " ")
-                .add_entity("copy", "©")
-                .add_entity("reg", "®")
-                .create_reader(content_slice),
+            reader: parser_config(content.as_bytes())
+                .max_entity_expansion_length(limits.max_entity_expansion)
+                .create_reader(content.as_bytes()),
+            strict,
+            limits: *limits,
         };
 
         reader.parse_xml()
@@ -84,17 +306,19 @@ impl<'a> XMLReader<'a> {
         let mut root: Option<ChildNodeRef> = None;
         let mut parents: Vec<ChildNodeRef> = vec![];
 
-        for e in self.reader {
+        let mut reader = self.reader;
+        loop {
+            let e = reader.next();
             match e {
                 Ok(ReaderEvent::StartElement {
-                    name,
-                    attributes,
-                    namespace,
+                    name, attributes, ..
                 }) => {
+                    self.limits
+                        .check_element(&name.local_name, parents.len(), attributes.len())?;
                     let node = XMLNode {
                         name,
                         attrs: attributes,
-                        namespace,
+                        position: reader.position(),
                         parent: None,
                         text: None,
                         cdata: None,
@@ -116,23 +340,31 @@ impl<'a> XMLReader<'a> {
                     }
                 }
                 Ok(ReaderEvent::EndElement { .. }) => {
-                    if !parents.is_empty() {
-                        parents.pop();
-                    }
+                    parents.pop();
                 }
                 Ok(ReaderEvent::Characters(text)) => {
+                    self.limits.check_text(&text)?;
                     let current = parents.last();
                     if let Some(c) = current {
                         c.borrow_mut().text = Some(text);
                     }
                 }
                 Ok(ReaderEvent::CData(text)) => {
+                    self.limits.check_text(&text)?;
                     let current = parents.last();
                     if let Some(c) = current {
                         c.borrow_mut().cdata = Some(text);
                     }
                 }
-                _ => continue,
+                Err(e) => {
+                    self.limits.check_reader_error(&e)?;
+                    if self.strict {
+                        return Err(e.into());
+                    }
+                    break;
+                }
+                Ok(ReaderEvent::EndDocument) => break,
+                _ => {}
             }
         }
 
@@ -151,7 +383,8 @@ impl<'a> XMLReader<'a> {
 pub struct XMLNode {
     pub name: xml::name::OwnedName,
     pub attrs: Vec<xml::attribute::OwnedAttribute>,
-    pub namespace: xml::namespace::Namespace,
+    /// Position of the start tag in the source document
+    pub position: TextPosition,
     pub text: Option<String>,
     pub cdata: Option<String>,
     pub parent: Option<ParentNodeRef>,
@@ -204,77 +437,447 @@ impl fmt::Display for XMLNode {
     }
 }
 
-pub fn replace_attrs<F>(
+/// The syntax of a document to rewrite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// A well-formed xml document, an error otherwise
+    Xml,
+    /// A html document, read as it comes
+    Html,
+}
+
+/// Returns the names and attributes of the elements of the xml `text`, in
+/// document order. The xml parser checks the document, within the
+/// `limits`, and resolves the namespaces.
+fn xml_elements(
+    text: &str,
+    entities: &Entities,
+    limits: &Limits,
+) -> Result<Vec<(OwnedName, Vec<OwnedAttribute>)>, XMLError> {
+    let reader = entities
+        .parser_config()
+        .max_entity_expansion_length(limits.max_entity_expansion)
+        .create_reader(text.as_bytes());
+    let mut elements = vec![];
+    let mut depth = 0;
+    for e in reader {
+        match e {
+            Ok(ReaderEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                limits.check_element(&name.local_name, depth, attributes.len())?;
+                depth += 1;
+                elements.push((name, attributes));
+            }
+            Ok(ReaderEvent::EndElement { .. }) => depth -= 1,
+            Ok(ReaderEvent::Characters(text) | ReaderEvent::CData(text)) => {
+                limits.check_text(&text)?;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                limits.check_reader_error(&e)?;
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(elements)
+}
+
+/// Rewrites the `xmldoc` replacing every attribute value with the result of
+/// `closure`, called with the element name, the attribute name and its
+/// value, and the content of the `<style>` elements with `style`.
+///
+/// The `injections` are written at the start or the end of the `<head>` of
+/// the html documents, one is added before the `<body>` when missing.
+///
+/// Only the changed values are patched, the rest of the document is kept
+/// byte for byte. Exceeding the `limits` while reading a xml document is
+/// an error.
+pub fn replace_attrs<F, S>(
     xmldoc: &[u8],
     closure: F,
-    extra_css: &[String],
+    style: S,
+    injections: &[(Injection, Placement)],
+    syntax: Syntax,
+    limits: &Limits,
 ) -> Result<Vec<u8>, XMLError>
 where
-    F: Fn(&str, &str, &str) -> String,
+    F: Fn(Name, Name, &str) -> String,
+    S: Fn(&str) -> String,
 {
-    let mut b = Vec::new();
-
-    {
-        let reader = ParserConfig::new()
-            .add_entity("nbsp", " ")
-            .add_entity("copy", "©")
-            .add_entity("reg", "®")
-            .create_reader(xmldoc);
-        let mut writer = EmitterConfig::default()
-            .perform_indent(true)
-            .create_writer(&mut b);
-
-        for e in reader {
-            match e? {
-                ev @ ReaderEvent::StartElement { .. } => {
-                    let mut attrs: Vec<xml::attribute::OwnedAttribute> = vec![];
-
-                    if let Some(WriterEvent::StartElement {
-                        name,
-                        attributes,
-                        namespace,
-                    }) = ev.as_writer_event()
-                    {
-                        for i in 0..attributes.len() {
-                            let mut attr = attributes[i].to_owned();
-                            let repl = closure(name.local_name, &attr.name.local_name, &attr.value);
-                            attr.value = repl;
-                            attrs.push(attr);
-                        }
+    let (text, source) = encoding::decode(xmldoc);
+    // the document is written back in UTF-8
+    let legacy = source != UTF_8;
+    let text = if legacy {
+        Cow::Owned(encoding::declare_utf8(&text).into_owned())
+    } else {
+        text
+    };
+    let html = syntax == Syntax::Html;
+    let entities = Entities::of(text.as_bytes());
+
+    let mut elements = if html {
+        vec![]
+    } else {
+        xml_elements(&text, &entities, limits)?
+    }
+    .into_iter();
+
+    let mut patch = Patch::new(&text);
+    let mut head = HeadInjector {
+        injections,
+        html,
+        state: if injections.is_empty() {
+            Head::Done
+        } else {
+            Head::Missing
+        },
+    };
+    let mut in_style = 0;
+    let mut in_svg = 0;
+    let mut depth: usize = 0;
+    // the end of the previous tag
+    let mut previous = 0;
+
+    for tag in Scanner::new(&text, html) {
+        if in_style > 0 {
+            let span = previous..tag.span.start;
+            if html {
+                // raw text, without entities
+                let css = style(&text[span.clone()]);
+                if css != text[span.clone()] {
+                    patch.replace(span, &css);
+                }
+            } else {
+                replace_style(&mut patch, &text, span, &entities, &style);
+            }
+        }
+        previous = tag.span.end;
+        let local_name = tag.local_name().to_ascii_lowercase();
 
-                        let w = WriterEvent::StartElement {
-                            name,
-                            attributes: Cow::Owned(
-                                attrs.iter().map(OwnedAttribute::borrow).collect(),
-                            ),
-                            //attributes: attributes,
-                            namespace,
+        match &tag.kind {
+            TagKind::Start { attrs, empty } => {
+                head.before_start(&mut patch, &tag, &local_name, depth);
+
+                let element = if html {
+                    let svg = in_svg > 0 || local_name == "svg";
+                    Some(html_element(&text, &tag, attrs, &entities, svg))
+                } else {
+                    // skipping the elements of the declared entities
+                    elements.find(|(name, _)| name.local_name == tag.local_name())
+                };
+                if let Some((name, attributes)) = element {
+                    for attr in attrs {
+                        let Some(attribute) = attributes
+                            .iter()
+                            .find(|a| a.name.borrow().to_repr().eq_ignore_ascii_case(attr.name))
+                        else {
+                            continue;
                         };
-                        writer.write(w)?;
+                        let mut value =
+                            closure(name.borrow(), attribute.name.borrow(), &attribute.value);
+                        if legacy && local_name == "meta" {
+                            value = encoding::meta_utf8(&attribute.name.local_name, value);
+                        }
+                        if value != attribute.value {
+                            patch.replace(attr.value.clone(), &attr.escape(&value));
+                        }
                     }
                 }
-                ReaderEvent::EndElement { name: n } => {
-                    if n.local_name.to_lowercase() == "head" && !extra_css.is_empty() {
-                        // injecting here the extra css
-                        let mut allcss = extra_css.concat();
-                        allcss = format!("*/ {} /*", allcss);
-
-                        writer.write(WriterEvent::start_element("style"))?;
-                        writer.write("/*")?;
-                        writer.write(WriterEvent::cdata(&allcss))?;
-                        writer.write("*/")?;
-                        writer.write(WriterEvent::end_element())?;
+
+                head.after_start(&mut patch, &tag, &local_name, depth, *empty);
+                if !*empty {
+                    depth += 1;
+                    if local_name == "style" {
+                        in_style += 1;
                     }
-                    writer.write(WriterEvent::end_element())?;
-                }
-                ev => {
-                    if let Some(e) = ev.as_writer_event() {
-                        writer.write(e)?;
+                    if html && local_name == "svg" {
+                        in_svg += 1;
                     }
                 }
             }
+            TagKind::End => {
+                // ignoring the unmatched end tags of html
+                let Some(parent) = depth.checked_sub(1) else {
+                    continue;
+                };
+                depth = parent;
+                if local_name == "style" && in_style > 0 {
+                    in_style -= 1;
+                }
+                if html && local_name == "svg" && in_svg > 0 {
+                    in_svg -= 1;
+                }
+                head.end(&mut patch, &tag, &local_name, depth);
+            }
+        }
+    }
+
+    Ok(patch.finish().into_bytes())
+}
+
+/// Returns the name and the attributes of a html start tag, resolving the
+/// namespaces like the html parser
+fn html_element(
+    text: &str,
+    tag: &Tag,
+    attrs: &[Attr],
+    entities: &Entities,
+    svg: bool,
+) -> (OwnedName, Vec<OwnedAttribute>) {
+    let name = if svg {
+        OwnedName::qualified(tag.local_name(), SVG_NAMESPACE, None::<&str>)
+    } else {
+        OwnedName::qualified(
+            tag.local_name().to_ascii_lowercase(),
+            XHTML_NAMESPACE,
+            None::<&str>,
+        )
+    };
+    let attributes = attrs
+        .iter()
+        .map(|attr| {
+            let name = match attr.name.split_once(':') {
+                Some((prefix @ "xlink", local)) => {
+                    OwnedName::qualified(local, XLINK_NAMESPACE, Some(prefix))
+                }
+                Some((prefix @ "xml", local)) => {
+                    OwnedName::qualified(local, xml::namespace::NS_XML_URI, Some(prefix))
+                }
+                Some((prefix @ "epub", local)) => {
+                    OwnedName::qualified(local, OPS_NAMESPACE, Some(prefix))
+                }
+                _ => OwnedName::local(attr.name.to_ascii_lowercase()),
+            };
+            let raw = &text[attr.value.clone()];
+            let value = entities
+                .decode(raw)
+                .map_or_else(|| raw.to_string(), Cow::into_owned);
+            OwnedAttribute { name, value }
+        })
+        .collect();
+    (name, attributes)
+}
+
+/// Replaces the references of the content of a `<style>` in `span`, made
+/// of text, CDATA sections and comments
+fn replace_style<S>(
+    patch: &mut Patch,
+    text: &str,
+    span: Range<usize>,
+    entities: &Entities,
+    style: &S,
+) where
+    S: Fn(&str) -> String,
+{
+    let mut position = span.start;
+    while position < span.end {
+        let rest = &text[position..span.end];
+        let (end, content) = match rest.strip_prefix("<![CDATA[") {
+            Some(cdata) => {
+                let length = cdata.find("]]>").unwrap_or(cdata.len());
+                let content = position + 9..position + 9 + length;
+                (content.end + 3, Some((content, true)))
+            }
+            None if rest.starts_with("<!--") => {
+                let end = rest.find("-->").map_or(span.end, |i| position + i + 3);
+                (end, None)
+            }
+            None => {
+                // the text goes to the next CDATA section or comment
+                let end = rest
+                    .find("<!")
+                    .filter(|i| *i > 0)
+                    .map_or(span.end, |i| position + i);
+                (end, Some((position..end, false)))
+            }
+        };
+
+        if let Some((content, cdata)) = content {
+            let raw = &text[content.clone()];
+            let decoded = if cdata {
+                Some(Cow::Borrowed(raw))
+            } else {
+                entities.decode(raw)
+            };
+            if let Some(decoded) = decoded {
+                let css = style(&decoded);
+                if css != decoded {
+                    let css = if cdata { css } else { escape_text(&css) };
+                    patch.replace(content, &css);
+                }
+            }
+        }
+        position = end.min(span.end);
+    }
+}
+
+/// Returns a `<head>` with all the `injections`
+fn head_markup(injections: &[(Injection, Placement)], html: bool) -> String {
+    format!(
+        "<head>{}{}</head>",
+        injections_markup(injections, Placement::Start, html),
+        injections_markup(injections, Placement::End, html)
+    )
+}
+
+/// Returns the markup of the `injections` with the `placement`
+fn injections_markup(
+    injections: &[(Injection, Placement)],
+    placement: Placement,
+    html: bool,
+) -> String {
+    let mut markup = String::new();
+    for (injection, _) in injections.iter().filter(|(_, p)| *p == placement) {
+        let (name, attrs, text) = injection.element();
+        markup.push('<');
+        markup.push_str(name);
+        for (attr, value) in attrs {
+            markup.push(' ');
+            markup.push_str(attr);
+            markup.push_str("=\"");
+            markup.push_str(&escape_attr(value, '"'));
+            markup.push('"');
+        }
+        match text {
+            Some(text) => {
+                markup.push('>');
+                markup.push_str(&raw_text(text, name, html));
+                markup.push_str("</");
+                markup.push_str(name);
+                markup.push('>');
+            }
+            None => markup.push_str("/>"),
+        }
+    }
+    markup
+}
+
+/// Returns the `text` of the `<style>` or `<script>` element `name`. It's
+/// raw text in html, where only its end tag is escaped, and a CDATA section
+/// in xml when it has markup characters.
+fn raw_text<'a>(text: &'a str, name: &str, html: bool) -> Cow<'a, str> {
+    if html {
+        let end_tag = format!("</{}", name);
+        let lowercase = text.to_ascii_lowercase();
+        if !lowercase.contains(&end_tag) {
+            return Cow::Borrowed(text);
+        }
+        let mut escaped = String::new();
+        let mut last = 0;
+        for (i, _) in lowercase.match_indices(&end_tag) {
+            escaped.push_str(&text[last..=i]);
+            escaped.push('\\');
+            last = i + 1;
+        }
+        escaped.push_str(&text[last..]);
+        Cow::Owned(escaped)
+    } else if text.contains(['<', '&']) {
+        Cow::Owned(format!(
+            "<![CDATA[{}]]>",
+            text.replace("]]>", "]]]]><![CDATA[>")
+        ))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// The state of the `<head>` of the document being rewritten
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Head {
+    /// Not found yet
+    Missing,
+    /// Inside the head
+    Open,
+    /// Past the head, or nothing to inject
+    Done,
+}
+
+/// Writes the injections in the `<head>` of a document. The head of a xml
+/// document is the `<head>` child of the root `<html>`, in a html one, that
+/// can omit the tags, it's the first `<head>` and ends at the `<body>`.
+struct HeadInjector<'a> {
+    injections: &'a [(Injection, Placement)],
+    html: bool,
+    state: Head,
+}
+
+impl HeadInjector<'_> {
+    /// Called at the start tag `tag` found at `depth`, before its
+    /// attributes are patched
+    fn before_start(&mut self, patch: &mut Patch, tag: &Tag, local_name: &str, depth: usize) {
+        if !self.html && depth == 0 && local_name != "html" {
+            self.state = Head::Done;
+        }
+        let missing = if self.html {
+            !matches!(local_name, "html" | "head")
+        } else {
+            depth == 1 && local_name != "head"
+        };
+        if self.state == Head::Missing && missing {
+            patch.insert(tag.span.start, &head_markup(self.injections, self.html));
+            self.state = Head::Done;
+        }
+        if self.html && self.state == Head::Open && local_name == "body" {
+            self.close(patch, tag.span.start);
         }
     }
 
-    Ok(b)
+    /// Called at the start tag `tag` found at `depth`, after its
+    /// attributes are patched
+    fn after_start(
+        &mut self,
+        patch: &mut Patch,
+        tag: &Tag,
+        local_name: &str,
+        depth: usize,
+        empty: bool,
+    ) {
+        if self.state != Head::Missing || local_name != "head" || !(self.html || depth == 1) {
+            return;
+        }
+        if empty {
+            let markup = format!(
+                ">{}{}</{}>",
+                injections_markup(self.injections, Placement::Start, self.html),
+                injections_markup(self.injections, Placement::End, self.html),
+                tag.name
+            );
+            patch.replace(tag.span.end - 2..tag.span.end, &markup);
+            self.state = Head::Done;
+        } else {
+            let markup = injections_markup(self.injections, Placement::Start, self.html);
+            patch.insert(tag.span.end, &markup);
+            self.state = Head::Open;
+        }
+    }
+
+    /// Called at the end tag `tag`, `depth` being the one of its element
+    fn end(&mut self, patch: &mut Patch, tag: &Tag, local_name: &str, depth: usize) {
+        let head = if self.html {
+            local_name == "head"
+        } else {
+            depth == 1
+        };
+        let root = if self.html {
+            local_name == "html"
+        } else {
+            depth == 0
+        };
+        if self.state == Head::Open && head {
+            self.close(patch, tag.span.start);
+        } else if self.state == Head::Missing && root {
+            patch.insert(tag.span.start, &head_markup(self.injections, self.html));
+            self.state = Head::Done;
+        }
+    }
+
+    /// Writes the injections at the end of the head, before `position`
+    fn close(&mut self, patch: &mut Patch, position: usize) {
+        patch.insert(
+            position,
+            &injections_markup(self.injections, Placement::End, self.html),
+        );
+        self.state = Head::Done;
+    }
 }
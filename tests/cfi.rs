@@ -0,0 +1,264 @@
+mod common;
+
+use epub::cfi::{Cfi, CfiError, DomPosition, Step};
+use epub::doc::{DocError, EpubDoc, EpubOptions};
+use epub::search::SearchOptions;
+use std::io::Cursor;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref id="second" idref="c2"/>"#,
+    );
+    let c1 = common::xhtml("<p>Intro</p>");
+    let c2 = common::xhtml(
+        r#"<h1 id="title">Title</h1>
+<p id="para">Some    <em>very</em> long
+  text, &#x1F600; here.</p>
+<p>Last</p>"#,
+    );
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn parse_and_serialize() {
+    let examples = [
+        "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)",
+        "epubcfi(/6/4!/4/10/1:0[yyy,zzz;s=b])",
+        "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05],/2/1:1,/3:4)",
+        "epubcfi(/6/4[ch^[1^]]!/4)",
+    ];
+    for example in examples {
+        let cfi = Cfi::parse(example).unwrap();
+        assert_eq!(cfi.to_string(), example);
+    }
+
+    let cfi: Cfi = "#epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)"
+        .parse()
+        .unwrap();
+    assert_eq!(cfi.path.steps.len(), 5);
+    assert!(cfi.path.steps[2].indirect);
+    assert_eq!(cfi.path.steps[2].id().as_deref(), Some("body01"));
+    assert_eq!(cfi.path.offset.as_ref().unwrap().offset, 10);
+    assert!(!cfi.is_range());
+
+    let cfi = Cfi::parse("/6/4[ch^[1^];s=a]!/4").unwrap();
+    assert_eq!(cfi.path.steps[1].id().as_deref(), Some("ch[1]"));
+    assert_eq!(Step::new(4, Some("a,b")).assertion.as_deref(), Some("a^,b"));
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(Cfi::parse("epubcfi()"), Err(CfiError::Invalid(8)));
+    assert_eq!(Cfi::parse("epubcfi(/6/x)"), Err(CfiError::Invalid(11)));
+    assert_eq!(Cfi::parse("epubcfi(/6/4"), Err(CfiError::Invalid(12)));
+    assert_eq!(Cfi::parse("/6/4[open"), Err(CfiError::Invalid(9)));
+    assert_eq!(Cfi::parse("/6/4!"), Err(CfiError::Invalid(5)));
+    assert_eq!(Cfi::parse("/6/4,/2"), Err(CfiError::Invalid(7)));
+    assert_eq!(Cfi::parse("/6/4!/2~2.5"), Err(CfiError::Unsupported('~')));
+}
+
+#[test]
+fn ordering() {
+    let mut cfis: Vec<Cfi> = [
+        "/6/4!/4/10/3:10",
+        "/6/4!/4/10",
+        "/6/2!/4",
+        "/6/4!/4/10/3:2",
+        "/6/4!/4/2",
+        "/6/4!/4/10,/3:2,/3:5",
+        "/6/4!/4/10/3:2[x]",
+    ]
+    .iter()
+    .map(|s| Cfi::parse(s).unwrap())
+    .collect();
+    cfis.sort();
+    let sorted: Vec<String> = cfis.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        sorted,
+        [
+            "epubcfi(/6/2!/4)",
+            "epubcfi(/6/4!/4/2)",
+            "epubcfi(/6/4!/4/10)",
+            "epubcfi(/6/4!/4/10/3:2)",
+            "epubcfi(/6/4!/4/10/3:2[x])",
+            "epubcfi(/6/4!/4/10,/3:2,/3:5)",
+            "epubcfi(/6/4!/4/10/3:10)",
+        ]
+    );
+}
+
+#[test]
+fn generate_and_resolve() {
+    let mut doc = book();
+    let text = doc.chapter_text(1).unwrap();
+    assert_eq!(text, "Title\nSome very long text, \u{1F600} here.\nLast");
+
+    let para = DomPosition {
+        path: vec![1, 1],
+        offset: None,
+    };
+    let cfi = doc.generate_cfi(1, &para).unwrap();
+    assert_eq!(cfi.to_string(), "epubcfi(/6/4[second]!/4/4[para])");
+    let location = doc.resolve_cfi(&cfi).unwrap();
+    assert_eq!(location.spine_index, 1);
+    assert_eq!(location.text_offset, text.find("Some").unwrap());
+    assert_eq!(location.element_id.as_deref(), Some("para"));
+
+    // after the collapsed whitespace and the surrogate pair
+    let offset = text.find("here").unwrap();
+    let cfi = doc.generate_text_cfi(1, offset).unwrap();
+    assert_eq!(cfi.to_string(), "epubcfi(/6/4[second]!/4/4[para]/3:17)");
+    assert_eq!(doc.resolve_cfi(&cfi).unwrap().text_offset, offset);
+
+    // the same character, counted in the text of the paragraph
+    let here = DomPosition {
+        path: vec![1, 1],
+        offset: Some(
+            "Some    very long\n  text, \u{1F600} "
+                .encode_utf16()
+                .count(),
+        ),
+    };
+    assert_eq!(doc.generate_cfi(1, &here).unwrap(), cfi);
+    let very = DomPosition {
+        path: vec![1, 1],
+        offset: Some(9),
+    };
+    assert_eq!(
+        doc.generate_cfi(1, &very).unwrap().to_string(),
+        "epubcfi(/6/4[second]!/4/4[para]/2/1:1)"
+    );
+
+    let cfi = doc.generate_text_cfi(0, 2).unwrap();
+    assert_eq!(cfi.to_string(), "epubcfi(/6/2!/4/2/1:2)");
+    let location = doc.resolve_cfi(&cfi).unwrap();
+    assert_eq!((location.spine_index, location.text_offset), (0, 2));
+
+    let nope = DomPosition {
+        path: vec![1, 5],
+        offset: None,
+    };
+    let err = doc.generate_cfi(1, &nope).unwrap_err();
+    assert!(matches!(err, DocError::Cfi(CfiError::NotFound(_))));
+    let past = DomPosition {
+        path: vec![1, 2],
+        offset: Some(5),
+    };
+    let err = doc.generate_cfi(1, &past).unwrap_err();
+    assert!(matches!(err, DocError::Cfi(CfiError::NotFound(_))));
+    let err = doc.generate_text_cfi(2, 0).unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::SpineIndexOutOfBounds { index: 2, len: 2 }
+    ));
+}
+
+#[test]
+fn resolve_with_assertions() {
+    let mut doc = book();
+    let text = doc.chapter_text(1).unwrap();
+
+    // the ids win over wrong indices
+    let cfi = Cfi::parse("epubcfi(/6/2[second]!/4/2[para])").unwrap();
+    let location = doc.resolve_cfi(&cfi).unwrap();
+    assert_eq!(location.spine_index, 1);
+    assert_eq!(location.text_offset, text.find("Some").unwrap());
+
+    // a location missing in the document resolves to the next one
+    let cfi = Cfi::parse("epubcfi(/6/4!/4/5:0)").unwrap();
+    let location = doc.resolve_cfi(&cfi).unwrap();
+    assert_eq!(location.text_offset, text.find("Last").unwrap());
+
+    let cfi = Cfi::parse("epubcfi(/6/8!/4)").unwrap();
+    let err = doc.resolve_cfi(&cfi).unwrap_err();
+    assert!(matches!(err, DocError::Cfi(CfiError::NotFound(_))));
+    let cfi = Cfi::parse("epubcfi(/6/4/4)").unwrap();
+    assert!(doc.resolve_cfi(&cfi).is_err());
+}
+
+#[test]
+fn dropped_spine_items() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref linear="no"/>
+    <itemref idref="c2"/>"#,
+    );
+    let c1 = common::xhtml("<p>Intro</p>");
+    let c2 = common::xhtml(r#"<p id="para">Text</p>"#);
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+        ],
+    );
+    let mut doc =
+        EpubDoc::from_reader_with_options(Cursor::new(epub), EpubOptions::lenient()).unwrap();
+    assert_eq!(doc.spine.len(), 2);
+
+    // the steps of the itemref, not of the spine index
+    let para = DomPosition {
+        path: vec![1, 0],
+        offset: None,
+    };
+    let cfi = doc.generate_cfi(1, &para).unwrap();
+    assert_eq!(cfi.to_string(), "epubcfi(/6/6!/4/2[para])");
+    assert_eq!(doc.resolve_cfi(&cfi).unwrap().spine_index, 1);
+
+    let cfi = Cfi::parse("epubcfi(/6/4!/4)").unwrap();
+    let err = doc.resolve_cfi(&cfi).unwrap_err();
+    assert!(matches!(err, DocError::Cfi(CfiError::NotFound(_))));
+}
+
+#[test]
+fn ranges() {
+    let mut doc = book();
+    let text = doc.chapter_text(1).unwrap();
+    let start = text.find("very").unwrap();
+    let end = text.find("text").unwrap();
+
+    let cfi = doc.generate_cfi_range(1, start..end).unwrap();
+    assert_eq!(
+        cfi.to_string(),
+        "epubcfi(/6/4[second]!/4/4[para],/2/1:0,/3:8)"
+    );
+    let (from, to) = doc.resolve_cfi_range(&cfi).unwrap();
+    assert_eq!((from.text_offset, to.text_offset), (start, end));
+
+    let hits = doc.search("long", SearchOptions::default()).unwrap();
+    let (from, to) = doc.resolve_cfi_range(&hits[0].cfi).unwrap();
+    assert_eq!(&text[from.text_offset..to.text_offset], "long");
+}
+
+#[test]
+fn offsets_inside_characters() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let c1 = common::xhtml("<p>héllo</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", c1.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    assert_eq!(doc.chapter_text(0).unwrap(), "héllo");
+
+    // the second byte of "é" is moved back to its start
+    let cfi = doc.generate_text_cfi(0, 2).unwrap();
+    assert_eq!(cfi, doc.generate_text_cfi(0, 1).unwrap());
+    assert_eq!(cfi.to_string(), "epubcfi(/6/2!/4/2/1:1)");
+    assert_eq!(doc.resolve_cfi(&cfi).unwrap().text_offset, 1);
+
+    let cfi = doc.generate_cfi_range(0, 2..6).unwrap();
+    assert_eq!(cfi.to_string(), "epubcfi(/6/2!/4/2,/1:1,/1:5)");
+}
@@ -0,0 +1,76 @@
+//! Helpers to build small epub files in memory for the tests.
+#![allow(dead_code)]
+
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+pub const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+/// Returns a minimal package document with the `manifest` and `spine`
+/// contents provided.
+pub fn opf(manifest: &str, spine: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:0f4a5d8e-3c0e-4b5b-9a59-2f8e7b2d1c3a</dc:identifier>
+    <dc:title>Test book</dc:title>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+{}
+  </manifest>
+  <spine>
+{}
+  </spine>
+</package>"#,
+        manifest, spine
+    )
+}
+
+/// Returns a xhtml document with the `body` provided.
+pub fn xhtml(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Chapter</title></head>
+<body>{}</body>
+</html>"#,
+        body
+    )
+}
+
+/// Builds a zip file with the `files` provided, in order. The mimetype
+/// file is always stored without compression.
+pub fn build_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        let method = if *name == "mimetype" {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+        let options = SimpleFileOptions::default().compression_method(method);
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// Builds a valid epub with the package document `opf` and the extra
+/// `files` provided.
+pub fn build_epub(opf: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut all: Vec<(&str, &[u8])> = vec![
+        ("mimetype", b"application/epub+zip"),
+        ("META-INF/container.xml", CONTAINER.as_bytes()),
+        ("OEBPS/content.opf", opf.as_bytes()),
+    ];
+    all.extend_from_slice(files);
+    build_zip(&all)
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::encryption::DrmScheme;
+use std::io::Cursor;
+
+fn encryption(items: &[(&str, &str, &str)]) -> String {
+    let items: String = items
+        .iter()
+        .map(|(algorithm, uri, key_info)| {
+            format!(
+                r#"<enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="{}"/>
+    <ds:KeyInfo xmlns:ds="http://www.w3.org/2000/09/xmldsig#">{}</ds:KeyInfo>
+    <enc:CipherData><enc:CipherReference URI="{}"/></enc:CipherData>
+  </enc:EncryptedData>"#,
+                algorithm, key_info, uri
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+    xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+  {}
+</encryption>"#,
+        items
+    )
+}
+
+fn scheme(files: &[(&str, &[u8])]) -> DrmScheme {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    let mut all: Vec<(&str, &[u8])> = vec![("OEBPS/c1.xhtml", chapter.as_bytes())];
+    all.extend_from_slice(files);
+    let epub = common::build_epub(&opf, &all);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    doc.drm_report().scheme
+}
+
+const AES: &str = "http://www.w3.org/2001/04/xmlenc#aes128-cbc";
+
+#[test]
+fn not_protected() {
+    assert_eq!(scheme(&[]), DrmScheme::None);
+
+    let fonts = encryption(&[("http://www.idpf.org/2008/embedding", "OEBPS/font.otf", "")]);
+    let fonts = scheme(&[("META-INF/encryption.xml", fonts.as_bytes())]);
+    assert_eq!(fonts, DrmScheme::FontObfuscationOnly);
+    assert!(!fonts.is_protected());
+}
+
+#[test]
+fn stray_rights() {
+    let rights = r#"<?xml version="1.0"?>
+<rights xmlns="http://example.com/rights"><holder>The publisher</holder></rights>"#;
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", chapter.as_bytes()),
+            ("META-INF/rights.xml", rights.as_bytes()),
+        ],
+    );
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    let report = doc.drm_report();
+    assert_eq!(report.scheme, DrmScheme::None);
+    assert!(!report.is_protected());
+    assert!(report.has_rights);
+
+    let fonts = encryption(&[("http://www.idpf.org/2008/embedding", "OEBPS/font.otf", "")]);
+    let fonts = scheme(&[
+        ("META-INF/encryption.xml", fonts.as_bytes()),
+        ("META-INF/rights.xml", rights.as_bytes()),
+    ]);
+    assert_eq!(fonts, DrmScheme::FontObfuscationOnly);
+}
+
+#[test]
+fn adobe_adept() {
+    let enc = encryption(&[(
+        AES,
+        "OEBPS/c1.xhtml",
+        "<ds:KeyName>urn:uuid:0f4a5d8e-3c0e-4b5b-9a59-2f8e7b2d1c3a</ds:KeyName>",
+    )]);
+    let rights = r#"<?xml version="1.0"?>
+<adept:rights xmlns:adept="http://ns.adobe.com/adept"><adept:licenseToken/></adept:rights>"#;
+    let scheme = scheme(&[
+        ("META-INF/encryption.xml", enc.as_bytes()),
+        ("META-INF/rights.xml", rights.as_bytes()),
+    ]);
+    assert_eq!(scheme, DrmScheme::AdobeAdept);
+    assert!(scheme.is_protected());
+}
+
+#[test]
+fn readium_lcp() {
+    let enc = encryption(&[(
+        "http://www.w3.org/2001/04/xmlenc#aes256-cbc",
+        "OEBPS/c1.xhtml",
+        r#"<ds:RetrievalMethod URI="license.lcpl#/encryption/content_key"
+            Type="http://readium.org/2014/01/lcp#EncryptedContentKey"/>"#,
+    )]);
+    assert_eq!(
+        scheme(&[("META-INF/encryption.xml", enc.as_bytes())]),
+        DrmScheme::ReadiumLcp
+    );
+    assert_eq!(
+        scheme(&[("META-INF/license.lcpl", b"{}")]),
+        DrmScheme::ReadiumLcp
+    );
+}
+
+#[test]
+fn apple_fairplay() {
+    let enc = encryption(&[("http://itunes.apple.com/dataenc", "OEBPS/c1.xhtml", "")]);
+    assert_eq!(
+        scheme(&[("META-INF/encryption.xml", enc.as_bytes())]),
+        DrmScheme::AppleFairPlay
+    );
+    assert_eq!(
+        scheme(&[("META-INF/sinf.xml", b"<fairplay:sinf/>")]),
+        DrmScheme::AppleFairPlay
+    );
+}
+
+#[test]
+fn unknown_and_report() {
+    let enc = encryption(&[
+        ("http://www.idpf.org/2008/embedding", "OEBPS/font.otf", ""),
+        (AES, "OEBPS/c1.xhtml", ""),
+    ]);
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("META-INF/encryption.xml", enc.as_bytes()),
+            ("META-INF/signatures.xml", b"<signatures/>"),
+        ],
+    );
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    let report = doc.drm_report();
+
+    assert_eq!(report.scheme, DrmScheme::Unknown);
+    assert!(report.is_protected());
+    assert!(report.has_signatures);
+    assert!(!report.has_rights);
+    assert_eq!(report.resources.len(), 2);
+    let encrypted: Vec<_> = report.encrypted().collect();
+    assert_eq!(encrypted.len(), 1);
+    assert_eq!(encrypted[0].path.as_str(), "OEBPS/c1.xhtml");
+    assert_eq!(encrypted[0].algorithm, AES);
+    assert_eq!(report.to_string(), "Unknown DRM (1 encrypted resources)");
+}
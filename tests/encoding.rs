@@ -0,0 +1,154 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use std::io::Cursor;
+
+/// Returns the `text` encoded in latin1
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| u8::try_from(c).unwrap()).collect()
+}
+
+fn encode_utf16(text: &str, little_endian: bool, bom: bool) -> Vec<u8> {
+    let bom = bom.then_some('\u{feff}');
+    bom.into_iter()
+        .chain(text.chars())
+        .collect::<String>()
+        .encode_utf16()
+        .flat_map(|c| {
+            if little_endian {
+                c.to_le_bytes()
+            } else {
+                c.to_be_bytes()
+            }
+        })
+        .collect()
+}
+
+fn book(files: &[(&str, &[u8])]) -> EpubDoc<Cursor<Vec<u8>>> {
+    let items: Vec<String> = files
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| {
+            let mime = if name.ends_with(".css") {
+                "text/css"
+            } else {
+                "application/xhtml+xml"
+            };
+            format!(
+                r#"<item id="r{}" href="{}" media-type="{}"/>"#,
+                i, name, mime
+            )
+        })
+        .collect();
+    let spine: Vec<String> = (0..files.len())
+        .map(|i| format!(r#"<itemref idref="r{}"/>"#, i))
+        .collect();
+    let opf = common::opf(&items.join("\n"), &spine.join("\n"));
+    let files: Vec<(String, &[u8])> = files
+        .iter()
+        .map(|(name, content)| (format!("OEBPS/{}", name), *content))
+        .collect();
+    let files: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(name, content)| (name.as_str(), *content))
+        .collect();
+    let epub = common::build_epub(&opf, &files);
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+const DECLARED: &str = r#"<?xml version="1.0" encoding="iso-8859-1"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Café</title></head>
+<body><p>Café <img src="crème.png"/></p></body>
+</html>"#;
+
+#[test]
+fn xml_declaration() {
+    let content = latin1(DECLARED);
+    let mut doc = book(&[("c1.xhtml", &content)]);
+    assert_eq!(doc.chapter_text(0).unwrap(), "Café");
+    assert_eq!(doc.get_resource_str("r0").unwrap().0, DECLARED);
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(
+        current,
+        DECLARED
+            .replace("iso-8859-1", "UTF-8")
+            .replace("crème.png", "epub://OEBPS/cr%C3%A8me.png")
+    );
+}
+
+#[test]
+fn meta_charset() {
+    let chapter = r#"<html xmlns="http://www.w3.org/1999/xhtml">
+<head><meta charset="windows-1252"/><title>Quotes</title></head>
+<body><p>“Quoted” text</p></body>
+</html>"#;
+    let content: Vec<u8> = chapter
+        .chars()
+        .map(|c| match c {
+            '“' => 0x93,
+            '”' => 0x94,
+            c => u8::try_from(c).unwrap(),
+        })
+        .collect();
+    let http_equiv = common::xhtml("<p>Caf\u{e9}</p>")
+        .replace(r#" encoding="UTF-8""#, "")
+        .replace(
+            "<head>",
+            r#"<head><meta http-equiv="Content-Type" content="text/html; charset=ISO-8859-1"/>"#,
+        );
+    let mut doc = book(&[("c1.xhtml", &content), ("c2.xhtml", &latin1(&http_equiv))]);
+
+    assert_eq!(doc.chapter_text(0).unwrap(), "“Quoted” text");
+    assert_eq!(doc.chapter_text(1).unwrap(), "Café");
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(current, chapter.replace("windows-1252", "utf-8"));
+    doc.go_next();
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(current, http_equiv.replace("ISO-8859-1", "utf-8"));
+}
+
+#[test]
+fn meta_charset_of_utf8_content() {
+    // the meta is kept from the source, the content is UTF-8
+    let chapter =
+        common::xhtml("<p>Café</p>").replace("<head>", r#"<head><meta charset="iso-8859-1"/>"#);
+    let mut doc = book(&[("c1.xhtml", chapter.as_bytes())]);
+    assert_eq!(doc.chapter_text(0).unwrap(), "Café");
+    assert_eq!(
+        doc.get_current_with_epub_uris().unwrap(),
+        chapter.as_bytes()
+    );
+}
+
+#[test]
+fn utf16() {
+    let chapter = common::xhtml("<p>Café</p>").replace("UTF-8", "UTF-16");
+    let without_bom = encode_utf16(&chapter, true, false);
+    let big_endian = encode_utf16(&chapter, false, true);
+    let mut doc = book(&[("c1.xhtml", &without_bom), ("c2.xhtml", &big_endian)]);
+
+    assert_eq!(doc.chapter_text(0).unwrap(), "Café");
+    assert_eq!(doc.chapter_text(1).unwrap(), "Café");
+    assert_eq!(doc.get_resource_str("r0").unwrap().0, chapter);
+    assert_eq!(doc.get_resource_str("r1").unwrap().0, chapter);
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(current, chapter.replace("UTF-16", "UTF-8"));
+}
+
+#[test]
+fn stylesheet_charset() {
+    let css = "@charset \"iso-8859-1\";\np::before { content: \"é\"; background: url(a.png) }";
+    let mut doc = book(&[("style.css", &latin1(css))]);
+    assert_eq!(doc.get_resource_str("r0").unwrap().0, css);
+
+    let (current, _) = doc.get_resource_with_epub_uris("r0").unwrap();
+    assert_eq!(
+        String::from_utf8(current).unwrap(),
+        css.replace("iso-8859-1", "UTF-8")
+            .replace("url(a.png)", r#"url("epub://OEBPS/a.png")"#)
+    );
+}
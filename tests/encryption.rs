@@ -0,0 +1,98 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::encryption::Obfuscation;
+use std::io::Cursor;
+
+const IDENTIFIER: &str = "urn:uuid:0f4a5d8e-3c0e-4b5b-9a59-2f8e7b2d1c3a";
+
+fn font() -> Vec<u8> {
+    (0..3000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn obfuscated(obfuscation: Obfuscation) -> Vec<u8> {
+    let key = obfuscation.key(IDENTIFIER).unwrap();
+    let mut data = font();
+    obfuscation.obfuscate(&key, &mut data);
+    data
+}
+
+#[test]
+fn obfuscation_keys() {
+    let key = Obfuscation::Idpf.key(IDENTIFIER).unwrap();
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(hex, "b12c23c27a621d5940e9076ebdabb36060fb89be");
+    assert_eq!(
+        Obfuscation::Idpf.key(&format!(" {}\n\t", IDENTIFIER)),
+        Some(key)
+    );
+
+    let key = Obfuscation::Adobe.key(IDENTIFIER).unwrap();
+    assert_eq!(key.len(), 16);
+    assert_eq!(key[..4], [0x0f, 0x4a, 0x5d, 0x8e]);
+    assert_eq!(Obfuscation::Adobe.key("isbn:1234"), None);
+}
+
+#[test]
+fn obfuscated_bytes() {
+    let original = font();
+    for obfuscation in [Obfuscation::Idpf, Obfuscation::Adobe] {
+        let data = obfuscated(obfuscation);
+        let len = obfuscation.header_len();
+        assert_ne!(data[..len], original[..len]);
+        assert_eq!(data[len..], original[len..]);
+    }
+}
+
+#[test]
+fn fonts_are_deobfuscated() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="idpf" href="Fonts/idpf.otf" media-type="font/otf"/>
+    <item id="adobe" href="Fonts/adobe%20font.otf" media-type="font/otf"/>
+    <item id="plain" href="Fonts/plain.otf" media-type="font/otf"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let encryption = r#"<?xml version="1.0" encoding="UTF-8"?>
+<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+    xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+    <enc:CipherData><enc:CipherReference URI="OEBPS/Fonts/idpf.otf"/></enc:CipherData>
+  </enc:EncryptedData>
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://ns.adobe.com/pdf/enc#RC"/>
+    <enc:CipherData><enc:CipherReference URI="OEBPS/Fonts/adobe%20font.otf"/></enc:CipherData>
+  </enc:EncryptedData>
+</encryption>"#;
+    let chapter = common::xhtml("<p>Hello</p>");
+    let idpf = obfuscated(Obfuscation::Idpf);
+    let adobe = obfuscated(Obfuscation::Adobe);
+    let plain = font();
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("META-INF/encryption.xml", encryption.as_bytes()),
+            ("OEBPS/c1.xhtml", chapter.as_bytes()),
+            ("OEBPS/Fonts/idpf.otf", &idpf),
+            ("OEBPS/Fonts/adobe font.otf", &adobe),
+            ("OEBPS/Fonts/plain.otf", &plain),
+        ],
+    );
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+
+    assert_eq!(doc.encryption().len(), 2);
+    assert_eq!(
+        doc.encryption()[1].path.as_str(),
+        "OEBPS/Fonts/adobe font.otf"
+    );
+    assert_eq!(doc.encryption()[1].obfuscation(), Some(Obfuscation::Adobe));
+
+    for id in ["idpf", "adobe", "plain"] {
+        assert_eq!(doc.get_resource(id).unwrap().0, font(), "{}", id);
+    }
+    assert_eq!(
+        doc.get_resource_by_path("OEBPS/Fonts/idpf.otf").unwrap(),
+        font()
+    );
+}
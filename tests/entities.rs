@@ -0,0 +1,55 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use std::io::Cursor;
+
+const CHAPTER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html [
+  <!ENTITY publisher "Acme &#38;#38; Sons &amp; Co">
+  <!ENTITY mdash "--">
+  <!ENTITY % ignored "parameter">
+]>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Chapter</title>
+<style>p { background: url(&quot;../Images/a&amp;b.png&quot;) }</style></head>
+<body>
+<p>Caf&eacute; &mdash; wait&hellip; &rarr; &publisher;</p>
+<p><img src="../Images/a.png" alt="&laquo;x&raquo;"/></p>
+</body>
+</html>"#;
+
+fn doc() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.xhtml", CHAPTER.as_bytes())]);
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn text_with_entities() {
+    let mut doc = doc();
+    assert_eq!(
+        doc.chapter_text(0).unwrap(),
+        "Café -- wait… → Acme & Sons & Co"
+    );
+}
+
+#[test]
+fn rewrite_with_entities() {
+    let mut doc = doc();
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(
+        current,
+        CHAPTER
+            .replace(
+                "&quot;../Images/a&amp;b.png&quot;",
+                "\"epub://OEBPS/Images/a&amp;b.png\""
+            )
+            .replace(
+                r#"src="../Images/a.png""#,
+                r#"src="epub://OEBPS/Images/a.png""#
+            )
+    );
+}
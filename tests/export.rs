@@ -0,0 +1,192 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::export::{AssetMode, HtmlOptions};
+use std::io::Cursor;
+
+const C1: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en">
+<head>
+<title>One</title>
+<link rel="stylesheet" type="text/css" href="../Styles/style.css"/>
+<style type="text/css">h1 { color: red }</style>
+</head>
+<body class="chapter" id="start">
+<h1 id="title" onclick="alert(1)">One</h1>
+<p><a href="c2.xhtml#title">next</a> <a href="notes.xhtml#n1">note</a> <a href="https://example.com">site</a> 1 &lt; 2</p>
+<p><img src="../Images/a.png" alt="a"/><br/></p>
+<script type="text/javascript">alert(2)</script>
+</body>
+</html>"##;
+
+const C2: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+<title>Two</title>
+<link rel="stylesheet" type="text/css" href="../Styles/style.css"/>
+</head>
+<body>
+<h1 id="title">Two</h1>
+<p><a href="c1.xhtml">back</a> <a href="#title">top</a> <a href="c1.xhtml#start">start</a></p>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><image xlink:href="../Images/a.png"/></svg>
+<p><a href=" JavaScript:alert(3)">js</a></p>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><script>alert(4)</script><a xlink:href="javascript:alert(5)"><text>svg link</text></a></svg>
+</body>
+</html>"##;
+
+const CSS: &str = "@charset \"UTF-8\";\nbody { background: url(../Images/a.png) }";
+
+const NCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <docTitle><text>Contents</text></docTitle>
+  <navMap>
+    <navPoint id="n1" playOrder="1">
+      <navLabel><text>One</text></navLabel>
+      <content src="Text/c1.xhtml"/>
+      <navPoint id="n2" playOrder="2">
+        <navLabel><text>Title of two</text></navLabel>
+        <content src="Text/c2.xhtml#title"/>
+      </navPoint>
+    </navPoint>
+    <navPoint id="n3" playOrder="3">
+      <navLabel><text>Notes</text></navLabel>
+      <content src="Text/notes.xhtml"/>
+    </navPoint>
+  </navMap>
+</ncx>"#;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    book_without("")
+}
+
+/// Returns the book without the file `missing`
+fn book_without(missing: &str) -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="Text/c2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="Text/notes.xhtml" media-type="application/xhtml+xml"/>
+    <item id="css" href="Styles/style.css" media-type="text/css"/>
+    <item id="img" href="Images/a.png" media-type="image/png"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="notes" linear="no"/>
+    <itemref idref="c2"/>"#,
+    )
+    .replace("<spine>", r#"<spine toc="ncx">"#);
+    let notes = common::xhtml(r#"<aside id="n1">A note</aside>"#);
+    let files: Vec<(&str, &[u8])> = vec![
+        ("OEBPS/Text/c1.xhtml", C1.as_bytes()),
+        ("OEBPS/Text/c2.xhtml", C2.as_bytes()),
+        ("OEBPS/Text/notes.xhtml", notes.as_bytes()),
+        ("OEBPS/Styles/style.css", CSS.as_bytes()),
+        ("OEBPS/Images/a.png", b"png"),
+        ("OEBPS/toc.ncx", NCX.as_bytes()),
+    ];
+    let files: Vec<_> = files.into_iter().filter(|(f, _)| *f != missing).collect();
+    let epub = common::build_epub(&opf, &files);
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+/// Returns the positions of the `parts` in `text`, failing if any is missing
+fn positions(text: &str, parts: &[&str]) -> Vec<usize> {
+    parts
+        .iter()
+        .map(|part| {
+            text.find(part)
+                .unwrap_or_else(|| panic!("{} not in {}", part, text))
+        })
+        .collect()
+}
+
+#[test]
+fn export_html() {
+    let mut doc = book();
+    let export = doc.export_html().unwrap();
+    let html = &export.html;
+    assert!(export.assets.is_empty());
+
+    let found = positions(
+        html,
+        &[
+            "<!DOCTYPE html>\n<html lang=\"en\">",
+            "<title>Test book</title>",
+            "<style>\nbody { background: url(\"data:image/png;base64,cG5n\") }\nh1 { color: red }\n</style>",
+            r#"<nav id="toc">"#,
+            "<h1>Contents</h1>",
+            r##"<li><a href="#chapter-0">One</a>"##,
+            r##"<li><a href="#title-2">Title of two</a></li>"##,
+            "<li><span>Notes</span></li>",
+            "</nav>",
+            r#"<section id="chapter-0" class="chapter" lang="en">"#,
+            r#"<h1 id="title">One</h1>"#,
+            r##"<a href="#title-2">next</a> <a>note</a> <a href="https://example.com">site</a> 1 &lt; 2"##,
+            r#"<img src="data:image/png;base64,cG5n" alt="a"><br>"#,
+            "</section>",
+            r#"<section id="chapter-2">"#,
+            r#"<h1 id="title-2">Two</h1>"#,
+            r##"<a href="#chapter-0">back</a> <a href="#title-2">top</a> <a href="#chapter-0">start</a>"##,
+            r#"<svg><image xlink:href="data:image/png;base64,cG5n"/></svg>"#,
+        ],
+    );
+    assert!(found.windows(2).all(|w| w[0] < w[1]), "{}", html);
+    assert_eq!(html.matches("background").count(), 1, "{}", html);
+    assert!(!html.contains("script"), "{}", html);
+    assert!(!html.contains("alert"), "{}", html);
+    assert!(html.contains("<p><a>js</a></p>"), "{}", html);
+    assert!(
+        html.contains("<svg><a><text>svg link</text></a></svg>"),
+        "{}",
+        html
+    );
+    assert!(!html.contains("onclick"), "{}", html);
+    assert!(!html.contains("A note"), "{}", html);
+}
+
+#[test]
+fn export_html_with_options() {
+    let mut doc = book();
+    let options = HtmlOptions {
+        assets: AssetMode::Folder("files/".into()),
+        skip_toc: true,
+        include_non_linear: true,
+    };
+    let export = doc.export_html_with(&options).unwrap();
+    let html = &export.html;
+
+    assert!(!html.contains("<nav"), "{}", html);
+    assert!(
+        html.contains(r#"<img src="files/OEBPS/Images/a.png" alt="a">"#),
+        "{}",
+        html
+    );
+    assert!(html.contains(r##"<a href="#n1">note</a>"##), "{}", html);
+    let found = positions(
+        html,
+        &[
+            r#"<section id="chapter-0""#,
+            r#"<section id="chapter-1">"#,
+            r#"<aside id="n1">A note</aside>"#,
+            r#"<section id="chapter-2">"#,
+        ],
+    );
+    assert!(found.windows(2).all(|w| w[0] < w[1]), "{}", html);
+
+    assert_eq!(export.assets.len(), 1);
+    assert_eq!(export.assets[0].path, "files/OEBPS/Images/a.png");
+    assert_eq!(export.assets[0].mime, "image/png");
+    assert_eq!(export.assets[0].content, b"png");
+}
+
+#[test]
+fn export_html_without_broken_chapters() {
+    let mut doc = book_without("OEBPS/Text/c2.xhtml");
+    let html = doc.export_html().unwrap().html;
+    assert!(html.contains(r#"<section id="chapter-0""#), "{}", html);
+    assert!(!html.contains(r#"<section id="chapter-2">"#), "{}", html);
+    assert!(
+        html.contains("<li><span>Title of two</span></li>"),
+        "{}",
+        html
+    );
+    assert!(html.contains("<a>next</a>"), "{}", html);
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "html5")]
+
+mod common;
+
+use epub::doc::{EpubDoc, EpubOptions};
+use epub::inject::{Injection, Placement};
+use epub::text::TextOptions;
+use std::io::Cursor;
+
+const BROKEN: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Broken</title></head>
+<body>
+<p>One&hellip;<br>two</p>
+<p>Note<a epub:type="noteref" href="#n1">1</a></p>
+<aside epub:type="footnote" id="n1">The note</aside>
+<img src=../Images/a.png alt=broken>
+</body>
+</html>"##;
+
+const HTML: &str = r#"<!DOCTYPE html>
+<title>Served as html</title>
+<script>if (a<b && c) { document.write("<img src='x.png'>") }</script>
+<p class=intro>Hello <b>world
+<p>Second&nbsp;paragraph <img SRC="../Images/b.png"></p>"#;
+
+fn book(options: EpubOptions) -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="Text/c2.html" media-type="text/html"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2"/>"#,
+    );
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/Text/c1.xhtml", BROKEN.as_bytes()),
+            ("OEBPS/Text/c2.html", HTML.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader_with_options(Cursor::new(epub), options).unwrap()
+}
+
+#[test]
+fn text_of_broken_xhtml() {
+    let mut doc = book(EpubOptions::default());
+    assert_eq!(doc.chapter_text(0).unwrap(), "One…\ntwo\nNote1\nThe note");
+    let options = TextOptions {
+        skip_notes: true,
+        ..TextOptions::default()
+    };
+    assert_eq!(
+        doc.chapter_text_with(0, options).unwrap(),
+        "One…\ntwo\nNote"
+    );
+
+    let mut doc = book(EpubOptions::strict());
+    assert!(doc.chapter_text(0).is_err());
+}
+
+#[test]
+fn text_of_html() {
+    let mut doc = book(EpubOptions::strict());
+    assert_eq!(
+        doc.chapter_text(1).unwrap(),
+        "Hello world\nSecond paragraph"
+    );
+}
+
+#[test]
+fn rewrite_broken_xhtml() {
+    let mut doc = book(EpubOptions::default());
+    doc.add_extra_css("p { color: red }");
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(
+        current,
+        BROKEN
+            .replace("src=../Images/a.png", r#"src="epub://OEBPS/Images/a.png""#)
+            .replace(
+                r##"href="#n1""##,
+                r##"href="epub://OEBPS/Text/c1.xhtml#n1""##
+            )
+            .replace(
+                "</head>",
+                r#"<style type="text/css">p { color: red }</style></head>"#
+            )
+    );
+}
+
+#[test]
+fn rewrite_html() {
+    let mut doc = book(EpubOptions::default());
+    doc.add_extra_css("p { color: red }");
+    let (current, mime) = doc.get_resource_with_epub_uris("c2").unwrap();
+    assert_eq!(mime, "text/html");
+    assert_eq!(
+        String::from_utf8(current).unwrap(),
+        HTML.replace(
+            "<title>",
+            r#"<head><style type="text/css">p { color: red }</style></head><title>"#
+        )
+        .replace(
+            r#"SRC="../Images/b.png""#,
+            r#"SRC="epub://OEBPS/Images/b.png""#
+        )
+    );
+}
+
+#[test]
+fn inject_raw_text_in_html() {
+    let mut doc = book(EpubOptions::default());
+    doc.inject(
+        Injection::Script("if (a < b && c) { run(\"</SCRIPT>\"); }".into()),
+        Placement::End,
+    );
+    doc.inject(
+        Injection::Style(r#"a[href*="&"] { color: red }"#.into()),
+        Placement::End,
+    );
+    let (current, _) = doc.get_resource_with_epub_uris("c2").unwrap();
+    let current = String::from_utf8(current).unwrap();
+    assert!(
+        current.contains(
+            r#"<script type="text/javascript">if (a < b && c) { run("<\/SCRIPT>"); }</script>"#
+        ),
+        "{}",
+        current
+    );
+    assert!(
+        current.contains(r#"<style type="text/css">a[href*="&"] { color: red }</style>"#),
+        "{}",
+        current
+    );
+}
+
+#[test]
+fn rewrite_html_with_bare_lt() {
+    const LT: &str = r#"<!DOCTYPE html>
+<html><head><title>1 < 2</title></head>
+<body><p>1 < 2 <img src="../Images/a.png"></p></div><p>2 <3</p></body></html>"#;
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.html" media-type="text/html"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.html", LT.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    doc.add_extra_css("p { color: red }");
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert_eq!(
+        current,
+        LT.replace(
+            "</head>",
+            r#"<style type="text/css">p { color: red }</style></head>"#
+        )
+        .replace("../Images/a.png", "epub://OEBPS/Images/a.png")
+    );
+}
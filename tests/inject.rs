@@ -0,0 +1,121 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::inject::{Injection, Placement};
+use std::io::Cursor;
+
+fn doc(chapter: &str) -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="img" href="img.svg" media-type="image/svg+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><rect/></svg>"#;
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", chapter.as_bytes()),
+            ("OEBPS/img.svg", svg.as_bytes()),
+        ],
+    );
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    doc.inject(Injection::Style("p { color: red }".into()), Placement::End);
+    doc.inject(
+        Injection::Viewport("width=device-width".into()),
+        Placement::Start,
+    );
+    doc.inject(
+        Injection::Stylesheet("app://reader/theme.css".into()),
+        Placement::Start,
+    );
+    doc.inject(
+        Injection::ScriptUrl("app://reader/shell.js".into()),
+        Placement::End,
+    );
+    doc.inject(Injection::Script("init(1 < 2)".into()), Placement::End);
+    doc.add_extra_css("body { margin: 0 }");
+    doc
+}
+
+/// Returns the positions of the `parts` in `text`, failing if any is missing
+fn positions(text: &str, parts: &[&str]) -> Vec<usize> {
+    parts
+        .iter()
+        .map(|part| {
+            text.find(part)
+                .unwrap_or_else(|| panic!("{} not in {}", part, text))
+        })
+        .collect()
+}
+
+#[test]
+fn inject_in_head() {
+    let mut doc = doc(&common::xhtml("<p>Hello</p>"));
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    let found = positions(
+        &current,
+        &[
+            "<head>",
+            r#"<meta name="viewport" content="width=device-width"/>"#,
+            r#"<link rel="stylesheet" type="text/css" href="app://reader/theme.css"/>"#,
+            "<title>Chapter</title>",
+            "p { color: red }",
+            r#"<script type="text/javascript" src="app://reader/shell.js"></script>"#,
+            "<![CDATA[init(1 < 2)]]>",
+            "body { margin: 0 }",
+            "</head>",
+        ],
+    );
+    assert!(found.windows(2).all(|w| w[0] < w[1]), "{}", current);
+    // only the script with markup characters is in a CDATA section
+    assert_eq!(current.matches("CDATA").count(), 1, "{}", current);
+}
+
+#[test]
+fn inject_without_head() {
+    let chapter = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><body><p>Hello</p></body></html>"#;
+    let mut doc = doc(chapter);
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    let found = positions(
+        &current,
+        &[
+            "<head>",
+            "<meta name=\"viewport\"",
+            "<link rel=\"stylesheet\"",
+            "p { color: red }",
+            "body { margin: 0 }",
+            "</head>",
+            "<body>",
+        ],
+    );
+    assert!(found.windows(2).all(|w| w[0] < w[1]), "{}", current);
+    assert_eq!(current.matches("<head>").count(), 1);
+}
+
+#[test]
+fn no_injection_in_svg() {
+    let mut doc = doc(&common::xhtml("<p>Hello</p>"));
+    let (svg, _) = doc.get_resource_with_epub_uris("img").unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert!(!svg.contains("head"), "{}", svg);
+    assert!(!svg.contains("viewport"), "{}", svg);
+}
+
+#[test]
+fn inject_in_empty_head() {
+    let chapter = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head/><body><p>Hello</p></body></html>"#;
+    let mut doc = doc(chapter);
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert!(
+        current.contains(r#"<head><meta name="viewport" content="width=device-width"/>"#),
+        "{}",
+        current
+    );
+    assert!(
+        current.contains("body { margin: 0 }</style></head><body>"),
+        "{}",
+        current
+    );
+}
@@ -0,0 +1,168 @@
+#![cfg(feature = "lcp")]
+
+mod common;
+
+use aes::Aes256;
+use base64::Engine;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockEncryptMut, KeyIvInit};
+use epub::doc::{DocError, EpubDoc};
+use epub::lcp::{LcpError, License};
+use std::io::{Cursor, Write};
+
+const PASSPHRASE: &str = "correct horse battery staple";
+const CONTENT_KEY: [u8; 32] = [7; 32];
+const LICENSE_ID: &str = "ef15e740-697f-11e3-949a-0800200c9a66";
+
+fn encrypt(key: &[u8], iv: [u8; 16], data: &[u8]) -> Vec<u8> {
+    let encrypted = cbc::Encryptor::<Aes256>::new_from_slices(key, &iv)
+        .unwrap()
+        .encrypt_padded_vec_mut::<Pkcs7>(data);
+    [iv.to_vec(), encrypted].concat()
+}
+
+fn b64(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+fn license(profile: &str) -> String {
+    let user_key = License::user_key(PASSPHRASE);
+    format!(
+        r#"{{
+  "id": "{id}",
+  "issued": "2024-01-01T00:00:00Z",
+  "provider": "https://example.com",
+  "encryption": {{
+    "profile": "{profile}",
+    "content_key": {{
+      "algorithm": "http://www.w3.org/2001/04/xmlenc#aes256-cbc",
+      "encrypted_value": "{content_key}"
+    }},
+    "user_key": {{
+      "algorithm": "http://www.w3.org/2001/04/xmlenc#sha256",
+      "text_hint": "The usual one",
+      "key_check": "{key_check}"
+    }}
+  }},
+  "links": [
+    {{"rel": "hint", "href": "https://example.com/hint"}}
+  ]
+}}"#,
+        id = LICENSE_ID,
+        profile = profile,
+        content_key = b64(&encrypt(&user_key, [1; 16], &CONTENT_KEY)),
+        key_check = b64(&encrypt(&user_key, [2; 16], LICENSE_ID.as_bytes())),
+    )
+}
+
+const ENCRYPTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container"
+    xmlns:enc="http://www.w3.org/2001/04/xmlenc#"
+    xmlns:ds="http://www.w3.org/2000/09/xmldsig#">
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes256-cbc"/>
+    <ds:KeyInfo>
+      <ds:RetrievalMethod URI="license.lcpl#/encryption/content_key"
+          Type="http://readium.org/2014/01/lcp#EncryptedContentKey"/>
+    </ds:KeyInfo>
+    <enc:CipherData><enc:CipherReference URI="OEBPS/c1.xhtml"/></enc:CipherData>
+  </enc:EncryptedData>
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes256-cbc"/>
+    <ds:KeyInfo>
+      <ds:RetrievalMethod URI="license.lcpl#/encryption/content_key"
+          Type="http://readium.org/2014/01/lcp#EncryptedContentKey"/>
+    </ds:KeyInfo>
+    <enc:CipherData><enc:CipherReference URI="OEBPS/c2.xhtml"/></enc:CipherData>
+    <enc:EncryptionProperties>
+      <enc:EncryptionProperty xmlns:ns="http://www.idpf.org/2016/encryption#compression">
+        <ns:Compression Method="8" OriginalLength="1000"/>
+      </enc:EncryptionProperty>
+    </enc:EncryptionProperties>
+  </enc:EncryptedData>
+</encryption>"#;
+
+fn book(profile: &str) -> (EpubDoc<Cursor<Vec<u8>>>, String, String) {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2"/>"#,
+    );
+    let c1 = common::xhtml("<p>First chapter</p>");
+    let c2 = common::xhtml(&"<p>Second chapter, compressed</p>".repeat(20));
+
+    let mut deflate = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+    deflate.write_all(c2.as_bytes()).unwrap();
+    let compressed = deflate.finish().unwrap();
+
+    let license = license(profile);
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("META-INF/license.lcpl", license.as_bytes()),
+            ("META-INF/encryption.xml", ENCRYPTION.as_bytes()),
+            (
+                "OEBPS/c1.xhtml",
+                &encrypt(&CONTENT_KEY, [3; 16], c1.as_bytes()),
+            ),
+            (
+                "OEBPS/c2.xhtml",
+                &encrypt(&CONTENT_KEY, [4; 16], &compressed),
+            ),
+        ],
+    );
+    let doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    (doc, c1, c2)
+}
+
+#[test]
+fn license_parsing() {
+    let (mut doc, _, _) = book("http://readium.org/lcp/basic-profile");
+    let license = doc.lcp_license().unwrap().unwrap();
+    assert_eq!(license.id, LICENSE_ID);
+    assert_eq!(license.provider, "https://example.com");
+    assert_eq!(license.text_hint, "The usual one");
+    assert_eq!(
+        license.hint_url.as_deref(),
+        Some("https://example.com/hint")
+    );
+
+    assert!(matches!(
+        License::parse(b"{}"),
+        Err(LcpError::FieldNotFound("profile"))
+    ));
+
+    let (mut doc, _, _) = book("http://readium.org/lcp/profile-1.0");
+    assert!(matches!(
+        doc.lcp_license(),
+        Err(DocError::Lcp(LcpError::UnsupportedProfile(_)))
+    ));
+}
+
+#[test]
+fn decryption() {
+    let (mut doc, c1, c2) = book("http://readium.org/lcp/basic-profile");
+
+    // still encrypted
+    assert_ne!(doc.get_resource("c1").unwrap().0, c1.as_bytes());
+
+    assert!(matches!(
+        doc.unlock_lcp("wrong passphrase"),
+        Err(DocError::Lcp(LcpError::WrongPassphrase))
+    ));
+
+    doc.unlock_lcp(PASSPHRASE).unwrap();
+    assert_eq!(doc.get_current_str().unwrap().0, c1);
+    assert_eq!(doc.get_resource_str("c2").unwrap().0, c2);
+}
+
+#[test]
+fn without_license() {
+    let mut doc = EpubDoc::new("test.epub").unwrap();
+    assert!(doc.lcp_license().unwrap().is_none());
+    assert!(matches!(
+        doc.unlock_lcp(PASSPHRASE),
+        Err(DocError::Lcp(LcpError::LicenseNotFound))
+    ));
+}
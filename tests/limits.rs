@@ -0,0 +1,247 @@
+mod common;
+
+use epub::archive::{ArchiveError, EpubArchive};
+use epub::doc::{EpubDoc, EpubOptions};
+use epub::limits::{LimitError, Limits, RATIO_CHECK_MIN_SIZE};
+use std::io::Cursor;
+
+const MANIFEST: &str = r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#;
+const SPINE: &str = r#"<itemref idref="c1"/>"#;
+
+fn open(epub: Vec<u8>, limits: Limits) -> Result<EpubDoc<Cursor<Vec<u8>>>, epub::doc::DocError> {
+    let options = EpubOptions {
+        limits,
+        ..EpubOptions::default()
+    };
+    EpubDoc::from_reader_with_options(Cursor::new(epub), options)
+}
+
+#[test]
+fn entry_size() {
+    let chapter = common::xhtml(&"<p>Hello</p>".repeat(100));
+    let epub = common::build_epub(
+        &common::opf(MANIFEST, SPINE),
+        &[("OEBPS/c1.xhtml", chapter.as_bytes())],
+    );
+    let limits = Limits {
+        max_entry_size: 1024,
+        ..Limits::default()
+    };
+
+    let err = open(epub, limits).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::EntrySize {
+            name: "OEBPS/c1.xhtml".to_string(),
+            limit: 1024
+        })
+    );
+}
+
+#[test]
+fn compression_ratio() {
+    let zeros = vec![0u8; 4 * 1024 * 1024];
+    let epub = common::build_epub(&common::opf(MANIFEST, SPINE), &[("OEBPS/c1.xhtml", &zeros)]);
+
+    let err = open(epub.clone(), Limits::default()).unwrap_err();
+    assert!(matches!(
+        err.limit_exceeded(),
+        Some(LimitError::CompressionRatio { name, limit: 100 }) if name == "OEBPS/c1.xhtml"
+    ));
+
+    let limits = Limits {
+        max_compression_ratio: u64::MAX,
+        ..Limits::default()
+    };
+    let mut doc = open(epub, limits).unwrap();
+    assert_eq!(doc.get_resource("c1").unwrap().0.len(), zeros.len());
+}
+
+#[test]
+fn compression_ratio_threshold() {
+    let size = usize::try_from(RATIO_CHECK_MIN_SIZE).unwrap();
+    let below = vec![b'a'; size - 1];
+    let epub = common::build_epub(&common::opf(MANIFEST, SPINE), &[("OEBPS/c1.xhtml", &below)]);
+    assert!(open(epub, Limits::default()).is_ok());
+
+    let at = vec![b'a'; size];
+    let epub = common::build_epub(&common::opf(MANIFEST, SPINE), &[("OEBPS/c1.xhtml", &at)]);
+    let err = open(epub, Limits::default()).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::CompressionRatio {
+            name: "OEBPS/c1.xhtml".to_string(),
+            limit: 100
+        })
+    );
+}
+
+#[test]
+fn total_size_and_entries() {
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(
+        &common::opf(MANIFEST, SPINE),
+        &[("OEBPS/c1.xhtml", chapter.as_bytes())],
+    );
+
+    let limits = Limits {
+        max_total_size: 512,
+        ..Limits::default()
+    };
+    let err = EpubArchive::from_reader_with_limits(Cursor::new(epub.clone()), limits).unwrap_err();
+    assert!(matches!(
+        err,
+        ArchiveError::LimitExceeded(LimitError::TotalSize { limit: 512 })
+    ));
+
+    let limits = Limits {
+        max_entries: 3,
+        ..Limits::default()
+    };
+    let err = open(epub, limits).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::Entries { limit: 3 })
+    );
+}
+
+#[test]
+fn xml_limits() {
+    let nested = format!(
+        "{}{}",
+        "<dc:subject>".repeat(300),
+        "</dc:subject>".repeat(300)
+    );
+    let opf =
+        common::opf(MANIFEST, SPINE).replace("<dc:language>", &format!("{nested}<dc:language>"));
+    let epub = common::build_epub(&opf, &[]);
+    let err = open(epub, Limits::default()).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::XmlDepth { limit: 256 })
+    );
+
+    let attrs: String = (0..10).map(|i| format!(" a{i}=\"{i}\"")).collect();
+    let opf = common::opf(MANIFEST, SPINE).replace("<dc:title", &format!("<dc:title{attrs}"));
+    let epub = common::build_epub(&opf, &[]);
+    let limits = Limits {
+        max_xml_attributes: 8,
+        ..Limits::default()
+    };
+    let err = open(epub, limits).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::XmlAttributes {
+            element: "title".to_string(),
+            limit: 8
+        })
+    );
+
+    let opf = common::opf(MANIFEST, SPINE).replace("Test book", &"long title ".repeat(100));
+    let epub = common::build_epub(&opf, &[]);
+    let limits = Limits {
+        max_xml_text_size: 64,
+        ..Limits::default()
+    };
+    let err = open(epub, limits).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::XmlTextSize { limit: 64 })
+    );
+}
+
+/// Patches the uncompressed size of the entry `name` in its local header and
+/// in the central directory, like the zip bombs lying about their size
+fn forge_size(zip: &mut [u8], name: &str, size: u32) {
+    let u16_at = |zip: &[u8], i: usize| usize::from(u16::from_le_bytes([zip[i], zip[i + 1]]));
+    let mut patched = 0;
+    for i in 0..zip.len().saturating_sub(46) {
+        // the signature, the offset of the size, the name length and the name
+        let (size_at, name_len_at, name_at) = match &zip[i..i + 4] {
+            b"PK\x03\x04" => (i + 22, i + 26, i + 30),
+            b"PK\x01\x02" => (i + 24, i + 28, i + 46),
+            _ => continue,
+        };
+        let len = u16_at(zip, name_len_at);
+        if zip.get(name_at..name_at + len) == Some(name.as_bytes()) {
+            zip[size_at..size_at + 4].copy_from_slice(&size.to_le_bytes());
+            patched += 1;
+        }
+    }
+    assert_eq!(patched, 2, "{} not found", name);
+}
+
+#[test]
+fn forged_entry_size() {
+    let mut epub = common::build_epub(&common::opf(MANIFEST, SPINE), &[]);
+    forge_size(&mut epub, "OEBPS/content.opf", 64);
+    let err = open(epub, Limits::default()).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::SizeMismatch {
+            name: "OEBPS/content.opf".to_string(),
+            declared: 64
+        })
+    );
+
+    // declared small enough not to have its compression ratio checked
+    let zeros = vec![0u8; 64 * 1024 * 1024];
+    let mut epub = common::build_epub(&common::opf(MANIFEST, SPINE), &[("OEBPS/c1.xhtml", &zeros)]);
+    forge_size(&mut epub, "OEBPS/c1.xhtml", 1024);
+    let mut doc = open(epub, Limits::default()).unwrap();
+    let err = doc.try_get_resource("c1").unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::SizeMismatch {
+            name: "OEBPS/c1.xhtml".to_string(),
+            declared: 1024
+        })
+    );
+}
+
+#[test]
+fn entity_expansion() {
+    // a billion laughs
+    let mut dtd =
+        String::from("<!DOCTYPE html [\n<!ENTITY lol0 \"lollollollollollollollollollol\">\n");
+    for i in 1..10 {
+        let previous = format!("&lol{};", i - 1);
+        dtd.push_str(&format!("<!ENTITY lol{} \"{}\">\n", i, previous.repeat(10)));
+    }
+    dtd.push_str("]>");
+    let chapter = common::xhtml("<p>&lol9;</p>").replacen("\n", &format!("\n{}\n", dtd), 1);
+    let epub = common::build_epub(
+        &common::opf(MANIFEST, SPINE),
+        &[("OEBPS/c1.xhtml", chapter.as_bytes())],
+    );
+    let mut doc = open(epub, Limits::default()).unwrap();
+    let err = doc.chapter_text(0).unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::EntityExpansion { limit: 1024 * 1024 })
+    );
+    let err = doc.get_current_with_epub_uris().unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::EntityExpansion { limit: 1024 * 1024 })
+    );
+}
+
+#[test]
+fn rewrite_limits() {
+    let chapter = common::xhtml(&format!("{}{}", "<div>".repeat(30), "</div>".repeat(30)));
+    let epub = common::build_epub(
+        &common::opf(MANIFEST, SPINE),
+        &[("OEBPS/c1.xhtml", chapter.as_bytes())],
+    );
+    let limits = Limits {
+        max_xml_depth: 20,
+        ..Limits::default()
+    };
+    let mut doc = open(epub, limits).unwrap();
+    let err = doc.get_current_with_epub_uris().unwrap_err();
+    assert_eq!(
+        err.limit_exceeded(),
+        Some(&LimitError::XmlDepth { limit: 20 })
+    );
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::export::AssetMode;
+use epub::markdown::MarkdownOptions;
+use std::io::Cursor;
+
+const C1: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>One</title></head>
+<body>
+<h1>Chapter <em>one</em></h1>
+<p>Some <em>emphasis </em>and <strong>strong</strong> text, <del>gone</del>,
+<code>a `tick`</code> and 2 * 3 [x]<br/>on a new line.</p>
+<p>See <a href="c2.xhtml#part">the part</a>, <a href="https://example.com/a b">a site</a>
+and a note<sup><a epub:type="noteref" id="r1" href="notes.xhtml#n1">1</a></sup>.</p>
+<blockquote><p>Quoted</p><p># not a heading</p></blockquote>
+<ul>
+<li>First</li>
+<li><p>Second</p><ol start="3"><li>Nested</li><li>Again</li></ol></li>
+</ul>
+<pre><code class="language-rust">fn main() {
+    println!("```");
+}</code></pre>
+<table>
+<thead><tr><th>Name</th><th>Value</th></tr></thead>
+<tbody><tr><td>a | b</td><td><img src="../Images/a.png" alt="an image"/></td></tr>
+<tr><td colspan="2">Both</td></tr></tbody>
+</table>
+<hr/>
+<div><p>In a div</p>Loose text</div>
+</body>
+</html>"##;
+
+const C2: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Two</title></head>
+<body>
+<h1>Two</h1>
+<p>Before the part, <a href="c1.xhtml">back</a> <a href="javascript:alert(1)">here</a>.</p>
+<section><h2 id="part">The part</h2>
+<p>In the part, see <a href="#part">here</a>.</p>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><image xlink:href="../Images/a.png"/></svg>
+</section>
+</body>
+</html>"##;
+
+const NOTES: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>Notes</title></head>
+<body>
+<aside epub:type="footnote" id="n1"><p><a href="c1.xhtml#r1">1</a> A <em>note</em>.</p><p>More.</p></aside>
+</body>
+</html>"##;
+
+const NCX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <navMap>
+    <navPoint id="p1" playOrder="1">
+      <navLabel><text>Chapter one</text></navLabel>
+      <content src="Text/c1.xhtml"/>
+    </navPoint>
+    <navPoint id="p2" playOrder="2">
+      <navLabel><text>Two</text></navLabel>
+      <content src="Text/c2.xhtml"/>
+      <navPoint id="p3" playOrder="3">
+        <navLabel><text>The part</text></navLabel>
+        <content src="Text/c2.xhtml#part"/>
+      </navPoint>
+    </navPoint>
+  </navMap>
+</ncx>"#;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    book_without("")
+}
+
+/// Returns the book without the file `missing`
+fn book_without(missing: &str) -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="Text/c2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="Text/notes.xhtml" media-type="application/xhtml+xml"/>
+    <item id="img" href="Images/a.png" media-type="image/png"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2"/>
+    <itemref idref="notes" linear="no"/>"#,
+    )
+    .replace("<spine>", r#"<spine toc="ncx">"#);
+    let files: Vec<(&str, &[u8])> = vec![
+        ("OEBPS/Text/c1.xhtml", C1.as_bytes()),
+        ("OEBPS/Text/c2.xhtml", C2.as_bytes()),
+        ("OEBPS/Text/notes.xhtml", NOTES.as_bytes()),
+        ("OEBPS/Images/a.png", b"png"),
+        ("OEBPS/toc.ncx", NCX.as_bytes()),
+    ];
+    let files: Vec<_> = files.into_iter().filter(|(f, _)| *f != missing).collect();
+    let epub = common::build_epub(&opf, &files);
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn export_markdown() {
+    let mut doc = book();
+    let export = doc.export_markdown().unwrap();
+    let paths: Vec<&str> = export.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, ["c1.md", "c2.md"]);
+    assert_eq!(export.files[0].title, "Chapter one");
+
+    let c1 = &export.files[0].markdown;
+    for block in [
+        "# Chapter *one*\n\n",
+        "Some *emphasis* and **strong** text, ~~gone~~, `` a `tick` `` and 2 \\* 3 \\[x\\]\\\non a new line.",
+        "See [the part](c2.md#part), [a site](<https://example.com/a b>) and a note[^1].",
+        "> Quoted\n>\n> \\# not a heading",
+        "- First\n- Second\n\n  3. Nested\n  4. Again",
+        "````rust\nfn main() {\n    println!(\"```\");\n}\n````",
+        "| Name | Value |\n| --- | --- |\n| a \\| b | ![an image](images/OEBPS/Images/a.png) |\n| Both |  |",
+        "---\n\nIn a div\n\nLoose text",
+        "\n\n[^1]: A *note*.\n\n    More.\n",
+    ] {
+        assert!(c1.contains(block), "{} not in {}", block, c1);
+    }
+    assert!(!c1.contains("r1"), "{}", c1);
+
+    assert_eq!(
+        export.files[1].markdown,
+        "# Two\n\nBefore the part, [back](c1.md) here.\n\n## <a id=\"part\"></a>The part\n\n\
+         In the part, see [here](#part).\n\n![](images/OEBPS/Images/a.png)\n"
+    );
+
+    assert_eq!(export.assets.len(), 1);
+    assert_eq!(export.assets[0].path, "images/OEBPS/Images/a.png");
+    assert_eq!(export.assets[0].content, b"png");
+}
+
+#[test]
+fn export_markdown_by_toc() {
+    let mut doc = book();
+    let options = MarkdownOptions {
+        assets: AssetMode::Inline,
+        split_by_toc: true,
+        include_non_linear: false,
+    };
+    let export = doc.export_markdown_with(&options).unwrap();
+    assert!(export.assets.is_empty());
+    let files: Vec<(&str, &str)> = export
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.title.as_str()))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            ("chapter-one.md", "Chapter one"),
+            ("two.md", "Two"),
+            ("the-part.md", "The part")
+        ]
+    );
+
+    let c1 = &export.files[0].markdown;
+    assert!(c1.contains("[the part](the-part.md#part)"), "{}", c1);
+    assert!(c1.contains("(data:image/png;base64,cG5n)"), "{}", c1);
+    assert!(c1.ends_with("[^1]: A *note*.\n\n    More.\n"), "{}", c1);
+    assert_eq!(
+        export.files[1].markdown,
+        "# Two\n\nBefore the part, [back](chapter-one.md) here.\n"
+    );
+    assert_eq!(
+        export.files[2].markdown,
+        "## <a id=\"part\"></a>The part\n\nIn the part, see [here](#part).\n\n\
+         ![](data:image/png;base64,cG5n)\n"
+    );
+}
+
+#[test]
+fn export_markdown_without_broken_chapters() {
+    let mut doc = book_without("OEBPS/Text/c2.xhtml");
+    let export = doc.export_markdown().unwrap();
+    let paths: Vec<&str> = export.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, ["c1.md"]);
+    let c1 = &export.files[0].markdown;
+    assert!(c1.contains("See the part, "), "{}", c1);
+    assert!(c1.contains("[^1]: A *note*."), "{}", c1);
+
+    let options = MarkdownOptions {
+        split_by_toc: true,
+        ..MarkdownOptions::default()
+    };
+    let export = doc.export_markdown_with(&options).unwrap();
+    let paths: Vec<&str> = export.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, ["chapter-one.md"]);
+}
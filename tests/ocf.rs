@@ -0,0 +1,151 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::ocf::DigestStatus;
+use std::io::Cursor;
+
+const SIGNATURES: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<signatures xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <Signature Id="sig" xmlns="http://www.w3.org/2000/09/xmldsig#">
+    <SignedInfo>
+      <CanonicalizationMethod Algorithm="http://www.w3.org/TR/2001/REC-xml-c14n-20010315"/>
+      <SignatureMethod Algorithm="http://www.w3.org/2000/09/xmldsig#rsa-sha1"/>
+      <Reference URI="#Manifest1">
+        <Transforms>
+          <Transform Algorithm="http://www.w3.org/TR/2001/REC-xml-c14n-20010315"/>
+        </Transforms>
+        <DigestMethod Algorithm="http://www.w3.org/2000/09/xmldsig#sha1"/>
+        <DigestValue>aaaa</DigestValue>
+      </Reference>
+    </SignedInfo>
+    <SignatureValue>bbbb</SignatureValue>
+    <Object>
+      <Manifest Id="Manifest1">
+        <Reference URI="OEBPS/signed.txt">
+          <DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/>
+          <DigestValue>JQ605TvCUU11kBztXzlvE8FBFsQUVqPGP0iHHyoiFz4=</DigestValue>
+        </Reference>
+        <Reference URI="OEBPS/signed.txt">
+          <DigestMethod Algorithm="http://www.w3.org/2000/09/xmldsig#sha1"/>
+          <DigestValue>
+            IuRIIMClr3AfuilHkVHDm4x8SqI=
+          </DigestValue>
+        </Reference>
+        <Reference URI="OEBPS/tampered.txt">
+          <DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/>
+          <DigestValue>JQ605TvCUU11kBztXzlvE8FBFsQUVqPGP0iHHyoiFz4=</DigestValue>
+        </Reference>
+        <Reference URI="OEBPS/missing.txt">
+          <DigestMethod Algorithm="http://www.w3.org/2001/04/xmlenc#sha256"/>
+          <DigestValue>JQ605TvCUU11kBztXzlvE8FBFsQUVqPGP0iHHyoiFz4=</DigestValue>
+        </Reference>
+      </Manifest>
+    </Object>
+  </Signature>
+</signatures>"##;
+
+const RIGHTS: &str = r#"<?xml version="1.0"?>
+<adept:rights xmlns:adept="http://ns.adobe.com/adept"><adept:licenseToken/></adept:rights>"#;
+
+const METADATA: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<metadata xmlns="http://www.idpf.org/2013/metadata" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <dc:identifier id="pub-id">urn:isbn:9780000000000</dc:identifier>
+  <meta property="dcterms:modified">2024-01-01T00:00:00Z</meta>
+  <meta refines="#pub-id" property="identifier-type" scheme="onix:codelist5">15</meta>
+</metadata>"##;
+
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0">
+  <manifest:file-entry manifest:full-path="/" manifest:media-type="application/epub+zip"/>
+  <manifest:file-entry manifest:full-path="OEBPS/content.opf" manifest:media-type="application/oebps-package+xml"/>
+</manifest:manifest>"#;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("META-INF/signatures.xml", SIGNATURES.as_bytes()),
+            ("META-INF/rights.xml", RIGHTS.as_bytes()),
+            ("META-INF/metadata.xml", METADATA.as_bytes()),
+            ("META-INF/manifest.xml", MANIFEST.as_bytes()),
+            ("OEBPS/c1.xhtml", chapter.as_bytes()),
+            ("OEBPS/signed.txt", b"signed data"),
+            ("OEBPS/tampered.txt", b"signed data!"),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn signatures() {
+    let mut doc = book();
+    let signatures = doc.signatures().unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert_eq!(signatures[0].id.as_deref(), Some("sig"));
+    assert_eq!(signatures[0].references.len(), 5);
+    assert_eq!(signatures[0].references[0].uri, "#Manifest1");
+    assert_eq!(signatures[0].references[0].transforms.len(), 1);
+    assert_eq!(signatures[0].references[0].path(), None);
+    assert_eq!(
+        signatures[0].references[1].path().unwrap().as_str(),
+        "OEBPS/signed.txt"
+    );
+
+    let statuses: Vec<DigestStatus> = doc
+        .verify_signatures()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            DigestStatus::Unsupported,
+            DigestStatus::Valid,
+            DigestStatus::Valid,
+            DigestStatus::Mismatch,
+            DigestStatus::Missing,
+        ]
+    );
+}
+
+#[test]
+fn rights_metadata_and_manifest() {
+    let mut doc = book();
+
+    let rights = doc.rights().unwrap().unwrap();
+    assert_eq!(rights.element, "rights");
+    assert_eq!(
+        rights.namespace.as_deref(),
+        Some("http://ns.adobe.com/adept")
+    );
+    assert_eq!(rights.content, RIGHTS);
+
+    let metadata = doc.container_metadata().unwrap();
+    assert_eq!(metadata.len(), 2);
+    assert_eq!(metadata[0].property, "identifier");
+    assert_eq!(metadata[0].value, "urn:isbn:9780000000000");
+    let refinement = metadata[0].refinement("identifier-type").unwrap();
+    assert_eq!(refinement.value, "15");
+    assert_eq!(refinement.scheme.as_deref(), Some("onix:codelist5"));
+    assert_eq!(metadata[1].property, "dcterms:modified");
+
+    let manifest = doc.container_manifest().unwrap();
+    assert_eq!(manifest.len(), 2);
+    assert_eq!(manifest[0].full_path, "/");
+    assert_eq!(manifest[1].media_type, "application/oebps-package+xml");
+}
+
+#[test]
+fn missing_files() {
+    let mut doc = EpubDoc::new("test.epub").unwrap();
+    assert!(doc.signatures().unwrap().is_empty());
+    assert!(doc.rights().unwrap().is_none());
+    assert!(doc.container_metadata().unwrap().is_empty());
+    assert!(doc.container_manifest().unwrap().is_empty());
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use epub::doc::{DocError, EpubDoc, EpubOptions};
+use epub::validate::Code;
+use std::io::Cursor;
+use std::path::Path;
+
+fn open(epub: Vec<u8>, options: EpubOptions) -> Result<EpubDoc<Cursor<Vec<u8>>>, DocError> {
+    EpubDoc::from_reader_with_options(Cursor::new(epub), options)
+}
+
+fn broken_manifest_epub() -> Vec<u8> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="nohref" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    common::build_epub(&opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())])
+}
+
+#[test]
+fn lenient_skips_broken_manifest_items() {
+    let doc = open(broken_manifest_epub(), EpubOptions::lenient()).unwrap();
+    assert_eq!(doc.resources.len(), 1);
+    assert_eq!(doc.warnings().len(), 1);
+    let warning = &doc.warnings()[0];
+    assert_eq!(warning.code, Code::MissingAttribute);
+    assert_eq!(warning.line, Some(10));
+}
+
+#[test]
+fn strict_fails_on_broken_manifest_items() {
+    let err = open(broken_manifest_epub(), EpubOptions::strict()).unwrap_err();
+    match err {
+        DocError::XmlError(e) => {
+            assert_eq!(e.path(), Some(Path::new("OEBPS/content.opf")));
+            assert_eq!(e.position().unwrap().row + 1, 10);
+            assert_eq!(
+                e.to_string(),
+                "OEBPS/content.opf:10:5: Attribute Not Found: href"
+            );
+        }
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn lenient_recovers_missing_metadata() {
+    let opf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <manifest>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="c1"/></spine>
+</package>"#;
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+
+    let doc = open(epub.clone(), EpubOptions::lenient()).unwrap();
+    assert!(doc.metadata.is_empty());
+    assert_eq!(doc.spine.len(), 1);
+    assert!(!doc.warnings().is_empty());
+
+    let err = open(epub, EpubOptions::strict()).unwrap_err();
+    assert!(
+        matches!(err, DocError::MissingMetadata { ref path } if path == Path::new("OEBPS/content.opf"))
+    );
+}
+
+#[test]
+fn default_mode() {
+    let epub = broken_manifest_epub();
+    let doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    assert_eq!(doc.resources.len(), 1);
+    assert_eq!(doc.warnings()[0].code, Code::MissingAttribute);
+
+    let opf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <manifest>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="c1"/></spine>
+</package>"#;
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+    let err = EpubDoc::from_reader(Cursor::new(epub)).unwrap_err();
+    assert!(matches!(err, DocError::MissingMetadata { .. }));
+
+    let opf = common::opf("<item", "");
+    let epub = common::build_epub(&opf, &[]);
+    let err = EpubDoc::from_reader(Cursor::new(epub)).unwrap_err();
+    assert!(matches!(err, DocError::XmlError(_)));
+}
+
+#[test]
+fn broken_ncx() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    )
+    .replace("<spine>", r#"<spine toc="ncx">"#);
+    let chapter = common::xhtml("<p>Hello</p>");
+    let ncx = r#"<?xml version="1.0"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <navMap>
+    <navPoint id="p1" playOrder="1">
+      <navLabel><text>One</text></navLabel>
+      <content src="c1.xhtml"/>
+    </navPoint>
+    <navPoint id="p2" playOrder="2">
+  </navMap>
+</ncx>"#;
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", chapter.as_bytes()),
+            ("OEBPS/toc.ncx", ncx.as_bytes()),
+        ],
+    );
+
+    let doc = open(epub.clone(), EpubOptions::lenient()).unwrap();
+    assert_eq!(doc.spine.len(), 1);
+    let warning = &doc.warnings()[0];
+    assert_eq!(warning.code, Code::XmlParse);
+    assert_eq!(warning.path.as_deref(), Some(Path::new("OEBPS/toc.ncx")));
+
+    let err = open(epub, EpubOptions::strict()).unwrap_err();
+    match err {
+        DocError::XmlError(e) => {
+            assert_eq!(e.path(), Some(Path::new("OEBPS/toc.ncx")));
+            assert_eq!(e.position().unwrap().row + 1, 9);
+        }
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn default_options_are_lenient() {
+    let doc = EpubDoc::from_reader(Cursor::new(broken_manifest_epub())).unwrap();
+    assert_eq!(doc.warnings().len(), 1);
+}
+
+#[test]
+fn toc_not_found() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    )
+    .replace("<spine>", r#"<spine toc="ncx">"#);
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+
+    let doc = open(epub.clone(), EpubOptions::lenient()).unwrap();
+    assert_eq!(doc.warnings()[0].code, Code::IdrefNotFound);
+
+    let err = open(epub, EpubOptions::strict()).unwrap_err();
+    assert!(matches!(err, DocError::TocNotFound { ref id, .. } if id == "ncx"));
+}
+
+#[test]
+fn current_chapter_not_found() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="gone"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = open(epub, EpubOptions::lenient()).unwrap();
+
+    assert!(doc.get_current_with_epub_uris().is_ok());
+    doc.go_next();
+    let err = doc.get_current_with_epub_uris().unwrap_err();
+    assert!(matches!(err, DocError::ResourceNotFound { ref id } if id == "gone"));
+}
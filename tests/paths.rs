@@ -0,0 +1,165 @@
+mod common;
+
+use epub::archive::{ArchivePath, PathError};
+use epub::doc::{DocError, EpubDoc, EpubOptions};
+use epub::validate::Code;
+use std::io::Cursor;
+use std::path::Path;
+
+fn open(epub: Vec<u8>, options: EpubOptions) -> Result<EpubDoc<Cursor<Vec<u8>>>, DocError> {
+    EpubDoc::from_reader_with_options(Cursor::new(epub), options)
+}
+
+#[test]
+fn archive_path_normalization() {
+    let base = ArchivePath::new("OEBPS/Text").unwrap();
+    assert_eq!(
+        base.join("./a/./b/../c.xhtml").unwrap().as_str(),
+        "OEBPS/Text/a/c.xhtml"
+    );
+    assert_eq!(
+        base.join("..\\Images\\x.png").unwrap().as_str(),
+        "OEBPS/Images/x.png"
+    );
+    assert_eq!(
+        base.join("a%2F..%2F..%2F..%2F..%2Fx").unwrap().as_str(),
+        "x"
+    );
+    assert_eq!(base.join("").unwrap(), base);
+    assert_eq!(base.parent().parent(), ArchivePath::root());
+
+    assert_eq!(
+        base.join("%2Fetc%2Fpasswd"),
+        Err(PathError::Absolute("%2Fetc%2Fpasswd".into()))
+    );
+    assert_eq!(
+        base.join("c:/x"),
+        Err(PathError::DriveLetter("c:/x".into()))
+    );
+    assert_eq!(
+        base.join("mailto:a@b.c"),
+        Err(PathError::External("mailto:a@b.c".into()))
+    );
+    assert_eq!(base.join("%ff"), Err(PathError::Encoding("%ff".into())));
+}
+
+fn traversal_epub(href: &str) -> Vec<u8> {
+    let opf = common::opf(
+        &format!(
+            r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="evil" href="{}" media-type="application/xhtml+xml"/>
+    <item id="remote" href="https://example.com/a.mp3" media-type="audio/mpeg"/>"#,
+            href
+        ),
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    common::build_epub(&opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())])
+}
+
+#[test]
+fn manifest_paths_are_clamped() {
+    let doc = open(
+        traversal_epub("../../../etc/passwd"),
+        EpubOptions::lenient(),
+    )
+    .unwrap();
+    assert_eq!(doc.resources["evil"].path, Path::new("etc/passwd"));
+    assert!(!doc.resources.contains_key("remote"));
+}
+
+#[test]
+fn absolute_manifest_paths_are_rejected() {
+    let doc = open(traversal_epub("/etc/passwd"), EpubOptions::lenient()).unwrap();
+    assert!(!doc.resources.contains_key("evil"));
+    assert_eq!(doc.warnings().len(), 1);
+    assert_eq!(doc.warnings()[0].code, Code::InvalidPath);
+    assert_eq!(doc.warnings()[0].line, Some(10));
+
+    let err = open(
+        traversal_epub("C:\\Windows\\win.ini"),
+        EpubOptions::strict(),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("Drive letter not allowed"));
+}
+
+#[test]
+fn toc_paths_are_relative_to_the_ncx() {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ncx" href="Nav/toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    )
+    .replace("<spine>", r#"<spine toc="ncx">"#);
+    let ncx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <docTitle><text>Toc</text></docTitle>
+  <navMap>
+    <navPoint id="n1" playOrder="1">
+      <navLabel><text>One</text></navLabel>
+      <content src="../Text/c1.xhtml#start"/>
+    </navPoint>
+  </navMap>
+</ncx>"#;
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/Text/c1.xhtml", chapter.as_bytes()),
+            ("OEBPS/Nav/toc.ncx", ncx.as_bytes()),
+        ],
+    );
+    let doc = open(epub, EpubOptions::strict()).unwrap();
+    assert_eq!(doc.toc[0].content, Path::new("OEBPS/Text/c1.xhtml#start"));
+}
+
+#[test]
+fn epub_uris_stay_inside_the_archive() {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml(
+        r##"<a href="#note">note</a>
+<a href="../../../../secret.xhtml">up</a>
+<a href="/etc/passwd">abs</a>
+<a href="mailto:someone@example.com">mail</a>
+<img src="../Images/a%20b.png?v=2"/>"##,
+    );
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = open(epub, EpubOptions::lenient()).unwrap();
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert!(current.contains(r#"href="epub://OEBPS/Text/c1.xhtml#note""#));
+    assert!(current.contains(r#"href="epub://secret.xhtml""#));
+    assert!(current.contains(r#"href="/etc/passwd""#));
+    assert!(current.contains(r#"href="mailto:someone@example.com""#));
+    assert!(current.contains(r#"src="epub://OEBPS/Images/a%20b.png""#));
+}
+
+#[test]
+fn hrefs_are_decoded_once() {
+    let base = ArchivePath::new("OEBPS/100%25").unwrap();
+    assert_eq!(base.as_str(), "OEBPS/100%25");
+    assert_eq!(
+        base.join("a%2525.png").unwrap().as_str(),
+        "OEBPS/100%25/a%25.png"
+    );
+
+    let opf = common::opf(
+        r#"<item id="c1" href="100%2525/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml(r#"<img src="a.png"/>"#);
+    let epub = common::build_epub(&opf, &[("OEBPS/100%25/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = open(epub, EpubOptions::lenient()).unwrap();
+    assert_eq!(doc.resources["c1"].path, Path::new("OEBPS/100%25/c1.xhtml"));
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert!(
+        current.contains(r#"src="epub://OEBPS/100%2525/a.png""#),
+        "{}",
+        current
+    );
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use epub::doc::{EpubDoc, EpubOptions};
+use epub::positions::POSITION_LENGTH;
+use std::io::Cursor;
+use std::path::Path;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="cover"/>
+    <itemref idref="c2"/>"#,
+    );
+    let long = "é".repeat(2000);
+    let c1 = common::xhtml(&format!(
+        r#"<p>{}</p><p id="end">{}</p>"#,
+        long,
+        "x".repeat(500)
+    ));
+    let cover = common::xhtml(r#"<img src="cover.png" alt=""/>"#);
+    let c2 = common::xhtml("<p>Short</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/cover.xhtml", cover.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn positions() {
+    let mut doc = book();
+    let positions = doc.positions().unwrap();
+    let keys: Vec<_> = positions
+        .iter()
+        .map(|p| (p.number, p.spine_index, p.text_offset))
+        .collect();
+    // 2501 characters, the first 2000 of two bytes
+    assert_eq!(
+        keys,
+        [
+            (1, 0, 0),
+            (2, 0, 2 * POSITION_LENGTH),
+            (3, 0, 2 * 2000 + 1 + (2 * POSITION_LENGTH - 2001)),
+            (4, 1, 0),
+            (5, 2, 0),
+        ]
+    );
+    assert_eq!(positions.len(), 5);
+    assert_eq!(positions.get(0), None);
+    assert_eq!(positions.get(4).unwrap().spine_index, 1);
+    assert_eq!(positions.chapter(0).count(), 3);
+
+    assert_eq!(positions.find(0, 2100).unwrap().number, 2);
+    assert_eq!(positions.find(0, 10_000).unwrap().number, 3);
+    assert_eq!(positions.find(2, 3).unwrap().number, 5);
+    assert_eq!(positions.find(3, 0), None);
+}
+
+#[test]
+fn positions_and_cfis() {
+    let mut doc = book();
+    let positions = doc.positions().unwrap();
+    for position in &positions {
+        let cfi = doc.position_cfi(position).unwrap();
+        assert_eq!(
+            doc.cfi_position(&positions, &cfi).unwrap(),
+            Some(position),
+            "{}",
+            cfi
+        );
+    }
+}
+
+#[test]
+fn fragment_position() {
+    let mut doc = book();
+    let positions = doc.positions().unwrap();
+
+    let position = doc
+        .fragment_position(&positions, Path::new("OEBPS/c1.xhtml#end"))
+        .unwrap();
+    assert_eq!(position.unwrap().number, 2);
+    let position = doc
+        .fragment_position(&positions, Path::new("OEBPS/c1.xhtml#%65nd"))
+        .unwrap();
+    assert_eq!(position.unwrap().number, 2);
+    let position = doc
+        .fragment_position(&positions, Path::new("OEBPS/c2.xhtml"))
+        .unwrap();
+    assert_eq!(position.unwrap().number, 5);
+    let position = doc
+        .fragment_position(
+            &positions,
+            Path::new("OEBPS/c1.xhtml#epubcfi(/6/2!/4/4/1:100)"),
+        )
+        .unwrap();
+    assert_eq!(position.unwrap().number, 3);
+    let position = doc
+        .fragment_position(&positions, Path::new("OEBPS/other.xhtml#end"))
+        .unwrap();
+    assert_eq!(position, None);
+
+    let position = positions.get(4).unwrap();
+    assert!(doc.set_current_position(position));
+    assert_eq!(doc.get_current_page(), 1);
+}
+
+#[test]
+fn current_position() {
+    let mut doc = book();
+    let positions = doc.positions().unwrap();
+    assert_eq!(doc.get_current_position(&positions).unwrap().number, 1);
+
+    let position = positions.get(2).unwrap();
+    assert!(doc.set_current_position(position));
+    assert_eq!(doc.get_current_page(), 0);
+    assert_eq!(doc.get_current_position(&positions), Some(position));
+    let cfi = doc.get_current_cfi().unwrap();
+    assert_eq!(cfi, doc.position_cfi(position).unwrap());
+    assert_eq!(doc.cfi_position(&positions, &cfi).unwrap(), Some(position));
+
+    // changing the chapter goes back to its start
+    assert!(doc.go_next());
+    assert_eq!(doc.get_current_position(&positions).unwrap().number, 4);
+    assert!(!doc.set_current_position(&epub::positions::Position {
+        number: 100,
+        spine_index: 100,
+        text_offset: 0,
+    }));
+    assert_eq!(doc.get_current_page(), 1);
+}
+
+#[test]
+fn positions_skip_broken_chapters() {
+    let opf = common::opf(
+        r#"<item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="missing"/>
+    <itemref idref="dangling"/>
+    <itemref idref="c1"/>"#,
+    );
+    let c1 = common::xhtml("<p>Text</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", c1.as_bytes())]);
+    let mut doc =
+        EpubDoc::from_reader_with_options(Cursor::new(epub), EpubOptions::lenient()).unwrap();
+    let positions = doc.positions().unwrap();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions.get(1).unwrap().spine_index, 2);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use epub::archive::ArchiveError;
+use epub::doc::{DocError, EpubDoc};
+use std::io::Cursor;
+
+fn book(files: &[(&str, &[u8])]) -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>
+    <item id="latin1" href="latin1.txt" media-type="text/plain"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="missing"/>"#,
+    );
+    let epub = common::build_epub(&opf, files);
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn try_get_resource_errors() {
+    let chapter = common::xhtml("<p>Hello</p>");
+    let mut doc = book(&[
+        ("OEBPS/c1.xhtml", chapter.as_bytes()),
+        ("OEBPS/latin1.txt", b"caf\xe9"),
+    ]);
+
+    let (content, mime) = doc.try_get_resource("c1").unwrap();
+    assert_eq!(content, chapter.as_bytes());
+    assert_eq!(mime, "application/xhtml+xml");
+
+    let err = doc.try_get_resource("nope").unwrap_err();
+    assert!(matches!(err, DocError::ResourceNotFound { ref id } if id == "nope"));
+
+    let err = doc.try_get_resource("missing").unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::ArchiveError(ArchiveError::EntryNotFound(ref name)) if name == "OEBPS/missing.xhtml"
+    ));
+
+    let err = doc.try_get_resource_str("latin1").unwrap_err();
+    assert!(matches!(err, DocError::ArchiveError(ArchiveError::Utf8(_))));
+    assert!(doc.get_resource_str("latin1").is_none());
+    assert!(doc.get_resource("latin1").is_some());
+}
+
+#[test]
+fn try_get_current_errors() {
+    let chapter = common::xhtml("<p>Hello</p>");
+    let mut doc = book(&[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+    assert!(doc.try_get_current().is_ok());
+    assert!(doc.try_get_current_str().is_ok());
+
+    doc.go_next();
+    let err = doc.try_get_current().unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::ArchiveError(ArchiveError::EntryNotFound(_))
+    ));
+    assert!(doc.get_current().is_none());
+}
+
+#[test]
+fn try_get_resource_corrupted() {
+    let content = b"A deflated chapter, to be corrupted in its compressed stream";
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.txt" media-type="text/plain"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let mut epub = common::build_epub(&opf, &[("OEBPS/c1.txt", content)]);
+    // the file is deflated, so corrupt the compressed stream in place
+    let deflated = zip::ZipArchive::new(Cursor::new(epub.clone()))
+        .unwrap()
+        .by_name("OEBPS/c1.txt")
+        .unwrap()
+        .data_start() as usize;
+    for b in &mut epub[deflated..deflated + 8] {
+        *b = !*b;
+    }
+
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    let err = doc.try_get_resource("c1").unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::ArchiveError(ArchiveError::Decompression { ref name, .. }) if name == "OEBPS/c1.txt"
+    ));
+}
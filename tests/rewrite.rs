@@ -0,0 +1,263 @@
+mod common;
+
+use epub::archive::ArchivePath;
+use epub::doc::EpubDoc;
+use epub::uri::BaseUrl;
+use std::io::Cursor;
+
+fn current_with_epub_uris(body: &str) -> String {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:xlink="http://www.w3.org/1999/xlink">
+<head>
+<title>Chapter</title>
+<link rel="stylesheet" href="../Styles/main.css"/>
+<style>
+@import "../Styles/fonts.css";
+@font-face {{ src: url(../Fonts/a.otf) }}
+body {{ background: URL( '../Images/bg.png' ) }}
+</style>
+</head>
+<body>{}</body>
+</html>"#,
+        body
+    );
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap()
+}
+
+#[test]
+fn rewrite_media_references() {
+    let current = current_with_epub_uris(
+        r#"<img src="../Images/a.png" srcset="../Images/a.png 1x, ../Images/a@2x.png 2x"/>
+<picture><source srcset="../Images/wide.png" media="(min-width: 800px)"/></picture>
+<video poster="../Images/poster.jpg" src="../Video/v.mp4"><track src="../Video/v.vtt"/></video>
+<audio src="../Audio/a.mp3"><source src="../Audio/a.ogg"/></audio>
+<object data="../Misc/o.svg"></object>
+<iframe src="other.xhtml"></iframe>"#,
+    );
+    for expected in [
+        r#"href="epub://OEBPS/Styles/main.css""#,
+        r#"src="epub://OEBPS/Images/a.png""#,
+        r#"srcset="epub://OEBPS/Images/a.png 1x, epub://OEBPS/Images/a@2x.png 2x""#,
+        r#"srcset="epub://OEBPS/Images/wide.png""#,
+        r#"poster="epub://OEBPS/Images/poster.jpg""#,
+        r#"src="epub://OEBPS/Video/v.mp4""#,
+        r#"src="epub://OEBPS/Video/v.vtt""#,
+        r#"src="epub://OEBPS/Audio/a.mp3""#,
+        r#"src="epub://OEBPS/Audio/a.ogg""#,
+        r#"data="epub://OEBPS/Misc/o.svg""#,
+        r#"src="epub://OEBPS/Text/other.xhtml""#,
+    ] {
+        assert!(
+            current.contains(expected),
+            "{} not in {}",
+            expected,
+            current
+        );
+    }
+}
+
+#[test]
+fn rewrite_svg_references() {
+    let current = current_with_epub_uris(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+<defs><linearGradient id="g"/></defs>
+<image xlink:href="../Images/cover.jpg" width="10" height="10"/>
+<image href="../Images/plain.jpg"/>
+<use xlink:href="#g"/>
+<rect style="fill: url(#g)"/>
+<a xlink:href="#g"><text>link</text></a>
+</svg>"##,
+    );
+    for expected in [
+        r#"xlink:href="epub://OEBPS/Images/cover.jpg""#,
+        r#"href="epub://OEBPS/Images/plain.jpg""#,
+        r##"<use xlink:href="#g""##,
+        r#"style="fill: url(#g)""#,
+        r##"xlink:href="epub://OEBPS/Text/c1.xhtml#g""##,
+    ] {
+        assert!(
+            current.contains(expected),
+            "{} not in {}",
+            expected,
+            current
+        );
+    }
+}
+
+#[test]
+fn rewrite_style_references() {
+    let current = current_with_epub_uris(
+        r#"<div style="background-image: url(&quot;../Images/bg.png&quot;); color: red">x</div>
+<p style="background: url(data:image/png;base64,AAAA)">y</p>"#,
+    );
+    for expected in [
+        r#"@import "epub://OEBPS/Styles/fonts.css";"#,
+        r#"src: url("epub://OEBPS/Fonts/a.otf")"#,
+        r#"background: url("epub://OEBPS/Images/bg.png")"#,
+        r#"style="background-image: url(&quot;epub://OEBPS/Images/bg.png&quot;); color: red""#,
+        r#"style="background: url(data:image/png;base64,AAAA)""#,
+    ] {
+        assert!(
+            current.contains(expected),
+            "{} not in {}",
+            expected,
+            current
+        );
+    }
+}
+
+#[test]
+fn rewrite_srcset_with_data_urls() {
+    let current = current_with_epub_uris(
+        r#"<img srcset="data:image/png;base64,AA,BB 1x,../Images/b.png 2x" src="x.png"/>"#,
+    );
+    assert!(current
+        .contains(r#"srcset="data:image/png;base64,AA,BB 1x, epub://OEBPS/Images/b.png 2x""#));
+}
+
+#[test]
+fn rewrite_stylesheet() {
+    let opf = common::opf(
+        r#"<item id="css" href="Styles/main.css" media-type="text/css"/>
+    <item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let css = r#"@charset "utf-8";
+@import url("base.css") screen;
+@import 'print.css' print;
+/* url(../Images/commented.png) */
+.a { content: "url(not-a-reference)"; background: url( ../Images/a\(1\).png ) }
+.b { background-image: -webkit-image-set("../Images/b.png" 1x, url(../Images/b2.png) 2x) }
+.c { filter: url(#shadow); mask: url(data:image/svg+xml,abc) }
+@font-face { font-family: "F"; src: local("F"), URL('../Fonts/f\20 g.woff2') format("woff2") }
+"#;
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/Styles/main.css", css.as_bytes()),
+            ("OEBPS/Text/c1.xhtml", chapter.as_bytes()),
+        ],
+    );
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+
+    let (content, mime) = doc.get_resource_with_epub_uris("css").unwrap();
+    assert_eq!(mime, "text/css");
+    assert_eq!(
+        String::from_utf8(content).unwrap(),
+        r#"@charset "utf-8";
+@import url("epub://OEBPS/Styles/base.css") screen;
+@import "epub://OEBPS/Styles/print.css" print;
+/* url(../Images/commented.png) */
+.a { content: "url(not-a-reference)"; background: url("epub://OEBPS/Images/a(1).png") }
+.b { background-image: -webkit-image-set("epub://OEBPS/Images/b.png" 1x, url("epub://OEBPS/Images/b2.png") 2x) }
+.c { filter: url(#shadow); mask: url(data:image/svg+xml,abc) }
+@font-face { font-family: "F"; src: local("F"), url("epub://OEBPS/Fonts/f%20g.woff2") format("woff2") }
+"#
+    );
+
+    let chapter = doc
+        .get_resource_by_path_with_epub_uris("OEBPS/Text/c1.xhtml")
+        .unwrap();
+    assert!(String::from_utf8(chapter).unwrap().contains("<p>Hello</p>"));
+}
+
+#[test]
+fn rewrite_with_resolver() {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml(
+        r##"<img src="httpdocs/img.png"/>
+<a href="https://example.com/">web</a>
+<a href="mailto:someone@example.com">mail</a>
+<img src="data:image/png;base64,AAAA"/>
+<img src="//example.com/a.png"/>
+<a href="../Text/c2.xhtml?x=1#note">note</a>
+<a href="#top">top</a>"##,
+    );
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+
+    doc.set_uri_resolver(BaseUrl::new("http://127.0.0.1:8080/book/123/"));
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    for expected in [
+        r#"src="http://127.0.0.1:8080/book/123/OEBPS/Text/httpdocs/img.png""#,
+        r#"href="https://example.com/""#,
+        r#"href="mailto:someone@example.com""#,
+        r#"src="data:image/png;base64,AAAA""#,
+        r#"src="//example.com/a.png""#,
+        r##"href="http://127.0.0.1:8080/book/123/OEBPS/Text/c2.xhtml#note""##,
+        r##"href="http://127.0.0.1:8080/book/123/OEBPS/Text/c1.xhtml#top""##,
+    ] {
+        assert!(
+            current.contains(expected),
+            "{} not in {}",
+            expected,
+            current
+        );
+    }
+
+    doc.set_uri_resolver(|path: &ArchivePath, fragment: Option<&str>| {
+        format!("blob:{}|{}", path, fragment.unwrap_or_default())
+    });
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    assert!(current.contains(r#"src="blob:OEBPS/Text/httpdocs/img.png|""#));
+    assert!(current.contains(r#"href="blob:OEBPS/Text/c2.xhtml|note""#));
+}
+
+#[test]
+fn rewrite_preserves_formatting() {
+    let opf = common::opf(
+        r#"<item id="c1" href="Text/c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = r##"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html [
+  <!ENTITY e "<not-a-tag>">
+]>
+<html xmlns="http://www.w3.org/1999/xhtml"  xmlns:xlink="http://www.w3.org/1999/xlink">
+<head>
+  <!-- <link href="commented.css"/> -->
+  <style>/*<![CDATA[*/ body > p { background: url(../Images/a.png) } /*]]>*/</style>
+  <style>
+    .a { background: url('../Images/b.png?x=1&amp;y=2') }
+  </style>
+</head>
+<body>
+<pre>  indented
+    <b>bold</b>   text  </pre>
+<p xml:space="preserve">a  <i>b</i>  c&nbsp;&#233;&amp;</p>
+<img   src = '../Images/c.png'   alt="a &quot;c&quot;"/><a href="#top">top</a>
+<svg xmlns="http://www.w3.org/2000/svg"><image xlink:href="../Images/d.png"/></svg>
+</body>
+</html>"##;
+    let epub = common::build_epub(&opf, &[("OEBPS/Text/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+
+    let current = String::from_utf8(doc.get_current_with_epub_uris().unwrap()).unwrap();
+    let expected = chapter
+        .replace(
+            "url(../Images/a.png)",
+            r#"url("epub://OEBPS/Images/a.png")"#,
+        )
+        .replace(
+            "url('../Images/b.png?x=1&amp;y=2')",
+            r#"url("epub://OEBPS/Images/b.png")"#,
+        )
+        .replace("'../Images/c.png'", "'epub://OEBPS/Images/c.png'")
+        .replace(
+            r##"href="#top""##,
+            r##"href="epub://OEBPS/Text/c1.xhtml#top""##,
+        )
+        .replace("../Images/d.png", "epub://OEBPS/Images/d.png");
+    assert_eq!(current, expected);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use epub::doc::{EpubDoc, EpubOptions};
+use epub::search::{SearchIndex, SearchOptions};
+use std::io::Cursor;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2"/>"#,
+    );
+    let c1 = common::xhtml(
+        r#"<h1 id="title">Café</h1>
+<p id="p1">The café opens <em>early</em>.</p>
+<p>No id here, cafés closed.</p>"#,
+    );
+    let c2 =
+        common::xhtml(r#"<section id="s"><p>A CAFE in <span id="x">Paris</span>.</p></section>"#);
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn search_exact() {
+    let mut doc = book();
+    let hits = doc.search("café", SearchOptions::default()).unwrap();
+    assert_eq!(hits.len(), 2);
+
+    assert_eq!(hits[0].spine_index, 0);
+    assert_eq!(hits[0].element_id.as_deref(), Some("p1"));
+    assert_eq!(
+        hits[0].snippet,
+        "Café The café opens early. No id here, cafés closed."
+    );
+    let text = doc.chapter_text(0).unwrap();
+    assert_eq!(&text[hits[0].range.clone()], "café");
+
+    assert_eq!(hits[1].element_id.as_deref(), Some("p1"));
+    assert_eq!(&text[hits[1].range.clone()], "café");
+}
+
+#[test]
+fn search_insensitive() {
+    let mut doc = book();
+    let options = SearchOptions {
+        case_insensitive: true,
+        diacritic_insensitive: true,
+        whole_word: false,
+    };
+    let hits = doc.search("cafe", options).unwrap();
+    let ids: Vec<_> = hits
+        .iter()
+        .map(|h| (h.spine_index, h.element_id.as_deref()))
+        .collect();
+    assert_eq!(
+        ids,
+        [
+            (0, Some("title")),
+            (0, Some("p1")),
+            (0, Some("p1")),
+            (1, Some("s"))
+        ]
+    );
+    let text = doc.chapter_text(0).unwrap();
+    assert_eq!(&text[hits[0].range.clone()], "Café");
+
+    let options = SearchOptions {
+        whole_word: true,
+        ..options
+    };
+    let hits = doc.search("CAFÉ", options).unwrap();
+    assert_eq!(hits.len(), 3);
+    assert!(hits.iter().all(|h| !h.snippet.starts_with("cafés")));
+
+    let hits = doc.search("paris", options).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].element_id.as_deref(), Some("x"));
+    assert_eq!(hits[0].snippet, "A CAFE in Paris.");
+}
+
+#[test]
+fn search_empty_query() {
+    let mut doc = book();
+    assert!(doc.search("", SearchOptions::default()).unwrap().is_empty());
+    assert!(doc
+        .search("nope", SearchOptions::default())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn search_index() {
+    let mut doc = book();
+    let index = SearchIndex::build(&mut doc).unwrap();
+    drop(doc);
+    let options = SearchOptions {
+        case_insensitive: true,
+        ..SearchOptions::default()
+    };
+    let hits = index.search("EARLY", options);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].spine_index, 0);
+}
+
+#[test]
+fn search_skips_broken_chapters() {
+    let opf = common::opf(
+        r#"<item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="missing"/>
+    <itemref idref="dangling"/>
+    <itemref idref="c1"/>"#,
+    );
+    let c1 = common::xhtml("<p>A café</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", c1.as_bytes())]);
+    let mut doc =
+        EpubDoc::from_reader_with_options(Cursor::new(epub), EpubOptions::lenient()).unwrap();
+    let hits = doc.search("café", SearchOptions::default()).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].spine_index, 2);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use epub::doc::{DocError, EpubDoc, EpubOptions};
+use epub::stats::{count_characters, count_words, ChapterStats, CJK_CHARACTERS_PER_MINUTE};
+use std::io::Cursor;
+use std::time::Duration;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c3" href="c3.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2" linear="no"/>
+    <itemref idref="c3"/>"#,
+    );
+    let c1 = common::xhtml("<h1>One</h1><p>Two three, <em>four</em>.</p>");
+    let c2 = common::xhtml("<p>Not in the total</p>");
+    let c3 = common::xhtml("<p>日本語の本 and a word</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+            ("OEBPS/c3.xhtml", c3.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn counts() {
+    assert_eq!(count_words(""), 0);
+    assert_eq!(count_words("  l'été -- 2024 rock’n’roll"), 3);
+    assert_eq!(count_words("カタカナとひらがな、漢字。"), 11);
+    assert_eq!(count_words("한국어 문장입니다"), 2);
+    // the unspaced scripts, one word per 5 characters of a run
+    assert_eq!(count_words("สวัสดีครับ ๑๒"), 3);
+    assert_eq!(count_words("Thai ไทย"), 2);
+    assert_eq!(count_words("ພາສາລາວ"), 2);
+    assert_eq!(count_words("ភាសាខ្មែរ"), 2);
+    assert_eq!(count_words("မြန်မာ"), 2);
+    assert_eq!(count_characters("a b\nc"), 3);
+}
+
+#[test]
+fn chapter_stats() {
+    let mut doc = book();
+    assert_eq!(
+        doc.chapter_stats(0).unwrap(),
+        ChapterStats {
+            spine_index: 0,
+            linear: true,
+            words: 4,
+            characters: 17,
+        }
+    );
+    let stats = doc.chapter_stats(2).unwrap();
+    assert_eq!((stats.words, stats.characters), (8, 13));
+    assert_eq!(stats.reading_time(240), Duration::from_secs(2));
+    assert_eq!(
+        stats.reading_time(CJK_CHARACTERS_PER_MINUTE),
+        Duration::from_millis(1600)
+    );
+
+    let err = doc.chapter_stats(3).unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::SpineIndexOutOfBounds { index: 3, .. }
+    ));
+}
+
+#[test]
+fn book_stats() {
+    let mut doc = book();
+    let stats = doc.book_stats().unwrap();
+    assert_eq!(stats.chapters.len(), 3);
+    assert!(!stats.chapters[1].linear);
+    assert_eq!(stats.words(), 12);
+    assert_eq!(stats.characters(), 30);
+    assert_eq!(stats.reading_time(60), Duration::from_secs(12));
+    assert_eq!(stats.reading_time(0), Duration::from_secs(720));
+}
+
+#[test]
+fn book_stats_with_broken_chapters() {
+    let opf = common::opf(
+        r#"<item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="missing"/>
+    <itemref idref="dangling"/>
+    <itemref idref="c1"/>"#,
+    );
+    let c1 = common::xhtml("<p>Two words</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", c1.as_bytes())]);
+    let mut doc =
+        EpubDoc::from_reader_with_options(Cursor::new(epub), EpubOptions::lenient()).unwrap();
+    let stats = doc.book_stats().unwrap();
+    let words: Vec<usize> = stats.chapters.iter().map(|c| c.words).collect();
+    assert_eq!(words, [0, 0, 2]);
+    assert_eq!(stats.chapters[1].spine_index, 1);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use epub::doc::{DocError, EpubDoc, EpubOptions};
+use epub::limits::LimitError;
+use epub::text::TextOptions;
+use std::io::Cursor;
+
+const CHAPTER: &str = r##"<h1>The   title</h1>
+<p>A <em>first</em>
+   paragraph&nbsp;&amp; more<a epub:type="noteref" href="#n1">1</a>.</p>
+<script>var ignored = 1;</script>
+<style>p { color: red; }</style>
+<p>One<br/>Two</p>
+<pre>  keep
+    this</pre>
+<table><tr><td>a</td><td>b</td></tr></table>
+<aside epub:type="footnote" id="n1"><p>The note.</p></aside>"##;
+
+fn book() -> EpubDoc<Cursor<Vec<u8>>> {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c2" href="c2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c3" href="c3.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="c2" linear="no"/>
+    <itemref idref="c3"/>"#,
+    );
+    let c1 = common::xhtml(CHAPTER);
+    let c2 = common::xhtml("<p>Extra</p>");
+    let c3 = common::xhtml("<div role=\"doc-endnotes\"><p>End</p></div><p>Last</p>");
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/c2.xhtml", c2.as_bytes()),
+            ("OEBPS/c3.xhtml", c3.as_bytes()),
+        ],
+    );
+    EpubDoc::from_reader(Cursor::new(epub)).unwrap()
+}
+
+#[test]
+fn chapter_text() {
+    let mut doc = book();
+    assert_eq!(
+        doc.chapter_text(0).unwrap(),
+        "The title\n\
+         A first paragraph & more1.\n\
+         One\n\
+         Two\n  keep\n    this\n\
+         a b\n\
+         The note."
+    );
+}
+
+#[test]
+fn chapter_text_skip_notes() {
+    let mut doc = book();
+    let options = TextOptions {
+        skip_notes: true,
+        ..TextOptions::default()
+    };
+    assert_eq!(
+        doc.chapter_text_with(0, options).unwrap(),
+        "The title\n\
+         A first paragraph & more.\n\
+         One\n\
+         Two\n  keep\n    this\n\
+         a b"
+    );
+    assert_eq!(doc.chapter_text_with(2, options).unwrap(), "Last");
+}
+
+#[test]
+fn book_text() {
+    let mut doc = book();
+    let text = doc.book_text().unwrap();
+    assert!(text.starts_with("The title\n"));
+    assert!(text.ends_with("The note.\n\nExtra\n\nEnd\nLast"));
+
+    let options = TextOptions {
+        skip_notes: true,
+        skip_non_linear: true,
+    };
+    let text = doc.book_text_with(options).unwrap();
+    assert!(text.ends_with("a b\n\nLast"));
+}
+
+#[test]
+fn book_text_skips_broken_chapters() {
+    let opf = common::opf(
+        r#"<item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="deep" href="deep.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="missing"/>
+    <itemref idref="dangling"/>
+    <itemref idref="c1"/>"#,
+    );
+    let c1 = common::xhtml("<p>Kept</p>");
+    let deep = common::xhtml(&format!(
+        "{}text{}",
+        "<div>".repeat(50),
+        "</div>".repeat(50)
+    ));
+    let epub = common::build_epub(
+        &opf,
+        &[
+            ("OEBPS/c1.xhtml", c1.as_bytes()),
+            ("OEBPS/deep.xhtml", deep.as_bytes()),
+        ],
+    );
+    let mut options = EpubOptions::lenient();
+    options.limits.max_xml_depth = 20;
+    let mut doc = EpubDoc::from_reader_with_options(Cursor::new(epub), options.clone()).unwrap();
+    assert_eq!(doc.book_text().unwrap(), "Kept");
+
+    // the limits aren't skipped
+    let opf = opf.replace(r#"<itemref idref="c1"/>"#, r#"<itemref idref="deep"/>"#);
+    let epub = common::build_epub(&opf, &[("OEBPS/deep.xhtml", deep.as_bytes())]);
+    let mut doc = EpubDoc::from_reader_with_options(Cursor::new(epub), options).unwrap();
+    let err = doc.book_text().unwrap_err();
+    assert!(matches!(
+        err.limit_exceeded(),
+        Some(LimitError::XmlDepth { .. })
+    ));
+}
+
+#[test]
+fn chapter_text_out_of_bounds() {
+    let mut doc = book();
+    let err = doc.chapter_text(3).unwrap_err();
+    assert!(matches!(
+        err,
+        DocError::SpineIndexOutOfBounds { index: 3, len: 3 }
+    ));
+}
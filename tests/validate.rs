@@ -0,0 +1,87 @@
+mod common;
+
+use epub::doc::EpubDoc;
+use epub::validate::{Code, Severity};
+use std::io::Cursor;
+use std::path::Path;
+
+#[test]
+fn validate_test_epub() {
+    let mut doc = EpubDoc::new("test.epub").unwrap();
+    let diagnostics = doc.validate();
+    let codes: Vec<&str> = diagnostics.iter().map(|d| d.code.as_str()).collect();
+    assert_eq!(
+        codes,
+        vec!["PKG-006", "RSC-005", "RSC-001", "RSC-001", "OPF-003", "OPF-003"]
+    );
+
+    let duplicated = &diagnostics[1];
+    assert_eq!(duplicated.line, Some(21));
+    assert_eq!(
+        duplicated.to_string(),
+        "ERROR(RSC-005): OEBPS/content.opf(21,5): Duplicate id \"000.xhtml\""
+    );
+}
+
+#[test]
+fn validate_valid_epub() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>"#,
+    );
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_epub(&opf, &[("OEBPS/c1.xhtml", chapter.as_bytes())]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    assert_eq!(doc.validate(), vec![]);
+}
+
+#[test]
+fn validate_broken_package() {
+    let opf = common::opf(
+        r#"<item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="c1" href="c2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="missing" href="missing.xhtml" media-type="application/xhtml+xml"/>"#,
+        r#"<itemref idref="c1"/>
+    <itemref idref="nope"/>"#,
+    )
+    .replace("unique-identifier=\"uid\"", "unique-identifier=\"other\"");
+    let chapter = common::xhtml("<p>Hello</p>");
+    let epub = common::build_zip(&[
+        ("META-INF/container.xml", common::CONTAINER.as_bytes()),
+        ("mimetype", b"application/epub+zip"),
+        ("OEBPS/content.opf", opf.as_bytes()),
+        ("OEBPS/c1.xhtml", chapter.as_bytes()),
+        ("OEBPS/c2.xhtml", chapter.as_bytes()),
+        ("OEBPS/extra.css", b"p {}"),
+    ]);
+    let mut doc = EpubDoc::from_reader(Cursor::new(epub)).unwrap();
+    let diagnostics = doc.validate();
+    let codes: Vec<Code> = diagnostics.iter().map(|d| d.code).collect();
+
+    assert!(codes.contains(&Code::MimetypeNotFirst));
+    assert!(codes.contains(&Code::DuplicateId));
+    assert!(codes.contains(&Code::ResourceMissing));
+    assert!(codes.contains(&Code::IdrefNotFound));
+    assert!(codes.contains(&Code::UndeclaredResource));
+    assert!(codes.contains(&Code::UniqueIdentifierNotFound));
+
+    let idref = diagnostics
+        .iter()
+        .find(|d| d.code == Code::IdrefNotFound)
+        .unwrap();
+    assert_eq!(idref.severity, Severity::Error);
+    assert_eq!(idref.code.as_str(), "OPF-049");
+    assert_eq!(idref.path.as_deref(), Some(Path::new("OEBPS/content.opf")));
+    assert_eq!(idref.line, Some(15));
+    assert_eq!(idref.column, Some(5));
+
+    let undeclared = diagnostics
+        .iter()
+        .find(|d| d.code == Code::UndeclaredResource)
+        .unwrap();
+    assert_eq!(undeclared.severity, Severity::Warning);
+    assert_eq!(
+        undeclared.path.as_deref(),
+        Some(Path::new("OEBPS/extra.css"))
+    );
+}